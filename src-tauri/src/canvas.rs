@@ -2,6 +2,8 @@
 //! 
 //! Handles canvas data transformation, stroke analysis, and image processing.
 
+use crate::color::{self, Color};
+use crate::ocr::TextRegion;
 use crate::{Point, Stroke};
 use image::{DynamicImage, Rgba, RgbaImage};
 use serde::{Deserialize, Serialize};
@@ -45,94 +47,244 @@ pub fn render_strokes_to_image(
     DynamicImage::ImageRgba8(img)
 }
 
-/// Parse hex color string to Rgba
-fn parse_color(color: &str) -> Rgba<u8> {
-    let color = color.trim_start_matches('#');
-    let r = u8::from_str_radix(&color[0..2], 16).unwrap_or(0);
-    let g = u8::from_str_radix(&color[2..4], 16).unwrap_or(0);
-    let b = u8::from_str_radix(&color[4..6], 16).unwrap_or(0);
-    let a = if color.len() >= 8 {
-        u8::from_str_radix(&color[6..8], 16).unwrap_or(255)
-    } else {
-        255
+/// Mapping between raster pixels and stroke (canvas) coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RasterTransform {
+    /// Canvas coordinate shown at raster pixel (0, 0)
+    pub origin_x: f64,
+    pub origin_y: f64,
+    /// Raster pixels per canvas unit
+    pub scale: f64,
+}
+
+impl RasterTransform {
+    /// Convert a raster pixel position to canvas coordinates
+    pub fn canvas_point(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.origin_x + x / self.scale, self.origin_y + y / self.scale)
+    }
+
+    /// Convert a canvas position to raster pixel coordinates
+    pub fn raster_point(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.origin_x) * self.scale, (y - self.origin_y) * self.scale)
+    }
+
+    /// Map a text region found in the raster back into canvas coordinates
+    pub fn text_region_to_canvas(&self, region: &mut TextRegion) {
+        let (x, y) = self.canvas_point(region.bounds.x, region.bounds.y);
+        region.bounds.x = x;
+        region.bounds.y = y;
+        region.bounds.width /= self.scale;
+        region.bounds.height /= self.scale;
+        region.font_size_estimate /= self.scale;
+    }
+}
+
+/// Largest raster area (in pixels) produced by [`render_strokes_for_analysis`]
+const MAX_ANALYSIS_PIXELS: f64 = 16_000_000.0;
+
+/// Rasterize strokes around their bounding box for analysis.
+///
+/// The raster covers the strokes plus `padding` canvas units on each side at
+/// `scale` pixels per unit (reduced if the image would get too large), so
+/// OCR results can be mapped back with the returned transform.
+pub fn render_strokes_for_analysis(
+    strokes: &[Stroke],
+    scale: f64,
+    padding: f64,
+) -> Option<(DynamicImage, RasterTransform)> {
+    let (min_x, min_y, max_x, max_y) = calculate_bounding_box(strokes)?;
+    if min_x > max_x || min_y > max_y {
+        return None;
+    }
+
+    let width = max_x - min_x + 2.0 * padding;
+    let height = max_y - min_y + 2.0 * padding;
+    let scale = scale
+        .min((MAX_ANALYSIS_PIXELS / (width * height)).sqrt())
+        .max(f64::EPSILON);
+
+    let transform = RasterTransform {
+        origin_x: min_x - padding,
+        origin_y: min_y - padding,
+        scale,
     };
-    Rgba([r, g, b, a])
+
+    let transformed = apply_transform(strokes, &transform);
+
+    let config = CanvasConfig {
+        width: (width * scale).ceil() as u32,
+        height: (height * scale).ceil() as u32,
+        ..CanvasConfig::default()
+    };
+
+    Some((render_strokes_to_image(&transformed, &config), transform))
+}
+
+/// Render strokes into a `width` x `height` raster window, mapping canvas
+/// coordinates through `transform`. Strokes outside the window are clipped.
+pub fn render_region(
+    strokes: &[Stroke],
+    transform: &RasterTransform,
+    width: u32,
+    height: u32,
+    background: Color,
+) -> RgbaImage {
+    let mut img = RgbaImage::from_pixel(width, height, Rgba(background.to_rgba()));
+    for stroke in apply_transform(strokes, transform) {
+        let color = parse_color(&stroke.color);
+        draw_stroke(&mut img, &stroke, color);
+    }
+    img
+}
+
+/// Parse a CSS color string to Rgba
+fn parse_color(color: &str) -> Rgba<u8> {
+    Rgba(color::ink(color).to_rgba())
 }
 
-/// Draw a single stroke on the image using Bresenham's line algorithm
+/// Pressure reported by pointers without pressure sensing
+const NEUTRAL_PRESSURE: f64 = 0.5;
+
+/// Smallest width factor applied to very light pressure
+const MIN_PRESSURE_FACTOR: f64 = 0.2;
+
+/// Brush radius at a point, scaled by pen pressure relative to neutral
+pub(crate) fn point_radius(stroke_width: f64, pressure: Option<f64>) -> f64 {
+    let factor = pressure
+        .map(|p| (p.clamp(0.0, 1.0) / NEUTRAL_PRESSURE).max(MIN_PRESSURE_FACTOR))
+        .unwrap_or(1.0);
+    (stroke_width * factor / 2.0).max(0.5)
+}
+
+/// Draw a single stroke with anti-aliasing, pressure-modulated width and alpha blending.
+///
+/// Coverage for the whole stroke is accumulated in a mask first (round caps and
+/// joins come from treating each segment as a tapered capsule), then composited
+/// once so overlapping segments of a translucent stroke don't darken.
 fn draw_stroke(img: &mut RgbaImage, stroke: &Stroke, color: Rgba<u8>) {
-    if stroke.points.len() < 2 {
+    if stroke.points.is_empty() || color[3] == 0 {
         return;
     }
 
-    let width = stroke.width as i32;
-    
-    for window in stroke.points.windows(2) {
-        let p1 = &window[0];
-        let p2 = &window[1];
-        draw_line_thick(
-            img,
-            p1.x as i32,
-            p1.y as i32,
-            p2.x as i32,
-            p2.y as i32,
-            width,
-            color,
-        );
+    let samples: Vec<(f64, f64, f64)> = stroke
+        .points
+        .iter()
+        .map(|p| (p.x, p.y, point_radius(stroke.width, p.pressure)))
+        .collect();
+
+    let (w, h) = img.dimensions();
+    let (mut min_x, mut min_y) = (f64::MAX, f64::MAX);
+    let (mut max_x, mut max_y) = (f64::MIN, f64::MIN);
+    for &(x, y, r) in &samples {
+        min_x = min_x.min(x - r);
+        min_y = min_y.min(y - r);
+        max_x = max_x.max(x + r);
+        max_y = max_y.max(y + r);
     }
-}
 
-/// Draw a thick line using filled circles along the line path
-fn draw_line_thick(
-    img: &mut RgbaImage,
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-    width: i32,
-    color: Rgba<u8>,
-) {
-    let dx = (x2 - x1).abs();
-    let dy = (y2 - y1).abs();
-    let sx = if x1 < x2 { 1 } else { -1 };
-    let sy = if y1 < y2 { 1 } else { -1 };
-    let mut err = dx - dy;
+    let x0 = (min_x.floor() - 1.0).max(0.0);
+    let y0 = (min_y.floor() - 1.0).max(0.0);
+    let x1 = (max_x.ceil() + 1.0).min(w as f64);
+    let y1 = (max_y.ceil() + 1.0).min(h as f64);
+    if !(x0 < x1 && y0 < y1) {
+        return;
+    }
 
-    let mut x = x1;
-    let mut y = y1;
+    let mut mask = CoverageMask {
+        origin_x: x0 as i64,
+        origin_y: y0 as i64,
+        width: (x1 - x0) as usize,
+        height: (y1 - y0) as usize,
+        coverage: vec![0.0; (x1 - x0) as usize * (y1 - y0) as usize],
+    };
 
-    loop {
-        draw_circle_filled(img, x, y, width / 2, color);
+    if samples.len() == 1 {
+        mask.add_capsule(samples[0], samples[0]);
+    }
+    for window in samples.windows(2) {
+        mask.add_capsule(window[0], window[1]);
+    }
 
-        if x == x2 && y == y2 {
-            break;
-        }
+    mask.composite(img, color);
+}
 
-        let e2 = 2 * err;
-        if e2 > -dy {
-            err -= dy;
-            x += sx;
-        }
-        if e2 < dx {
-            err += dx;
-            y += sy;
+/// Per-pixel stroke coverage over a clipped region of the image
+struct CoverageMask {
+    origin_x: i64,
+    origin_y: i64,
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl CoverageMask {
+    /// Accumulate coverage of a segment whose radius tapers from `a` to `b`
+    fn add_capsule(&mut self, a: (f64, f64, f64), b: (f64, f64, f64)) {
+        let (ax, ay, ar) = a;
+        let (bx, by, br) = b;
+
+        let left = ((ax - ar).min(bx - br).floor() as i64 - 1 - self.origin_x).max(0);
+        let top = ((ay - ar).min(by - br).floor() as i64 - 1 - self.origin_y).max(0);
+        let right = ((ax + ar).max(bx + br).ceil() as i64 + 1 - self.origin_x).min(self.width as i64);
+        let bottom = ((ay + ar).max(by + br).ceil() as i64 + 1 - self.origin_y).min(self.height as i64);
+
+        let dx = bx - ax;
+        let dy = by - ay;
+        let len_sq = dx * dx + dy * dy;
+
+        for my in top..bottom {
+            let cy = (my + self.origin_y) as f64 + 0.5;
+            let row = my as usize * self.width;
+            for mx in left..right {
+                let cx = (mx + self.origin_x) as f64 + 0.5;
+
+                let t = if len_sq > 0.0 {
+                    (((cx - ax) * dx + (cy - ay) * dy) / len_sq).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let qx = ax + t * dx - cx;
+                let qy = ay + t * dy - cy;
+                let radius = ar + t * (br - ar);
+
+                // Signed distance to the edge, turned into a one-pixel coverage ramp
+                let coverage = (radius - (qx * qx + qy * qy).sqrt() + 0.5).clamp(0.0, 1.0) as f32;
+                let cell = &mut self.coverage[row + mx as usize];
+                if coverage > *cell {
+                    *cell = coverage;
+                }
+            }
         }
     }
-}
 
-/// Draw a filled circle at the given position
-fn draw_circle_filled(img: &mut RgbaImage, cx: i32, cy: i32, radius: i32, color: Rgba<u8>) {
-    let (w, h) = img.dimensions();
-    let r_sq = radius * radius;
-
-    for dy in -radius..=radius {
-        for dx in -radius..=radius {
-            if dx * dx + dy * dy <= r_sq {
-                let px = cx + dx;
-                let py = cy + dy;
-                if px >= 0 && px < w as i32 && py >= 0 && py < h as i32 {
-                    img.put_pixel(px as u32, py as u32, color);
+    /// Blend `color` onto the image weighted by coverage (source-over)
+    fn composite(&self, img: &mut RgbaImage, color: Rgba<u8>) {
+        let src_alpha = color[3] as f32 / 255.0;
+
+        for my in 0..self.height {
+            for mx in 0..self.width {
+                let coverage = self.coverage[my * self.width + mx];
+                if coverage <= 0.0 {
+                    continue;
+                }
+
+                let px = (mx as i64 + self.origin_x) as u32;
+                let py = (my as i64 + self.origin_y) as u32;
+                let dst = img.get_pixel_mut(px, py);
+
+                let sa = src_alpha * coverage;
+                let da = dst[3] as f32 / 255.0;
+                let out_a = sa + da * (1.0 - sa);
+                if out_a <= 0.0 {
+                    continue;
                 }
+
+                for c in 0..3 {
+                    let blended =
+                        (color[c] as f32 * sa + dst[c] as f32 * da * (1.0 - sa)) / out_a;
+                    dst[c] = blended.round().clamp(0.0, 255.0) as u8;
+                }
+                dst[3] = (out_a * 255.0).round() as u8;
             }
         }
     }
@@ -161,48 +313,70 @@ pub fn calculate_bounding_box(strokes: &[Stroke]) -> Option<(f64, f64, f64, f64)
     Some((min_x, min_y, max_x, max_y))
 }
 
-/// Normalize strokes to fit within a given viewport
-pub fn normalize_strokes(
+/// Transform that fits the strokes' bounding box into a viewport, centred
+///
+/// Returns `None` when there are no strokes or their extent is degenerate.
+pub fn fit_transform(
     strokes: &[Stroke],
     target_width: f64,
     target_height: f64,
     padding: f64,
-) -> Vec<Stroke> {
-    let bbox = match calculate_bounding_box(strokes) {
-        Some(b) => b,
-        None => return strokes.to_vec(),
-    };
-
-    let (min_x, min_y, max_x, max_y) = bbox;
+) -> Option<RasterTransform> {
+    let (min_x, min_y, max_x, max_y) = calculate_bounding_box(strokes)?;
     let width = max_x - min_x;
     let height = max_y - min_y;
 
-    if width == 0.0 || height == 0.0 {
-        return strokes.to_vec();
+    if width <= 0.0 || height <= 0.0 {
+        return None;
     }
 
     let scale_x = (target_width - 2.0 * padding) / width;
     let scale_y = (target_height - 2.0 * padding) / height;
     let scale = scale_x.min(scale_y);
+    if scale <= 0.0 {
+        return None;
+    }
 
     let offset_x = padding + (target_width - 2.0 * padding - width * scale) / 2.0;
     let offset_y = padding + (target_height - 2.0 * padding - height * scale) / 2.0;
 
+    Some(RasterTransform {
+        origin_x: min_x - offset_x / scale,
+        origin_y: min_y - offset_y / scale,
+        scale,
+    })
+}
+
+/// Normalize strokes to fit within a given viewport
+pub fn normalize_strokes(
+    strokes: &[Stroke],
+    target_width: f64,
+    target_height: f64,
+    padding: f64,
+) -> Vec<Stroke> {
+    match fit_transform(strokes, target_width, target_height, padding) {
+        Some(transform) => apply_transform(strokes, &transform),
+        None => strokes.to_vec(),
+    }
+}
+
+/// Map strokes into the transform's target space, scaling their width
+fn apply_transform(strokes: &[Stroke], transform: &RasterTransform) -> Vec<Stroke> {
     strokes
         .iter()
         .map(|stroke| Stroke {
             id: stroke.id.clone(),
             color: stroke.color.clone(),
-            width: stroke.width * scale,
+            width: stroke.width * transform.scale,
             tool: stroke.tool.clone(),
+            layer_id: stroke.layer_id.clone(),
+            curves: stroke.curves.iter().map(|c| c.map(|x, y| transform.raster_point(x, y))).collect(),
             points: stroke
                 .points
                 .iter()
-                .map(|p| Point {
-                    x: (p.x - min_x) * scale + offset_x,
-                    y: (p.y - min_y) * scale + offset_y,
-                    pressure: p.pressure,
-                    timestamp: p.timestamp,
+                .map(|p| {
+                    let (x, y) = transform.raster_point(p.x, p.y);
+                    Point { x, y, pressure: p.pressure, timestamp: p.timestamp }
                 })
                 .collect(),
         })
@@ -277,9 +451,320 @@ fn perpendicular_distance(point: &Point, line_start: &Point, line_end: &Point) -
     ((point.x - nearest_x).powi(2) + (point.y - nearest_y).powi(2)).sqrt()
 }
 
+/// A cubic Bézier segment from `p0` to `p3`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CubicBezier {
+    pub p0: (f64, f64),
+    pub p1: (f64, f64),
+    pub p2: (f64, f64),
+    pub p3: (f64, f64),
+}
+
+impl CubicBezier {
+    pub fn point_at(&self, t: f64) -> (f64, f64) {
+        let mt = 1.0 - t;
+        let (a, b, c, d) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+        (
+            a * self.p0.0 + b * self.p1.0 + c * self.p2.0 + d * self.p3.0,
+            a * self.p0.1 + b * self.p1.1 + c * self.p2.1 + d * self.p3.1,
+        )
+    }
+
+    /// Map every control point through `f` (exact for affine maps)
+    pub fn map(&self, f: impl Fn(f64, f64) -> (f64, f64)) -> Self {
+        Self {
+            p0: f(self.p0.0, self.p0.1),
+            p1: f(self.p1.0, self.p1.1),
+            p2: f(self.p2.0, self.p2.1),
+            p3: f(self.p3.0, self.p3.1),
+        }
+    }
+}
+
+/// Fitting tolerance for stored stroke curves, in canvas units
+pub const CURVE_TOLERANCE: f64 = 1.0;
+
+/// Turning angle (degrees) above which a stroke point is kept as a corner
+const CORNER_ANGLE: f64 = 60.0;
+
+/// Arc length over which directions are measured when looking for corners
+const CORNER_SPAN: f64 = 4.0;
+
+/// Newton reparameterization passes tried before splitting a segment
+const MAX_REPARAMETERIZATIONS: usize = 4;
+
+/// Curves stored with a stroke
+pub fn fit_stroke_curves(stroke: &Stroke) -> Vec<CubicBezier> {
+    fit_bezier(&stroke.points, CURVE_TOLERANCE)
+}
+
+/// Fit a chain of cubic Bézier segments to the points, within `tolerance`.
+///
+/// Uses Schneider's algorithm ("An Algorithm for Automatically Fitting
+/// Digitized Curves", Graphics Gems 1990) on each run between detected
+/// corners, so sharp turns stay sharp instead of being rounded off.
+pub fn fit_bezier(points: &[Point], tolerance: f64) -> Vec<CubicBezier> {
+    let mut pts: Vec<(f64, f64)> = Vec::with_capacity(points.len());
+    for p in points {
+        if pts.last().is_none_or(|&(x, y)| (p.x - x).hypot(p.y - y) > 1e-6) {
+            pts.push((p.x, p.y));
+        }
+    }
+    if pts.len() < 2 {
+        return Vec::new();
+    }
+
+    let tolerance = tolerance.max(0.01);
+    let corners = find_corners(&pts);
+    let mut curves = Vec::new();
+    for span in corners.windows(2) {
+        let run = &pts[span[0]..=span[1]];
+        let left = left_tangent(run);
+        let right = left_tangent(&run.iter().rev().copied().collect::<Vec<_>>());
+        fit_cubic(run, left, right, tolerance * tolerance, &mut curves);
+    }
+    curves
+}
+
+/// Indices of the first point, every corner and the last point
+fn find_corners(pts: &[(f64, f64)]) -> Vec<usize> {
+    let n = pts.len();
+    // Walk back/forward along the stroke until CORNER_SPAN of arc length is covered
+    let reach = |i: usize, forward: bool| -> Option<usize> {
+        let mut j = i;
+        let mut length = 0.0;
+        while length < CORNER_SPAN {
+            let next = if forward { j + 1 } else { j.checked_sub(1)? };
+            if next >= n {
+                return None;
+            }
+            length += distance(pts[j], pts[next]);
+            j = next;
+        }
+        Some(j)
+    };
+
+    let angles: Vec<f64> = (0..n)
+        .map(|i| {
+            let (Some(a), Some(b)) = (reach(i, false), reach(i, true)) else {
+                return 0.0;
+            };
+            let d1 = (pts[i].0 - pts[a].0, pts[i].1 - pts[a].1);
+            let d2 = (pts[b].0 - pts[i].0, pts[b].1 - pts[i].1);
+            let cos = (d1.0 * d2.0 + d1.1 * d2.1) / (d1.0.hypot(d1.1) * d2.0.hypot(d2.1)).max(f64::EPSILON);
+            cos.clamp(-1.0, 1.0).acos().to_degrees()
+        })
+        .collect();
+
+    let mut corners = vec![0];
+    for i in 1..n - 1 {
+        if angles[i] < CORNER_ANGLE {
+            continue;
+        }
+        // Keep only the sharpest point within reach of a corner
+        let (a, b) = (reach(i, false).unwrap_or(0), reach(i, true).unwrap_or(n - 1));
+        let sharpest = (a..=b).all(|j| angles[j] < angles[i] || (angles[j] == angles[i] && j >= i));
+        if sharpest && i > *corners.last().unwrap_or(&0) {
+            corners.push(i);
+        }
+    }
+    corners.push(n - 1);
+    corners
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+fn normalize(v: (f64, f64)) -> (f64, f64) {
+    let len = v.0.hypot(v.1);
+    if len < f64::EPSILON {
+        (0.0, 0.0)
+    } else {
+        (v.0 / len, v.1 / len)
+    }
+}
+
+/// Unit tangent leaving the first point, smoothed over a couple of samples
+fn left_tangent(pts: &[(f64, f64)]) -> (f64, f64) {
+    let k = 2.min(pts.len() - 1);
+    let t = normalize((pts[k].0 - pts[0].0, pts[k].1 - pts[0].1));
+    if t == (0.0, 0.0) {
+        normalize((pts[1].0 - pts[0].0, pts[1].1 - pts[0].1))
+    } else {
+        t
+    }
+}
+
+/// Fit one run recursively; `left` points into the curve from the start, `right` from the end
+fn fit_cubic(
+    pts: &[(f64, f64)],
+    left: (f64, f64),
+    right: (f64, f64),
+    tolerance_sq: f64,
+    out: &mut Vec<CubicBezier>,
+) {
+    let (first, last) = (pts[0], pts[pts.len() - 1]);
+    if pts.len() == 2 {
+        let d = distance(first, last) / 3.0;
+        out.push(CubicBezier {
+            p0: first,
+            p1: (first.0 + left.0 * d, first.1 + left.1 * d),
+            p2: (last.0 + right.0 * d, last.1 + right.1 * d),
+            p3: last,
+        });
+        return;
+    }
+
+    let mut u = chord_length_parameterize(pts);
+    let mut curve = generate_bezier(pts, &u, left, right);
+    let (mut error, mut split) = fit_error(pts, &curve, &u);
+    if error < tolerance_sq {
+        out.push(curve);
+        return;
+    }
+
+    // Close misses are usually a parameterization problem rather than a shape one
+    if error < tolerance_sq * 4.0 {
+        for _ in 0..MAX_REPARAMETERIZATIONS {
+            u = reparameterize(pts, &u, &curve);
+            curve = generate_bezier(pts, &u, left, right);
+            (error, split) = fit_error(pts, &curve, &u);
+            if error < tolerance_sq {
+                out.push(curve);
+                return;
+            }
+        }
+    }
+
+    let split = split.clamp(1, pts.len() - 2);
+    let mut center = normalize((pts[split - 1].0 - pts[split + 1].0, pts[split - 1].1 - pts[split + 1].1));
+    if center == (0.0, 0.0) {
+        center = normalize((pts[split - 1].0 - pts[split].0, pts[split - 1].1 - pts[split].1));
+    }
+    fit_cubic(&pts[..=split], left, center, tolerance_sq, out);
+    fit_cubic(&pts[split..], (-center.0, -center.1), right, tolerance_sq, out);
+}
+
+fn chord_length_parameterize(pts: &[(f64, f64)]) -> Vec<f64> {
+    let mut u = Vec::with_capacity(pts.len());
+    u.push(0.0);
+    for i in 1..pts.len() {
+        u.push(u[i - 1] + distance(pts[i - 1], pts[i]));
+    }
+    let total = u[pts.len() - 1].max(f64::EPSILON);
+    u.iter_mut().for_each(|v| *v /= total);
+    u
+}
+
+/// Least-squares control point distances along the end tangents
+fn generate_bezier(pts: &[(f64, f64)], u: &[f64], left: (f64, f64), right: (f64, f64)) -> CubicBezier {
+    let (first, last) = (pts[0], pts[pts.len() - 1]);
+    let dot = |a: (f64, f64), b: (f64, f64)| a.0 * b.0 + a.1 * b.1;
+
+    let mut c = [[0.0; 2]; 2];
+    let mut x = [0.0; 2];
+    for (&p, &t) in pts.iter().zip(u) {
+        let mt = 1.0 - t;
+        let (b0, b1, b2, b3) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+        let a0 = (left.0 * b1, left.1 * b1);
+        let a1 = (right.0 * b2, right.1 * b2);
+
+        c[0][0] += dot(a0, a0);
+        c[0][1] += dot(a0, a1);
+        c[1][1] += dot(a1, a1);
+
+        let tmp = (
+            p.0 - (first.0 * (b0 + b1) + last.0 * (b2 + b3)),
+            p.1 - (first.1 * (b0 + b1) + last.1 * (b2 + b3)),
+        );
+        x[0] += dot(a0, tmp);
+        x[1] += dot(a1, tmp);
+    }
+    c[1][0] = c[0][1];
+
+    let det = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+    let (mut alpha_l, mut alpha_r) = if det.abs() > 1e-12 {
+        (
+            (x[0] * c[1][1] - x[1] * c[0][1]) / det,
+            (c[0][0] * x[1] - c[1][0] * x[0]) / det,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+
+    // Degenerate or backwards handles: fall back to the Wu/Barsky heuristic
+    let seg_length = distance(first, last);
+    let epsilon = 1e-6 * seg_length;
+    if alpha_l < epsilon || alpha_r < epsilon {
+        alpha_l = seg_length / 3.0;
+        alpha_r = seg_length / 3.0;
+    }
+
+    CubicBezier {
+        p0: first,
+        p1: (first.0 + left.0 * alpha_l, first.1 + left.1 * alpha_l),
+        p2: (last.0 + right.0 * alpha_r, last.1 + right.1 * alpha_r),
+        p3: last,
+    }
+}
+
+/// Largest squared distance between the points and the curve, and where it occurs
+fn fit_error(pts: &[(f64, f64)], curve: &CubicBezier, u: &[f64]) -> (f64, usize) {
+    let mut worst = (0.0, pts.len() / 2);
+    for i in 1..pts.len() - 1 {
+        let p = curve.point_at(u[i]);
+        let d = (p.0 - pts[i].0).powi(2) + (p.1 - pts[i].1).powi(2);
+        if d >= worst.0 {
+            worst = (d, i);
+        }
+    }
+    worst
+}
+
+/// One Newton-Raphson step per point towards the closest curve parameter
+fn reparameterize(pts: &[(f64, f64)], u: &[f64], curve: &CubicBezier) -> Vec<f64> {
+    let CubicBezier { p0, p1, p2, p3 } = *curve;
+    // First and second derivative control points
+    let d1 = [
+        (3.0 * (p1.0 - p0.0), 3.0 * (p1.1 - p0.1)),
+        (3.0 * (p2.0 - p1.0), 3.0 * (p2.1 - p1.1)),
+        (3.0 * (p3.0 - p2.0), 3.0 * (p3.1 - p2.1)),
+    ];
+    let d2 = [
+        (2.0 * (d1[1].0 - d1[0].0), 2.0 * (d1[1].1 - d1[0].1)),
+        (2.0 * (d1[2].0 - d1[1].0), 2.0 * (d1[2].1 - d1[1].1)),
+    ];
+    let quadratic = |c: &[(f64, f64); 3], t: f64| {
+        let mt = 1.0 - t;
+        (
+            mt * mt * c[0].0 + 2.0 * mt * t * c[1].0 + t * t * c[2].0,
+            mt * mt * c[0].1 + 2.0 * mt * t * c[1].1 + t * t * c[2].1,
+        )
+    };
+    let linear = |c: &[(f64, f64); 2], t: f64| ((1.0 - t) * c[0].0 + t * c[1].0, (1.0 - t) * c[0].1 + t * c[1].1);
+
+    pts.iter()
+        .zip(u)
+        .map(|(&p, &t)| {
+            let q = curve.point_at(t);
+            let q1 = quadratic(&d1, t);
+            let q2 = linear(&d2, t);
+            let numerator = (q.0 - p.0) * q1.0 + (q.1 - p.1) * q1.1;
+            let denominator = q1.0 * q1.0 + q1.1 * q1.1 + (q.0 - p.0) * q2.0 + (q.1 - p.1) * q2.1;
+            if denominator.abs() < f64::EPSILON {
+                t
+            } else {
+                (t - numerator / denominator).clamp(0.0, 1.0)
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shapes::test_stroke;
 
     #[test]
     fn test_parse_color() {
@@ -288,20 +773,19 @@ mod tests {
         
         let color2 = parse_color("#00ff00ff");
         assert_eq!(color2, Rgba([0, 255, 0, 255]));
+
+        // Short and named colours used to panic on slicing
+        assert_eq!(parse_color("#fff"), Rgba([255, 255, 255, 255]));
+        assert_eq!(parse_color("red"), Rgba([255, 0, 0, 255]));
+        assert_eq!(parse_color(""), Rgba([0, 0, 0, 255]));
     }
 
     #[test]
     fn test_bounding_box() {
-        let strokes = vec![Stroke {
-            id: "1".to_string(),
-            points: vec![
-                Point { x: 10.0, y: 20.0, pressure: None, timestamp: 0 },
-                Point { x: 100.0, y: 200.0, pressure: None, timestamp: 1 },
-            ],
-            color: "#000000".to_string(),
-            width: 2.0,
-            tool: "pen".to_string(),
-        }];
+        let strokes = vec![test_stroke("1", vec![
+            Point { x: 10.0, y: 20.0, pressure: None, timestamp: 0 },
+            Point { x: 100.0, y: 200.0, pressure: None, timestamp: 1 },
+        ])];
         
         let bbox = calculate_bounding_box(&strokes);
         assert_eq!(bbox, Some((10.0, 20.0, 100.0, 200.0)));
@@ -320,4 +804,117 @@ mod tests {
         let simplified = simplify_stroke(&points, 0.5);
         assert!(simplified.len() <= points.len());
     }
+
+    #[test]
+    fn test_render_strokes_for_analysis_round_trip() {
+        let strokes = vec![test_stroke("1", vec![
+            Point { x: 100.0, y: 50.0, pressure: None, timestamp: 0 },
+            Point { x: 300.0, y: 150.0, pressure: None, timestamp: 1 },
+        ])];
+
+        let (img, transform) = render_strokes_for_analysis(&strokes, 2.0, 10.0).unwrap();
+        assert_eq!((img.width(), img.height()), (440, 240));
+
+        let (rx, ry) = transform.raster_point(100.0, 50.0);
+        assert_eq!((rx, ry), (20.0, 20.0));
+        assert_eq!(transform.canvas_point(rx, ry), (100.0, 50.0));
+    }
+
+    #[test]
+    fn test_analysis_raster_is_capped_by_area() {
+        let mut wide = line_stroke("#000000", 2.0, None);
+        wide.points[1].x = 10_010.0;
+
+        // A long, thin board stays at full resolution
+        let (img, _) = render_strokes_for_analysis(&[wide.clone()], 1.0, 10.0).unwrap();
+        assert_eq!(img.width(), 10_020);
+
+        wide.points[1].y = 10_020.0;
+        let (img, transform) = render_strokes_for_analysis(&[wide], 2.0, 10.0).unwrap();
+        assert!(transform.scale < 2.0);
+        assert!(img.width() as f64 * img.height() as f64 <= MAX_ANALYSIS_PIXELS * 1.01);
+    }
+
+    fn line_stroke(color: &str, width: f64, pressure: Option<f64>) -> Stroke {
+        Stroke {
+            color: color.to_string(),
+            width,
+            ..test_stroke("line", vec![
+                Point { x: 10.0, y: 20.0, pressure, timestamp: 0 },
+                Point { x: 90.0, y: 20.0, pressure, timestamp: 1 },
+            ])
+        }
+    }
+
+    fn dark_pixels(img: &DynamicImage) -> usize {
+        img.to_rgba8().pixels().filter(|p| p[0] < 128).count()
+    }
+
+    #[test]
+    fn test_render_antialiased_edges_and_caps() {
+        let config = CanvasConfig { width: 100, height: 40, ..CanvasConfig::default() };
+        let img = render_strokes_to_image(&[line_stroke("#000000", 3.0, None)], &config).to_rgba8();
+
+        // Solid on the centre line, partially covered at the edge, untouched outside
+        assert_eq!(img.get_pixel(50, 20)[0], 0);
+        let edge = img.get_pixel(50, 21)[0];
+        assert!(edge > 0 && edge < 255, "edge pixel should be blended, got {}", edge);
+        assert_eq!(img.get_pixel(50, 25)[0], 255);
+
+        // Round cap extends past the end point
+        assert!(img.get_pixel(91, 20)[0] < 255);
+    }
+
+    #[test]
+    fn test_render_pressure_and_alpha() {
+        let config = CanvasConfig { width: 100, height: 40, ..CanvasConfig::default() };
+        let light = render_strokes_to_image(&[line_stroke("#000000", 6.0, Some(0.2))], &config);
+        let heavy = render_strokes_to_image(&[line_stroke("#000000", 6.0, Some(1.0))], &config);
+        assert!(dark_pixels(&heavy) > dark_pixels(&light));
+
+        let translucent = render_strokes_to_image(&[line_stroke("#ff000080", 6.0, None)], &config);
+        let pixel = translucent.to_rgba8().get_pixel(50, 20).0;
+        assert_eq!(pixel[0], 255);
+        assert!(pixel[1] > 100 && pixel[1] < 155, "expected half blend, got {:?}", pixel);
+    }
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point { x, y, pressure: None, timestamp: 0 }).collect()
+    }
+
+    #[test]
+    fn test_fit_bezier_follows_arc_within_tolerance() {
+        let arc: Vec<(f64, f64)> = (0..=60)
+            .map(|i| {
+                let a = std::f64::consts::PI * i as f64 / 60.0;
+                (100.0 + 80.0 * a.cos(), 100.0 - 80.0 * a.sin())
+            })
+            .collect();
+        let curves = fit_bezier(&points(&arc), 0.5);
+
+        assert!(!curves.is_empty() && curves.len() <= 4, "{} segments", curves.len());
+        assert_eq!(curves[0].p0, arc[0]);
+        assert_eq!(curves.last().unwrap().p3, arc[60]);
+        for curve in &curves {
+            for k in 0..=10 {
+                let (x, y) = curve.point_at(k as f64 / 10.0);
+                let r = (x - 100.0).hypot(y - 100.0);
+                assert!((r - 80.0).abs() < 1.5, "off the arc by {}", r - 80.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fit_bezier_keeps_corners() {
+        // An "L": down, then right
+        let mut coords: Vec<(f64, f64)> = (0..=20).map(|i| (0.0, i as f64 * 5.0)).collect();
+        coords.extend((1..=20).map(|i| (i as f64 * 5.0, 100.0)));
+        let curves = fit_bezier(&points(&coords), 1.0);
+
+        assert!(curves.iter().any(|c| c.p3 == (0.0, 100.0)), "corner was smoothed away");
+        for curve in &curves {
+            let (x, y) = curve.point_at(0.5);
+            assert!(x.abs() < 1.0 || (y - 100.0).abs() < 1.0);
+        }
+    }
 }
@@ -0,0 +1,554 @@
+//! Binary stroke codec module
+//!
+//! A compact stroke encoding for backups and incremental sync. Coordinates
+//! are quantised to hundredths of a canvas pixel and stored as zigzag varint
+//! deltas from the previous point; timestamps are varint deltas; pressure is
+//! only written for strokes that have it. Fitted curves are not stored, they
+//! are refitted after decoding.
+
+use crate::{Point, Stroke};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Leading bytes of a backup, followed by the format version
+const BACKUP_MAGIC: &[u8; 3] = b"WBB";
+/// Leading bytes of an encoded stroke list, followed by the format version
+const STROKES_MAGIC: &[u8; 3] = b"WBS";
+/// Leading bytes of an encoded sync message, followed by the format version
+const SYNC_MAGIC: &[u8; 3] = b"WBD";
+const FORMAT_VERSION: u8 = 1;
+
+/// Quantisation steps per canvas pixel
+const COORD_SCALE: f64 = 100.0;
+/// Quantisation steps per unit of stroke width
+const WIDTH_SCALE: f64 = 100.0;
+/// Quantisation steps for pressure in 0..=1
+const PRESSURE_SCALE: f64 = 1000.0;
+
+const HAS_PRESSURE: u8 = 1;
+const HAS_TIMESTAMPS: u8 = 2;
+
+/// Removed stroke ids remembered for sync before the oldest are dropped
+const MAX_REMOVED: usize = 10_000;
+
+/// Strokes changed since a client's last known version
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeSync {
+    /// Version the client is up to date with after applying this
+    pub version: u64,
+    /// The client's version was too old: `changed` holds every stroke and
+    /// everything else should be dropped
+    pub full: bool,
+    /// Ids of strokes no longer on the board
+    pub removed: Vec<String>,
+    /// New or changed strokes with their position in the board's stroke list, ascending
+    pub changed: Vec<(usize, Stroke)>,
+}
+
+/// Whether backup contents are in the binary format rather than legacy JSON
+pub fn is_binary_backup(bytes: &[u8]) -> bool {
+    bytes.starts_with(BACKUP_MAGIC)
+}
+
+/// Encode a backup: the strokes in binary, followed by the rest of the board as JSON
+pub fn encode_backup(strokes: &[Stroke], metadata: &[u8]) -> Vec<u8> {
+    let encoded = encode_strokes(strokes);
+    let mut out = Vec::with_capacity(encoded.len() + metadata.len() + 16);
+    out.extend_from_slice(BACKUP_MAGIC);
+    out.push(FORMAT_VERSION);
+    write_varint(&mut out, encoded.len() as u64);
+    out.extend_from_slice(&encoded);
+    out.extend_from_slice(metadata);
+    out
+}
+
+/// Decode a backup written by [`encode_backup`] into its strokes and JSON metadata
+pub fn decode_backup(bytes: &[u8]) -> Result<(Vec<Stroke>, &[u8]), String> {
+    let mut reader = Reader::new(bytes);
+    reader.header(BACKUP_MAGIC)?;
+    let len = reader.len()?;
+    let strokes = decode_strokes(reader.take(len)?)?;
+    Ok((strokes, &bytes[reader.pos..]))
+}
+
+/// Encode strokes in the compact binary format
+pub fn encode_strokes(strokes: &[Stroke]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(STROKES_MAGIC);
+    out.push(FORMAT_VERSION);
+    write_varint(&mut out, strokes.len() as u64);
+    for stroke in strokes {
+        write_stroke(&mut out, stroke);
+    }
+    out
+}
+
+/// Decode strokes written by [`encode_strokes`]
+pub fn decode_strokes(bytes: &[u8]) -> Result<Vec<Stroke>, String> {
+    let mut reader = Reader::new(bytes);
+    reader.header(STROKES_MAGIC)?;
+    let count = reader.len()?;
+    (0..count).map(|_| reader.stroke()).collect()
+}
+
+/// Encode a sync message for the frontend
+pub fn encode_sync(sync: &StrokeSync) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(SYNC_MAGIC);
+    out.push(FORMAT_VERSION);
+    write_varint(&mut out, sync.version);
+    out.push(sync.full as u8);
+    write_varint(&mut out, sync.removed.len() as u64);
+    for id in &sync.removed {
+        write_str(&mut out, id);
+    }
+    write_varint(&mut out, sync.changed.len() as u64);
+    for (index, stroke) in &sync.changed {
+        write_varint(&mut out, *index as u64);
+        write_stroke(&mut out, stroke);
+    }
+    out
+}
+
+/// Remembers when each stroke last changed so clients can fetch only what's new.
+///
+/// Changes are found by fingerprinting the board's strokes whenever a client
+/// asks, so board edits don't have to report them. Stamps are only consistent
+/// if the version is read under the same lock that edits bump it under.
+#[derive(Debug, Default)]
+pub struct SyncTracker {
+    /// Fingerprint and change version of each stroke on the board
+    strokes: HashMap<String, (u64, u64)>,
+    /// Ids of removed strokes with the version their removal was noticed at, oldest first
+    removed: Vec<(u64, String)>,
+    /// Clients older than this version can't be diffed and get every stroke;
+    /// unset until the first sync
+    horizon: Option<u64>,
+}
+
+impl SyncTracker {
+    /// Strokes changed between `since` and `version`, the board's current version.
+    ///
+    /// Clients drop the removed and changed ids, then insert the changed
+    /// strokes at their positions in ascending order. Without `since`, or when
+    /// it is older than what the tracker remembers, a full sync is returned.
+    pub fn since(&mut self, strokes: &[Stroke], version: u64, since: Option<u64>) -> StrokeSync {
+        self.refresh(strokes, version);
+        let horizon = self.horizon.unwrap_or(version);
+
+        match since {
+            Some(since) if since >= horizon && since <= version => StrokeSync {
+                version,
+                full: false,
+                removed: self
+                    .removed
+                    .iter()
+                    .filter(|(stamp, _)| *stamp > since)
+                    .map(|(_, id)| id.clone())
+                    .collect(),
+                changed: strokes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| self.strokes.get(&s.id).is_some_and(|&(_, stamp)| stamp > since))
+                    .map(|(i, s)| (i, s.clone()))
+                    .collect(),
+            },
+            _ => StrokeSync {
+                version,
+                full: true,
+                removed: Vec::new(),
+                changed: strokes.iter().cloned().enumerate().collect(),
+            },
+        }
+    }
+
+    /// Stamp strokes that changed or disappeared since the last refresh with `version`
+    fn refresh(&mut self, strokes: &[Stroke], version: u64) {
+        self.horizon.get_or_insert(version);
+
+        let mut present = HashSet::with_capacity(strokes.len());
+        for stroke in strokes {
+            present.insert(stroke.id.as_str());
+            let fingerprint = fingerprint(stroke);
+            match self.strokes.get_mut(&stroke.id) {
+                Some(entry) if entry.0 == fingerprint => {}
+                Some(entry) => *entry = (fingerprint, version),
+                None => {
+                    self.strokes.insert(stroke.id.clone(), (fingerprint, version));
+                }
+            }
+        }
+
+        let gone: Vec<String> = self
+            .strokes
+            .keys()
+            .filter(|id| !present.contains(id.as_str()))
+            .cloned()
+            .collect();
+        for id in gone {
+            self.strokes.remove(&id);
+            self.removed.push((version, id));
+        }
+
+        if self.removed.len() > MAX_REMOVED {
+            let dropped: Vec<_> = self.removed.drain(..self.removed.len() - MAX_REMOVED / 2).collect();
+            if let Some((stamp, _)) = dropped.last() {
+                self.horizon = Some(self.horizon.map_or(*stamp, |h| h.max(*stamp)));
+            }
+        }
+    }
+}
+
+/// Hash of everything the codec stores for a stroke
+fn fingerprint(stroke: &Stroke) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (&stroke.id, &stroke.color, &stroke.tool, &stroke.layer_id).hash(&mut hasher);
+    stroke.width.to_bits().hash(&mut hasher);
+    for point in &stroke.points {
+        (point.x.to_bits(), point.y.to_bits(), point.pressure.map(f64::to_bits), point.timestamp).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn write_stroke(out: &mut Vec<u8>, stroke: &Stroke) {
+    write_str(out, &stroke.id);
+    write_str(out, &stroke.color);
+    write_str(out, &stroke.tool);
+    write_str(out, &stroke.layer_id);
+    write_varint(out, quantize(stroke.width.max(0.0), WIDTH_SCALE) as u64);
+
+    let mut flags = 0;
+    if stroke.points.iter().any(|p| p.pressure.is_some()) {
+        flags |= HAS_PRESSURE;
+    }
+    if stroke.points.iter().any(|p| p.timestamp != 0) {
+        flags |= HAS_TIMESTAMPS;
+    }
+    out.push(flags);
+    write_varint(out, stroke.points.len() as u64);
+
+    let (mut x, mut y, mut t) = (0i64, 0i64, 0u64);
+    for point in &stroke.points {
+        let (qx, qy) = (quantize(point.x, COORD_SCALE), quantize(point.y, COORD_SCALE));
+        write_varint(out, zigzag(qx.wrapping_sub(x)));
+        write_varint(out, zigzag(qy.wrapping_sub(y)));
+        (x, y) = (qx, qy);
+
+        if flags & HAS_TIMESTAMPS != 0 {
+            write_varint(out, zigzag((point.timestamp as i64).wrapping_sub(t as i64)));
+            t = point.timestamp;
+        }
+        if flags & HAS_PRESSURE != 0 {
+            // 0 marks a point without a pressure reading
+            let pressure = point.pressure.map_or(0, |p| quantize(p.clamp(0.0, 1.0), PRESSURE_SCALE) as u64 + 1);
+            write_varint(out, pressure);
+        }
+    }
+}
+
+fn quantize(value: f64, scale: f64) -> i64 {
+    (value * scale).round() as i64
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, text: &str) {
+    write_varint(out, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn header(&mut self, magic: &[u8; 3]) -> Result<(), String> {
+        if self.take(3)? != magic {
+            return Err("Not a binary stroke file".to_string());
+        }
+        match self.byte()? {
+            FORMAT_VERSION => Ok(()),
+            version => Err(format!("Unsupported stroke format version {}", version)),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| "Stroke data is truncated".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Malformed varint in stroke data".to_string())
+    }
+
+    /// A count or length, bounded by the bytes left so corrupt data can't
+    /// trigger huge allocations
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.varint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err("Stroke data is truncated".to_string());
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| format!("Invalid text in stroke data: {}", e))
+    }
+
+    fn stroke(&mut self) -> Result<Stroke, String> {
+        let id = self.string()?;
+        let color = self.string()?;
+        let tool = self.string()?;
+        let layer_id = self.string()?;
+        let width = self.varint()? as f64 / WIDTH_SCALE;
+        let flags = self.byte()?;
+        let count = self.len()?;
+
+        let mut points = Vec::with_capacity(count);
+        let (mut x, mut y, mut t) = (0i64, 0i64, 0u64);
+        for _ in 0..count {
+            x = x.wrapping_add(unzigzag(self.varint()?));
+            y = y.wrapping_add(unzigzag(self.varint()?));
+            if flags & HAS_TIMESTAMPS != 0 {
+                t = (t as i64).wrapping_add(unzigzag(self.varint()?)) as u64;
+            }
+            let pressure = if flags & HAS_PRESSURE != 0 {
+                self.varint()?.checked_sub(1).map(|p| p as f64 / PRESSURE_SCALE)
+            } else {
+                None
+            };
+            points.push(Point {
+                x: x as f64 / COORD_SCALE,
+                y: y as f64 / COORD_SCALE,
+                pressure,
+                timestamp: t,
+            });
+        }
+
+        Ok(Stroke { id, points, color, width, tool, layer_id, curves: Vec::new() })
+    }
+}
+
+/// Serde adapter that stores indexed strokes (as held in the undo log) in the
+/// binary stroke format, base64-encoded, instead of as JSON point lists.
+///
+/// Plain JSON lists from older backups are still read.
+pub mod packed_strokes {
+    use crate::history::IndexedStroke;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Packed {
+        indices: Vec<usize>,
+        strokes: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Packed(Packed),
+        Plain(Vec<IndexedStroke>),
+    }
+
+    pub fn serialize<S: Serializer>(strokes: &[IndexedStroke], serializer: S) -> Result<S::Ok, S::Error> {
+        let plain: Vec<crate::Stroke> = strokes.iter().map(|s| s.stroke.clone()).collect();
+        Packed {
+            indices: strokes.iter().map(|s| s.index).collect(),
+            strokes: base64::engine::general_purpose::STANDARD.encode(super::encode_strokes(&plain)),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<IndexedStroke>, D::Error> {
+        let packed = match Stored::deserialize(deserializer)? {
+            Stored::Plain(mut strokes) => {
+                for s in strokes.iter_mut().filter(|s| s.stroke.curves.is_empty()) {
+                    s.stroke.curves = crate::canvas::fit_stroke_curves(&s.stroke);
+                }
+                return Ok(strokes);
+            }
+            Stored::Packed(packed) => packed,
+        };
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&packed.strokes)
+            .map_err(serde::de::Error::custom)?;
+        let strokes = super::decode_strokes(&bytes).map_err(serde::de::Error::custom)?;
+        if strokes.len() != packed.indices.len() {
+            return Err(serde::de::Error::custom("Stroke and index counts differ"));
+        }
+        Ok(packed
+            .indices
+            .into_iter()
+            .zip(strokes)
+            .map(|(index, mut stroke)| {
+                stroke.curves = crate::canvas::fit_stroke_curves(&stroke);
+                IndexedStroke { index, stroke }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+
+    fn sample_strokes() -> Vec<Stroke> {
+        let points = (0..50)
+            .map(|i| Point {
+                x: 1000.0 + i as f64 * 1.25,
+                y: -20.5 + (i as f64 * 0.3).sin() * 40.0,
+                pressure: if i == 3 { None } else { Some(i as f64 / 50.0) },
+                timestamp: 1_700_000_000_000 + i * 8,
+            })
+            .collect();
+        vec![
+            Stroke {
+                color: "#ff000080".to_string(),
+                width: 2.5,
+                ..test_stroke("a", points)
+            },
+            Stroke {
+                width: 1.0,
+                layer_id: "notes".to_string(),
+                ..test_stroke("imported", vec![Point { x: 0.0, y: 0.0, pressure: None, timestamp: 0 }])
+            },
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip_within_quantisation() {
+        let strokes = sample_strokes();
+        let bytes = encode_strokes(&strokes);
+        let decoded = decode_strokes(&bytes).unwrap();
+
+        assert_eq!(decoded.len(), strokes.len());
+        for (a, b) in strokes.iter().zip(&decoded) {
+            assert_eq!((&a.id, &a.color, &a.tool, &a.layer_id, a.width), (&b.id, &b.color, &b.tool, &b.layer_id, b.width));
+            for (p, q) in a.points.iter().zip(&b.points) {
+                assert!((p.x - q.x).abs() <= 0.005 && (p.y - q.y).abs() <= 0.005);
+                assert_eq!(p.timestamp, q.timestamp);
+                assert_eq!(p.pressure.is_some(), q.pressure.is_some());
+                assert!((p.pressure.unwrap_or(0.0) - q.pressure.unwrap_or(0.0)).abs() <= 0.0005);
+            }
+        }
+
+        // Much smaller than the JSON it replaces
+        let json = serde_json::to_vec(&strokes).unwrap();
+        assert!(bytes.len() * 4 < json.len());
+    }
+
+    #[test]
+    fn test_corrupt_data_is_rejected() {
+        let bytes = encode_strokes(&sample_strokes());
+        assert!(decode_strokes(&bytes[..bytes.len() - 3]).is_err());
+        assert!(decode_strokes(b"{\"strokes\":[]}").is_err());
+
+        let mut future = bytes.clone();
+        future[3] = 9;
+        assert_eq!(decode_strokes(&future).unwrap_err(), "Unsupported stroke format version 9");
+
+        // A huge declared count must fail cleanly rather than allocate
+        let mut huge = b"WBS\x01".to_vec();
+        write_varint(&mut huge, u64::MAX);
+        assert!(decode_strokes(&huge).is_err());
+    }
+
+    #[test]
+    fn test_backup_keeps_metadata() {
+        let strokes = sample_strokes();
+        let bytes = encode_backup(&strokes, b"{\"layers\":null}");
+        assert!(is_binary_backup(&bytes));
+        assert!(!is_binary_backup(b"[]"));
+
+        let (decoded, metadata) = decode_backup(&bytes).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(metadata, b"{\"layers\":null}");
+    }
+
+    #[test]
+    fn test_history_deltas_are_packed() {
+        use crate::history::{Delta, IndexedStroke};
+
+        let added: Vec<IndexedStroke> = sample_strokes()
+            .into_iter()
+            .enumerate()
+            .map(|(index, stroke)| IndexedStroke { index: index * 2, stroke })
+            .collect();
+        let delta = Delta { added: added.clone(), ..Delta::default() };
+
+        let json = serde_json::to_string(&delta).unwrap();
+        assert!(!json.contains("\"points\""));
+        let decoded: Delta = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.added.iter().map(|s| s.index).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(decoded.added[0].stroke.points.len(), 50);
+
+        // Older backups hold the strokes as plain JSON
+        let legacy = serde_json::json!({ "removed": [], "added": [{ "index": 4, "stroke": &added[1].stroke }] });
+        let decoded: Delta = serde_json::from_value(legacy).unwrap();
+        assert_eq!((decoded.added[0].index, decoded.added[0].stroke.id.as_str()), (4, "imported"));
+    }
+
+    #[test]
+    fn test_sync_sends_only_changes() {
+        let mut strokes = sample_strokes();
+        let mut tracker = SyncTracker::default();
+
+        let first = tracker.since(&strokes, 4, None);
+        assert!(first.full);
+        assert_eq!(first.changed.len(), 2);
+
+        let unchanged = tracker.since(&strokes, 4, Some(4));
+        assert!(!unchanged.full && unchanged.changed.is_empty() && unchanged.removed.is_empty());
+
+        strokes.remove(0);
+        strokes[0].width = 3.0;
+        strokes.push(Stroke { id: "new".to_string(), ..strokes[0].clone() });
+        let sync = tracker.since(&strokes, 6, Some(4));
+        assert!(!sync.full);
+        assert_eq!(sync.removed, vec!["a".to_string()]);
+        let changed: Vec<_> = sync.changed.iter().map(|(i, s)| (*i, s.id.as_str())).collect();
+        assert_eq!(changed, vec![(0, "imported"), (1, "new")]);
+
+        // Versions from before the tracker started can't be diffed
+        assert!(tracker.since(&strokes, 6, Some(2)).full);
+        assert!(tracker.since(&strokes, 6, Some(9)).full);
+    }
+}
@@ -0,0 +1,466 @@
+//! Colour module
+//!
+//! A single colour type for stroke ink and export styling. Parses the CSS
+//! forms the frontend may send (hex, named colours, `rgb()`/`rgba()`,
+//! `hsl()`/`hsla()`) and maps ink onto a palette of draw.io fill and stroke
+//! pairs.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// An sRGB colour with alpha
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const TRANSPARENT: Color = Color { r: 0, g: 0, b: 0, a: 0 };
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Parse a CSS colour: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, a named
+    /// colour, `rgb()`/`rgba()` or `hsl()`/`hsla()`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let lower = text.to_ascii_lowercase();
+
+        let parsed = if let Some(hex) = lower.strip_prefix('#') {
+            parse_hex(hex)
+        } else if let Some(args) = function_args(&lower, &["rgba", "rgb"]) {
+            parse_rgb(args)
+        } else if let Some(args) = function_args(&lower, &["hsla", "hsl"]) {
+            parse_hsl(args)
+        } else {
+            named(&lower)
+        };
+        parsed.ok_or_else(|| format!("Invalid colour: {:?}", text))
+    }
+
+    /// `#rrggbb`, with an `aa` suffix when not fully opaque
+    pub fn to_hex(self) -> String {
+        if self.a == 255 {
+            self.rgb_hex()
+        } else {
+            format!("{}{:02x}", self.rgb_hex(), self.a)
+        }
+    }
+
+    /// `#rrggbb`, ignoring alpha
+    pub fn rgb_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Alpha as 0.0..=1.0
+    pub fn opacity(self) -> f64 {
+        self.a as f64 / 255.0
+    }
+
+    pub fn to_rgba(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Red, green and blue as 0.0..=1.0
+    pub fn components(self) -> (f32, f32, f32) {
+        (self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0)
+    }
+
+    /// Perceptually weighted ("redmean") RGB distance, ignoring alpha
+    pub fn distance(self, other: Color) -> f64 {
+        let mean_r = (self.r as f64 + other.r as f64) / 2.0;
+        let dr = self.r as f64 - other.r as f64;
+        let dg = self.g as f64 - other.g as f64;
+        let db = self.b as f64 - other.b as f64;
+        ((2.0 + mean_r / 256.0) * dr * dr + 4.0 * dg * dg + (2.0 + (255.0 - mean_r) / 256.0) * db * db).sqrt()
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::parse(s)
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Color::parse(&value)
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_hex()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// Colour of stroke ink. Stroke colours are validated when strokes are added,
+/// so an unreadable one only comes from old data and is drawn black.
+pub fn ink(text: &str) -> Color {
+    Color::parse(text).unwrap_or(Color::BLACK)
+}
+
+/// Canonical hex form of a colour string, or `None` if it can't be parsed
+pub fn normalize(text: &str) -> Option<String> {
+    Color::parse(text).ok().map(|c| c.to_hex())
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    match hex.len() {
+        3 => Some(Color::rgb(digit(0)?, digit(1)?, digit(2)?)),
+        4 => Some(Color { a: digit(3)?, ..Color::rgb(digit(0)?, digit(1)?, digit(2)?) }),
+        6 => Some(Color::rgb(pair(0)?, pair(2)?, pair(4)?)),
+        8 => Some(Color { a: pair(6)?, ..Color::rgb(pair(0)?, pair(2)?, pair(4)?) }),
+        _ => None,
+    }
+}
+
+/// Arguments of `name(...)` for the first matching function name
+fn function_args<'a>(text: &'a str, names: &[&str]) -> Option<&'a str> {
+    names.iter().find_map(|name| {
+        text.strip_prefix(name)?
+            .trim_start()
+            .strip_prefix('(')?
+            .strip_suffix(')')
+    })
+}
+
+/// Split function arguments in either the legacy comma form or the modern
+/// space form with an optional `/ alpha`
+fn split_args(args: &str) -> Option<(Vec<&str>, Option<&str>)> {
+    if args.contains(',') {
+        let mut parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        let alpha = if parts.len() == 4 { parts.pop() } else { None };
+        return (parts.len() == 3).then_some((parts, alpha));
+    }
+    let (channels, alpha) = match args.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (args, None),
+    };
+    let parts: Vec<&str> = channels.split_whitespace().collect();
+    (parts.len() == 3).then_some((parts, alpha))
+}
+
+/// A number or percentage, as a fraction of `full`
+fn parse_number(text: &str, full: f64) -> Option<f64> {
+    let value = match text.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().ok()? / 100.0 * full,
+        None => text.parse::<f64>().ok()?,
+    };
+    value.is_finite().then_some(value)
+}
+
+fn parse_alpha(alpha: Option<&str>) -> Option<u8> {
+    match alpha {
+        Some(alpha) => Some(to_byte(parse_number(alpha, 1.0)? * 255.0)),
+        None => Some(255),
+    }
+}
+
+fn to_byte(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+fn parse_rgb(args: &str) -> Option<Color> {
+    let (channels, alpha) = split_args(args)?;
+    let channel = |i: usize| parse_number(channels[i], 255.0).map(to_byte);
+    Some(Color {
+        r: channel(0)?,
+        g: channel(1)?,
+        b: channel(2)?,
+        a: parse_alpha(alpha)?,
+    })
+}
+
+fn parse_hsl(args: &str) -> Option<Color> {
+    let (channels, alpha) = split_args(args)?;
+    let hue = channels[0]
+        .strip_suffix("deg")
+        .unwrap_or(channels[0])
+        .parse::<f64>()
+        .ok()
+        .filter(|h| h.is_finite())?;
+    let saturation = parse_number(channels[1], 1.0)?.clamp(0.0, 1.0);
+    let lightness = parse_number(channels[2], 1.0)?.clamp(0.0, 1.0);
+
+    // CSS Color 4, section 7.1
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0).rem_euclid(12.0);
+        let a = saturation * lightness.min(1.0 - lightness);
+        to_byte((lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)) * 255.0)
+    };
+    Some(Color {
+        r: channel(0.0),
+        g: channel(8.0),
+        b: channel(4.0),
+        a: parse_alpha(alpha)?,
+    })
+}
+
+fn named(name: &str) -> Option<Color> {
+    if name == "transparent" {
+        return Some(Color::TRANSPARENT);
+    }
+    let index = NAMED_COLORS.binary_search_by(|(n, _)| n.cmp(&name)).ok()?;
+    let rgb = NAMED_COLORS[index].1;
+    Some(Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/// CSS named colours, sorted by name
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// A draw.io fill/stroke pair used for ink close to `ink`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaletteEntry {
+    pub ink: Color,
+    pub fill: Color,
+    pub stroke: Color,
+}
+
+/// Ink colours mapped to draw.io fill and stroke pairs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub entries: Vec<PaletteEntry>,
+}
+
+impl Default for Palette {
+    /// draw.io's standard style pairs
+    fn default() -> Self {
+        let entry = |ink: u32, fill: u32, stroke: u32| {
+            let color = |rgb: u32| Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+            PaletteEntry { ink: color(ink), fill: color(fill), stroke: color(stroke) }
+        };
+        Self {
+            entries: vec![
+                entry(0x000000, 0xffffff, 0x000000),
+                entry(0x808080, 0xf5f5f5, 0x666666),
+                entry(0x1e63d6, 0xdae8fc, 0x6c8ebf),
+                entry(0x2e9e3e, 0xd5e8d4, 0x82b366),
+                entry(0xf0c419, 0xfff2cc, 0xd6b656),
+                entry(0xf08c00, 0xffe6cc, 0xd79b00),
+                entry(0xe03131, 0xf8cecc, 0xb85450),
+                entry(0x8e44ad, 0xe1d5e7, 0x9673a6),
+            ],
+        }
+    }
+}
+
+impl Palette {
+    /// The entry whose ink colour is closest to `ink`
+    pub fn nearest(&self, ink: Color) -> Option<&PaletteEntry> {
+        self.entries
+            .iter()
+            .min_by(|a, b| a.ink.distance(ink).total_cmp(&b.ink.distance(ink)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_css_forms() {
+        let red = Color::rgb(255, 0, 0);
+        for text in ["#f00", "#ff0000", "#FF0000FF", "red", " Red ", "rgb(255, 0, 0)", "rgb(100% 0% 0%)", "hsl(0, 100%, 50%)", "hsl(360deg 100% 50%)"] {
+            assert_eq!(Color::parse(text), Ok(red), "{}", text);
+        }
+        assert_eq!(Color::parse("#f008").unwrap().a, 0x88);
+        assert_eq!(Color::parse("rgba(0, 0, 255, 0.5)").unwrap(), Color { a: 128, ..Color::rgb(0, 0, 255) });
+        assert_eq!(Color::parse("hsla(120, 100%, 25%, 50%)").unwrap(), Color { a: 128, ..Color::rgb(0, 128, 0) });
+        assert_eq!(Color::parse("transparent").unwrap().a, 0);
+        assert_eq!(Color::parse("#00ff0080").unwrap().to_hex(), "#00ff0080");
+
+        for text in ["", "#", "#ff", "#gggggg", "#ff00000", "rgb(1, 2)", "hsl(red)", "notacolour", "#é0000"] {
+            assert!(Color::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_named_colors_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(Color::parse("rebeccapurple").unwrap().to_hex(), "#663399");
+    }
+
+    #[test]
+    fn test_palette_maps_ink_to_nearest_pair() {
+        let palette = Palette::default();
+        let fill = |ink: &str| palette.nearest(Color::parse(ink).unwrap()).unwrap().fill.to_hex();
+        assert_eq!(fill("#1a1a1a"), "#ffffff");
+        assert_eq!(fill("blue"), "#dae8fc");
+        assert_eq!(fill("crimson"), "#f8cecc");
+        assert_eq!(fill("forestgreen"), "#d5e8d4");
+    }
+}
@@ -3,12 +3,16 @@
 //! Generates mxGraph XML format compatible with draw.io/diagrams.net
 //! for exporting hand-drawn diagrams.
 
+use crate::color::{self, Color, Palette};
+use crate::icons::IconStyleConfig;
+use crate::layers::Layer;
 use crate::ocr::TextRegion;
-use crate::shapes::DetectedShape;
-use crate::ExportOptions;
+use crate::shapes::{Annotation, DetectedShape};
+use crate::{ExportOptions, Stroke};
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Writer;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::Cursor;
 
 /// Diagram structure for export
@@ -31,6 +35,10 @@ pub struct DiagramNode {
     pub width: f64,
     pub height: f64,
     pub style: String,
+    /// Id of the container node (e.g. a swimlane) this node sits in; `x`
+    /// and `y` stay absolute either way
+    #[serde(default)]
+    pub parent: Option<String>,
 }
 
 /// An edge/connection in the diagram
@@ -41,6 +49,14 @@ pub struct DiagramEdge {
     pub target: String,
     pub label: Option<String>,
     pub style: String,
+    /// Absolute bend points between the ends
+    #[serde(default)]
+    pub waypoints: Vec<(f64, f64)>,
+    /// Absolute end points, for ends not attached to a node
+    #[serde(default)]
+    pub source_point: Option<(f64, f64)>,
+    #[serde(default)]
+    pub target_point: Option<(f64, f64)>,
 }
 
 /// Diagram metadata
@@ -52,6 +68,9 @@ pub struct DiagramMetadata {
     pub version: Option<String>,
 }
 
+/// Distance around a shape within which OCR text counts as its label
+pub(crate) const LABEL_MARGIN: f64 = 20.0;
+
 /// Style presets for different shape types
 pub struct StylePresets;
 
@@ -91,14 +110,169 @@ impl StylePresets {
     pub fn dashed_arrow() -> &'static str {
         "edgeStyle=orthogonalEdgeStyle;rounded=0;orthogonalLoop=1;jettySize=auto;html=1;endArrow=classic;endFill=1;dashed=1;"
     }
+
+    pub fn text() -> &'static str {
+        "text;html=1;align=left;verticalAlign=top;whiteSpace=wrap;"
+    }
+
+    pub fn note() -> &'static str {
+        "shape=note;whiteSpace=wrap;html=1;backgroundOutline=1;size=12;fillColor=#fff2cc;strokeColor=#d6b656;"
+    }
+
+    pub fn note_link() -> &'static str {
+        "edgeStyle=none;html=1;dashed=1;endArrow=none;"
+    }
+
+    pub fn swimlane_horizontal() -> &'static str {
+        "swimlane;horizontal=0;whiteSpace=wrap;html=1;startSize=30;"
+    }
+
+    pub fn swimlane_vertical() -> &'static str {
+        "swimlane;whiteSpace=wrap;html=1;startSize=30;"
+    }
+
+    pub fn bpmn_start_event() -> &'static str {
+        "shape=mxgraph.bpmn.event;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=ellipsePerimeter;outlineConnect=0;aspect=fixed;outline=standard;symbol=general;"
+    }
+
+    pub fn bpmn_intermediate_event() -> &'static str {
+        "shape=mxgraph.bpmn.event;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=ellipsePerimeter;outlineConnect=0;aspect=fixed;outline=catching;symbol=general;"
+    }
+
+    pub fn bpmn_end_event() -> &'static str {
+        "shape=mxgraph.bpmn.event;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=ellipsePerimeter;outlineConnect=0;aspect=fixed;outline=end;symbol=general;"
+    }
+
+    pub fn bpmn_exclusive_gateway() -> &'static str {
+        "shape=mxgraph.bpmn.gateway2;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=rhombusPerimeter;outlineConnect=0;outline=none;symbol=none;gwType=exclusive;"
+    }
+
+    pub fn bpmn_parallel_gateway() -> &'static str {
+        "shape=mxgraph.bpmn.gateway2;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=rhombusPerimeter;outlineConnect=0;outline=none;symbol=none;gwType=parallel;"
+    }
+
+    pub fn bpmn_blank_gateway() -> &'static str {
+        "shape=mxgraph.bpmn.gateway2;html=1;verticalLabelPosition=bottom;labelBackgroundColor=#ffffff;verticalAlign=top;align=center;perimeter=rhombusPerimeter;outlineConnect=0;outline=none;symbol=none;"
+    }
+
+    pub fn bpmn_task() -> &'static str {
+        "shape=mxgraph.bpmn.task;whiteSpace=wrap;rectStyle=rounded;size=10;html=1;taskMarker=abstract;"
+    }
+
+    pub fn bpmn_pool() -> &'static str {
+        "swimlane;html=1;horizontal=0;startSize=20;"
+    }
+
+    pub fn bpmn_lane() -> &'static str {
+        "swimlane;html=1;horizontal=0;swimlaneLine=0;startSize=20;"
+    }
+
+    pub fn bpmn_sequence_flow() -> &'static str {
+        "edgeStyle=elbowEdgeStyle;fontSize=12;html=1;endArrow=block;endFill=1;"
+    }
+
+    pub fn mockup_image() -> &'static str {
+        "verticalLabelPosition=bottom;shadow=0;dashed=0;align=center;html=1;verticalAlign=top;strokeWidth=1;shape=mxgraph.mockup.graphics.simpleIcon;strokeColor=#999999;"
+    }
+
+    pub fn mockup_button() -> &'static str {
+        "strokeWidth=1;shadow=0;dashed=0;align=center;html=1;shape=mxgraph.mockup.buttons.button;strokeColor=#666666;fontColor=#ffffff;mainText=;buttonStyle=round;fontSize=14;fontStyle=1;fillColor=#008cff;whiteSpace=wrap;"
+    }
+
+    pub fn mockup_input() -> &'static str {
+        "strokeWidth=1;shadow=0;dashed=0;html=1;shape=mxgraph.mockup.forms.rrect;rSize=0;strokeColor=#999999;fillColor=#ffffff;align=left;spacingLeft=4;fontSize=14;fontColor=#666666;"
+    }
+
+    pub fn mockup_checkbox() -> &'static str {
+        "strokeWidth=1;shadow=0;dashed=0;html=1;shape=mxgraph.mockup.forms.rrect;rSize=0;fillColor=#eeeeee;strokeColor=#999999;labelPosition=right;verticalLabelPosition=middle;align=left;verticalAlign=middle;spacingLeft=4;fontSize=14;fontColor=#666666;"
+    }
+}
+
+/// How the ink behind each shape styles its exported cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InkStyle {
+    /// Preset draw.io colours per shape type
+    #[default]
+    Theme,
+    /// The palette fill and stroke pair nearest the ink colour, at the ink's width
+    Palette,
+    /// The ink's own colour and width, unfilled
+    Preserve,
+}
+
+/// How highlighter emphasis regions are exported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightStyle {
+    /// A low-opacity rectangle behind the highlighted content
+    #[default]
+    Region,
+    /// The highlight colour as the fill of the cells it covers; regions
+    /// covering no cell stay rectangles
+    Fill,
+}
+
+/// Highlighter colour used when the region's strokes aren't available
+const DEFAULT_HIGHLIGHT: Color = Color::rgb(255, 235, 59);
+
+/// Board data used by the exporter beyond the recognized shapes
+#[derive(Debug, Default)]
+pub struct BoardContext<'a> {
+    /// Board layers, bottom to top; each becomes an mxGraph layer cell.
+    /// With no layers a single unnamed layer is written.
+    pub layers: &'a [Layer],
+    /// Layer id of each shape; unmapped shapes and free text go on the bottom layer
+    pub shape_layers: HashMap<String, String>,
+    /// Raw strokes, drawn as freehand cells for freeform shapes and used for ink styling
+    pub strokes: &'a [Stroke],
+}
+
+/// Ink of the strokes behind a shape
+struct Ink {
+    /// Colour covering the most points
+    color: Color,
+    /// Point-weighted mean stroke width
+    width: f64,
+}
+
+/// Per-shape placement and styling derived from the board
+#[derive(Default)]
+struct ShapeExtras {
+    /// Layer cell each shape is written into
+    layer_cells: HashMap<String, String>,
+    /// Freehand stencil styles for freeform shapes
+    freehand_styles: HashMap<String, String>,
+    /// Style keys set from each shape's ink
+    ink_styles: HashMap<String, Vec<(&'static str, String)>>,
+    /// Colour of each highlight region written as its own cell
+    highlights: HashMap<String, Color>,
+    /// Highlight fill of cells covered by a highlight region
+    highlight_fills: HashMap<String, Color>,
+}
+
+impl ShapeExtras {
+    fn layer_cell(&self, shape_id: &str) -> &str {
+        self.layer_cells.get(shape_id).map_or("1", String::as_str)
+    }
+
+    /// `style` with the shape's ink styling applied
+    fn inked_style(&self, shape_id: &str, style: String) -> String {
+        match self.ink_styles.get(shape_id) {
+            Some(keys) => keys.iter().fold(style, |style, (key, value)| set_style_key(&style, key, value)),
+            None => style,
+        }
+    }
 }
 
 /// Generate mxGraph XML from detected shapes and text
 pub fn generate_xml(
     shapes: &[DetectedShape],
     text_regions: &[TextRegion],
+    board: &BoardContext,
     options: &ExportOptions,
 ) -> Result<String, String> {
+    let layers = board.layers;
     let mut writer = Writer::new(Cursor::new(Vec::new()));
 
     // XML declaration
@@ -156,16 +330,87 @@ pub fn generate_xml(
 
     // Default parent cells (required by draw.io)
     write_cell(&mut writer, "0", "", "")?;
-    write_cell_with_parent(&mut writer, "1", "0")?;
 
     // Cell ID counter
     let mut cell_id = 2;
 
+    // The bottom layer is the default layer cell "1"; the others follow it
+    let mut layer_cell_ids: HashMap<&str, String> = HashMap::new();
+    if layers.is_empty() {
+        write_cell_with_parent(&mut writer, "1", "0")?;
+    }
+    for (i, layer) in layers.iter().enumerate() {
+        let id = if i == 0 {
+            "1".to_string()
+        } else {
+            cell_id += 1;
+            (cell_id - 1).to_string()
+        };
+        write_layer_cell(&mut writer, &id, layer)?;
+        layer_cell_ids.insert(layer.id.as_str(), id);
+    }
+    let mut extras = ShapeExtras {
+        layer_cells: board
+            .shape_layers
+            .iter()
+            .filter_map(|(shape_id, layer_id)| Some((shape_id.clone(), layer_cell_ids.get(layer_id.as_str())?.clone())))
+            .collect(),
+        freehand_styles: shapes
+            .iter()
+            .filter(|s| s.shape_type == crate::shapes::ShapeType::Freeform)
+            .filter_map(|s| Some((s.id.clone(), freehand_style(s, board.strokes)?)))
+            .collect(),
+        ink_styles: shapes
+            .iter()
+            .filter_map(|s| {
+                let ink = shape_ink(s, board.strokes)?;
+                Some((s.id.clone(), ink_style_keys(s, &ink, options.ink_style, &options.palette)))
+            })
+            .filter(|(_, keys)| !keys.is_empty())
+            .collect(),
+        ..ShapeExtras::default()
+    };
+    place_highlights(&mut extras, shapes, board.strokes, options.highlight_style);
+
+    // Mode-specific styling (BPMN, wireframe) follows the requested or detected diagram type
+    let diagram_type = options
+        .diagram_type
+        .clone()
+        .unwrap_or_else(|| crate::shapes::classify_diagram(shapes, text_regions).0);
+
     // Convert shapes to cells
-    let shape_id_map = write_shapes(&mut writer, shapes, text_regions, &mut cell_id)?;
+    let shape_id_map = write_shapes(
+        &mut writer,
+        shapes,
+        text_regions,
+        &mut cell_id,
+        &diagram_type,
+        &options.icon_styles,
+        &extras,
+    )?;
+
+    // Free text becomes text cells, or notes linked to the shape they point at
+    let annotations = crate::shapes::detect_annotations(shapes, text_regions, LABEL_MARGIN);
+    let annotation_layers = annotations
+        .iter()
+        .map(|a| (a.text_id.clone(), annotation_layer_cell(a, &extras, board.strokes, &layer_cell_ids)))
+        .collect();
+    write_annotations(&mut writer, &annotations, &shape_id_map, &annotation_layers, &mut cell_id)?;
+    let leader_ids: Vec<String> = annotations
+        .iter()
+        .filter_map(|a| a.leader_shape_id.clone())
+        .collect();
 
     // Write connectors
-    write_connectors(&mut writer, shapes, &shape_id_map, &mut cell_id)?;
+    write_connectors(
+        &mut writer,
+        shapes,
+        &shape_id_map,
+        &mut cell_id,
+        diagram_type == "bpmn",
+        &leader_ids,
+        &extras,
+    )?;
 
     // Close root
     writer
@@ -225,17 +470,287 @@ fn write_cell_with_parent(
         .map_err(|e| e.to_string())
 }
 
+/// Write a layer cell; hidden and locked layers keep those states in draw.io
+fn write_layer_cell(writer: &mut Writer<Cursor<Vec<u8>>>, id: &str, layer: &Layer) -> Result<(), String> {
+    let mut cell = BytesStart::new("mxCell");
+    cell.push_attribute(("id", id));
+    cell.push_attribute(("value", layer.name.as_str()));
+    if layer.locked {
+        cell.push_attribute(("style", "locked=1;"));
+    }
+    cell.push_attribute(("parent", "0"));
+    if !layer.visible {
+        cell.push_attribute(("visible", "0"));
+    }
+    writer
+        .write_event(Event::Empty(cell))
+        .map_err(|e| e.to_string())
+}
+
+/// Decide how each highlight region is written: as its own cell, or as the
+/// fill of the cells whose centre it covers
+fn place_highlights(extras: &mut ShapeExtras, shapes: &[DetectedShape], strokes: &[Stroke], style: HighlightStyle) {
+    use crate::shapes::{bounds_center, bounds_contain, ShapeType};
+
+    for highlight in shapes.iter().filter(|s| s.shape_type == ShapeType::Highlight) {
+        let color = shape_ink(highlight, strokes).map_or(DEFAULT_HIGHLIGHT, |ink| ink.color);
+        let covered: Vec<&DetectedShape> = match style {
+            HighlightStyle::Region => Vec::new(),
+            HighlightStyle::Fill => shapes
+                .iter()
+                .filter(|s| {
+                    !matches!(
+                        s.shape_type,
+                        ShapeType::Arrow | ShapeType::Line | ShapeType::Connector | ShapeType::Swimlane | ShapeType::Highlight
+                    ) && s.properties.icon.is_none()
+                })
+                .filter(|s| {
+                    let (cx, cy) = bounds_center(&s.bounds);
+                    bounds_contain(&highlight.bounds, cx, cy)
+                })
+                .collect(),
+        };
+
+        if covered.is_empty() {
+            extras.highlights.insert(highlight.id.clone(), color);
+        }
+        for shape in covered {
+            extras.highlight_fills.insert(shape.id.clone(), color);
+        }
+    }
+}
+
+/// Low-opacity rectangle style for a highlight region
+fn highlight_style(color: Color) -> String {
+    format!(
+        "rounded=0;whiteSpace=wrap;html=1;fillColor={};strokeColor=none;opacity={:.0};",
+        color.rgb_hex(),
+        (color.opacity() * 40.0).max(10.0)
+    )
+}
+
+/// Dominant colour and mean width of the ink strokes behind a shape
+fn shape_ink(shape: &DetectedShape, strokes: &[Stroke]) -> Option<Ink> {
+    let members: Vec<&Stroke> = strokes
+        .iter()
+        .filter(|s| s.tool != "eraser" && shape.stroke_ids.contains(&s.id))
+        .collect();
+    let total: usize = members.iter().map(|s| s.points.len()).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut coverage: Vec<(Color, usize)> = Vec::new();
+    for stroke in &members {
+        let color = color::ink(&stroke.color);
+        match coverage.iter_mut().find(|(c, _)| *c == color) {
+            Some((_, count)) => *count += stroke.points.len(),
+            None => coverage.push((color, stroke.points.len())),
+        }
+    }
+    // First-drawn colour wins ties
+    let color = coverage.iter().rev().max_by_key(|(_, count)| *count)?.0;
+    let width = members.iter().map(|s| s.width * s.points.len() as f64).sum::<f64>() / total as f64;
+    Some(Ink { color, width })
+}
+
+/// Style keys to set on a shape's cell from its ink
+fn ink_style_keys(shape: &DetectedShape, ink: &Ink, mode: InkStyle, palette: &Palette) -> Vec<(&'static str, String)> {
+    use crate::shapes::ShapeType;
+
+    // Highlights are styled from their ink separately
+    if shape.shape_type == ShapeType::Highlight {
+        return Vec::new();
+    }
+    let is_edge = matches!(shape.shape_type, ShapeType::Arrow | ShapeType::Line | ShapeType::Connector);
+    let is_freehand = shape.shape_type == ShapeType::Freeform;
+    let entry = palette.nearest(ink.color);
+    let width = ("strokeWidth", format!("{:.1}", ink.width.max(0.5)));
+
+    match mode {
+        // Freehand cells are the ink itself, so they always carry its width
+        InkStyle::Theme if is_freehand => {
+            let stroke = entry.map_or(ink.color, |e| e.stroke);
+            vec![("strokeColor", stroke.rgb_hex()), width]
+        }
+        InkStyle::Theme => Vec::new(),
+        InkStyle::Palette => {
+            let Some(entry) = entry else {
+                return vec![width];
+            };
+            let mut keys = vec![("strokeColor", entry.stroke.rgb_hex()), width];
+            if !is_edge && !is_freehand {
+                keys.push(("fillColor", entry.fill.rgb_hex()));
+            }
+            keys
+        }
+        InkStyle::Preserve => {
+            let mut keys = vec![("strokeColor", ink.color.rgb_hex()), width];
+            if ink.color.a < 255 {
+                keys.push(("strokeOpacity", format!("{:.0}", ink.color.opacity() * 100.0)));
+            }
+            if !is_edge {
+                keys.push(("fillColor", "none".to_string()));
+            }
+            keys
+        }
+    }
+}
+
+/// Set `key=value` in a draw.io style string, replacing any existing value
+fn set_style_key(style: &str, key: &str, value: &str) -> String {
+    let mut found = false;
+    let mut entries: Vec<String> = style
+        .split(';')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((k, _)) if k == key => {
+                found = true;
+                format!("{}={}", key, value)
+            }
+            _ => entry.to_string(),
+        })
+        .collect();
+    if !found {
+        entries.push(format!("{}={}", key, value));
+    }
+    entries.join(";") + ";"
+}
+
+/// draw.io freehand style for a freeform shape: its strokes' fitted curves as
+/// an inline stencil, in coordinates relative to the shape's bounds
+fn freehand_style(shape: &DetectedShape, strokes: &[Stroke]) -> Option<String> {
+    let b = &shape.bounds;
+    let members: Vec<&Stroke> = strokes
+        .iter()
+        .filter(|s| shape.stroke_ids.contains(&s.id) && s.points.len() > 1)
+        .collect();
+    if members.is_empty() {
+        return None;
+    }
+
+    let local = |(x, y): (f64, f64)| (x - b.x, y - b.y);
+    let mut path = String::new();
+    for stroke in &members {
+        let fitted;
+        let curves = if stroke.curves.is_empty() {
+            fitted = crate::canvas::fit_stroke_curves(stroke);
+            &fitted
+        } else {
+            &stroke.curves
+        };
+        let Some(first) = curves.first() else { continue };
+        let (x, y) = local(first.p0);
+        path.push_str(&format!("<move x=\"{:.2}\" y=\"{:.2}\"/>", x, y));
+        for c in curves {
+            let (p1, p2, p3) = (local(c.p1), local(c.p2), local(c.p3));
+            path.push_str(&format!(
+                "<curve x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" x3=\"{:.2}\" y3=\"{:.2}\"/>",
+                p1.0, p1.1, p2.0, p2.1, p3.0, p3.1
+            ));
+        }
+    }
+
+    let stencil = format!(
+        "<shape w=\"{:.2}\" h=\"{:.2}\" aspect=\"variable\" strokewidth=\"inherit\"><foreground><path>{}</path><stroke/></foreground></shape>",
+        b.width.max(1.0),
+        b.height.max(1.0),
+        path
+    );
+    Some(format!(
+        "shape=stencil({});whiteSpace=wrap;html=1;fillColor=none;",
+        compress(&stencil).ok()?
+    ))
+}
+
+/// draw.io's `Graph.compress`: URI-encode, raw-deflate, then base64
+pub fn compress(text: &str) -> Result<String, String> {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(encode_uri_component(text).as_bytes())
+        .map_err(|e| format!("Failed to compress: {}", e))?;
+    let bytes = encoder.finish().map_err(|e| format!("Failed to compress: {}", e))?;
+    Ok(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes))
+}
+
+/// draw.io's `Graph.decompress`: base64-decode, raw-inflate, then URI-decode
+pub fn decompress(data: &str) -> Result<String, String> {
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    let compact: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, compact)
+        .map_err(|e| format!("Invalid compressed diagram: {}", e))?;
+    let mut inflated = String::new();
+    DeflateDecoder::new(bytes.as_slice())
+        .read_to_string(&mut inflated)
+        .map_err(|e| format!("Invalid compressed diagram: {}", e))?;
+    decode_uri_component(&inflated)
+}
+
+/// JavaScript's `encodeURIComponent`
+fn encode_uri_component(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// JavaScript's `decodeURIComponent`
+fn decode_uri_component(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("Invalid escape in compressed diagram at {}", i))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|e| format!("Invalid text in compressed diagram: {}", e))
+}
+
 /// Write shape cells and return a mapping of original IDs to cell IDs
 fn write_shapes(
     writer: &mut Writer<Cursor<Vec<u8>>>,
     shapes: &[DetectedShape],
     text_regions: &[TextRegion],
     cell_id: &mut i32,
+    diagram_type: &str,
+    icon_styles: &IconStyleConfig,
+    extras: &ShapeExtras,
 ) -> Result<std::collections::HashMap<String, String>, String> {
-    use crate::shapes::ShapeType;
-    let mut id_map = std::collections::HashMap::new();
-
-    for shape in shapes {
+    use crate::shapes::{BpmnElement, ShapeType};
+    use crate::wireframe::WireframeElement;
+    let bpmn_mode = diagram_type == "bpmn";
+    let mut id_map: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+
+    // Containers are written first so they sit behind (and can parent) their
+    // contents, then highlights so they sit behind everything else
+    let mut ordered: Vec<&DetectedShape> = shapes.iter().collect();
+    ordered.sort_by_key(|s| {
+        let is_container = s.shape_type == ShapeType::Swimlane
+            || (bpmn_mode && matches!(s.properties.bpmn, Some(BpmnElement::Pool | BpmnElement::Lane)));
+        (!is_container, s.shape_type != ShapeType::Highlight)
+    });
+
+    for shape in ordered {
         // Skip connector shapes (handled separately)
         if matches!(
             shape.shape_type,
@@ -244,28 +759,90 @@ fn write_shapes(
             continue;
         }
 
+        // Highlights either get their own cell or fill the cells they cover
+        let highlight = extras.highlights.get(&shape.id);
+        if shape.shape_type == ShapeType::Highlight && highlight.is_none() {
+            continue;
+        }
+
         let current_id = cell_id.to_string();
-        id_map.insert(shape.id.clone(), current_id.clone());
 
-        // Find label text for this shape
-        let label = find_label_for_shape(shape, text_regions);
+        // Find label text for this shape; highlighted text stays a text cell
+        let label = match (&shape.label, highlight) {
+            (Some(label), _) => label.clone(),
+            (None, Some(_)) => String::new(),
+            (None, None) => find_label_for_shape(shape, text_regions),
+        };
 
         // Get style based on shape type
-        let style = get_style_for_shape(&shape.shape_type);
+        let mode_style = match diagram_type {
+            "bpmn" => get_bpmn_style_for_shape(shape),
+            "wireframe" => get_wireframe_style_for_shape(shape),
+            _ => None,
+        };
+        let freehand = extras.freehand_styles.get(&shape.id);
+        let style = match (shape.properties.icon, mode_style.clone()) {
+            _ if highlight.is_some() => highlight_style(highlight.copied().unwrap_or(DEFAULT_HIGHLIGHT)),
+            (Some(kind), _) => icon_styles.style_for(kind),
+            (None, Some(style)) => style,
+            (None, None) if freehand.is_some() => freehand.cloned().unwrap_or_default(),
+            (None, None) if shape.shape_type == ShapeType::Swimlane => get_swimlane_style(shape),
+            (None, None) => get_style_for_shape(&shape.shape_type),
+        };
+        // Icon stencils keep their own colours
+        let style = if shape.properties.icon.is_some() {
+            style
+        } else {
+            extras.inked_style(&shape.id, style)
+        };
+        let style = match extras.highlight_fills.get(&shape.id) {
+            Some(color) => set_style_key(&style, "fillColor", &color.rgb_hex()),
+            None => style,
+        };
+
+        // BPMN events and checkboxes keep their drawn (fixed aspect) size
+        let is_event = bpmn_mode
+            && matches!(
+                shape.properties.bpmn,
+                Some(BpmnElement::StartEvent | BpmnElement::IntermediateEvent | BpmnElement::EndEvent)
+            );
+        let is_checkbox = diagram_type == "wireframe"
+            && shape.properties.wireframe == Some(WireframeElement::Checkbox);
+        let keeps_size = shape.properties.icon.is_none() && (is_event || is_checkbox);
+        let (width, height) = if keeps_size {
+            let min_size = if is_event { 30.0 } else { 16.0 };
+            let size = shape.bounds.width.max(shape.bounds.height).max(min_size);
+            (size, size)
+        } else if highlight.is_some() || (freehand.is_some() && shape.properties.icon.is_none()) {
+            // Highlights and freehand stencils cover exactly the drawn ink
+            (shape.bounds.width.max(1.0), shape.bounds.height.max(1.0))
+        } else {
+            (shape.bounds.width.max(80.0), shape.bounds.height.max(40.0))
+        };
+
+        // Children of a container use geometry relative to it
+        let parent = shape.parent_id.as_ref().and_then(|pid| {
+            let parent_cell = id_map.get(pid)?;
+            let parent_shape = shapes.iter().find(|s| &s.id == pid)?;
+            Some((parent_cell.clone(), parent_shape.bounds.x, parent_shape.bounds.y))
+        });
+        let (parent_cell, offset_x, offset_y) =
+            parent.unwrap_or_else(|| (extras.layer_cell(&shape.id).to_string(), 0.0, 0.0));
 
         // Write cell with geometry
         write_shape_cell(
             writer,
             &current_id,
-            "1",
+            &parent_cell,
             &label,
             &style,
-            shape.bounds.x,
-            shape.bounds.y,
-            shape.bounds.width.max(80.0),
-            shape.bounds.height.max(40.0),
+            shape.bounds.x - offset_x,
+            shape.bounds.y - offset_y,
+            width,
+            height,
         )?;
 
+        id_map.insert(shape.id.clone(), current_id);
         *cell_id += 1;
     }
 
@@ -314,12 +891,109 @@ fn write_shape_cell(
         .map_err(|e| e.to_string())
 }
 
+/// Layer cell for an annotation: that of the shape it points at or its leader
+/// line, else of the first stroke under the text, else the bottom layer
+fn annotation_layer_cell(
+    annotation: &Annotation,
+    extras: &ShapeExtras,
+    strokes: &[Stroke],
+    layer_cell_ids: &HashMap<&str, String>,
+) -> String {
+    let b = &annotation.bounds;
+    annotation
+        .target_shape_id
+        .iter()
+        .chain(&annotation.leader_shape_id)
+        .find_map(|id| extras.layer_cells.get(id).cloned())
+        .or_else(|| {
+            strokes
+                .iter()
+                .filter(|s| {
+                    crate::selection::stroke_bounds(s).is_some_and(|(x0, y0, x1, y1)| {
+                        x0 <= b.x + b.width && x1 >= b.x && y0 <= b.y + b.height && y1 >= b.y
+                    })
+                })
+                .find_map(|s| layer_cell_ids.get(s.layer_id.as_str()).cloned())
+        })
+        .unwrap_or_else(|| "1".to_string())
+}
+
+/// Write annotation cells: plain text, or a note with a dashed link to its target
+fn write_annotations(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    annotations: &[Annotation],
+    shape_id_map: &std::collections::HashMap<String, String>,
+    layer_cells: &HashMap<String, String>,
+    cell_id: &mut i32,
+) -> Result<(), String> {
+    for annotation in annotations {
+        let layer = layer_cells.get(&annotation.text_id).map_or("1", String::as_str);
+        let note_id = cell_id.to_string();
+        *cell_id += 1;
+
+        let target = annotation
+            .target_shape_id
+            .as_ref()
+            .and_then(|id| shape_id_map.get(id));
+        let font_size = format!("fontSize={:.0};", annotation.font_size);
+        let b = &annotation.bounds;
+
+        if let Some(target) = target {
+            let style = format!("{}{}", StylePresets::note(), font_size);
+            write_shape_cell(
+                writer,
+                &note_id,
+                layer,
+                &annotation.text,
+                &style,
+                b.x,
+                b.y,
+                b.width.max(80.0),
+                b.height.max(30.0),
+            )?;
+
+            let link_id = cell_id.to_string();
+            *cell_id += 1;
+            write_edge_cell_with_points(
+                writer,
+                &link_id,
+                layer,
+                "",
+                StylePresets::note_link(),
+                Some(&note_id),
+                Some(target),
+                None,
+                None,
+                &[],
+            )?;
+        } else {
+            let style = format!("{}{}", StylePresets::text(), font_size);
+            write_shape_cell(
+                writer,
+                &note_id,
+                layer,
+                &annotation.text,
+                &style,
+                b.x,
+                b.y,
+                b.width,
+                b.height,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Write connector/edge cells
 fn write_connectors(
     writer: &mut Writer<Cursor<Vec<u8>>>,
     shapes: &[DetectedShape],
     shape_id_map: &std::collections::HashMap<String, String>,
     cell_id: &mut i32,
+    bpmn_mode: bool,
+    leader_ids: &[String],
+    extras: &ShapeExtras,
 ) -> Result<(), String> {
     use crate::shapes::ShapeType;
 
@@ -331,11 +1005,21 @@ fn write_connectors(
             continue;
         }
 
+        // Annotation leader lines are written as note links instead
+        if leader_ids.contains(&shape.id) {
+            continue;
+        }
+
         // Find source and target based on proximity
         let (source_id, target_id) = find_connection_endpoints(shape, shapes, shape_id_map);
 
         let current_id = cell_id.to_string();
-        let style = get_connector_style(&shape.shape_type);
+        let style = if bpmn_mode && shape.shape_type != ShapeType::Line {
+            StylePresets::bpmn_sequence_flow().to_string()
+        } else {
+            get_connector_style(&shape.shape_type)
+        };
+        let style = extras.inked_style(&shape.id, style);
 
         // Get start and end points for standalone lines (not connected to shapes)
         let start_point = shape.properties.start_point;
@@ -344,13 +1028,14 @@ fn write_connectors(
         write_edge_cell_with_points(
             writer,
             &current_id,
-            "1",
+            extras.layer_cell(&shape.id),
             "",
             &style,
             source_id.as_deref(),
             target_id.as_deref(),
             start_point,
             end_point,
+            &[],
         )?;
 
         *cell_id += 1;
@@ -360,6 +1045,7 @@ fn write_connectors(
 }
 
 /// Write an edge cell with explicit start/end points for standalone lines
+/// and optional bend points
 fn write_edge_cell_with_points(
     writer: &mut Writer<Cursor<Vec<u8>>>,
     id: &str,
@@ -370,6 +1056,7 @@ fn write_edge_cell_with_points(
     target: Option<&str>,
     start_point: Option<(f64, f64)>,
     end_point: Option<(f64, f64)>,
+    waypoints: &[(f64, f64)],
 ) -> Result<(), String> {
     let mut cell = BytesStart::new("mxCell");
     cell.push_attribute(("id", id));
@@ -394,60 +1081,43 @@ fn write_edge_cell_with_points(
         .write_event(Event::Start(cell))
         .map_err(|e| e.to_string())?;
 
-    // For standalone lines (no source/target), use absolute geometry with points
+    // For standalone lines (no source/target), use absolute end points
     let is_standalone = source.map_or(true, |s| s.is_empty()) && target.map_or(true, |t| t.is_empty());
-    
-    if is_standalone {
-        if let (Some((sx, sy)), Some((ex, ey))) = (start_point, end_point) {
-            // Use absolute positioning with mxPoint elements
-            let mut geometry = BytesStart::new("mxGeometry");
-            geometry.push_attribute(("relative", "1"));
-            geometry.push_attribute(("as", "geometry"));
-            writer
-                .write_event(Event::Start(geometry))
-                .map_err(|e| e.to_string())?;
-            
-            // Source point (absolute)
-            let sx_str = format!("{:.0}", sx);
-            let sy_str = format!("{:.0}", sy);
-            let mut source_pt = BytesStart::new("mxPoint");
-            source_pt.push_attribute(("x", sx_str.as_str()));
-            source_pt.push_attribute(("y", sy_str.as_str()));
-            source_pt.push_attribute(("as", "sourcePoint"));
-            writer
-                .write_event(Event::Empty(source_pt))
-                .map_err(|e| e.to_string())?;
-            
-            // Target point (absolute)
-            let ex_str = format!("{:.0}", ex);
-            let ey_str = format!("{:.0}", ey);
-            let mut target_pt = BytesStart::new("mxPoint");
-            target_pt.push_attribute(("x", ex_str.as_str()));
-            target_pt.push_attribute(("y", ey_str.as_str()));
-            target_pt.push_attribute(("as", "targetPoint"));
-            writer
-                .write_event(Event::Empty(target_pt))
-                .map_err(|e| e.to_string())?;
-            
+    let ends = if is_standalone { start_point.zip(end_point) } else { None };
+
+    let mut geometry = BytesStart::new("mxGeometry");
+    geometry.push_attribute(("relative", "1"));
+    geometry.push_attribute(("as", "geometry"));
+    if ends.is_none() && waypoints.is_empty() {
+        writer
+            .write_event(Event::Empty(geometry))
+            .map_err(|e| e.to_string())?;
+    } else {
+        writer
+            .write_event(Event::Start(geometry))
+            .map_err(|e| e.to_string())?;
+
+        if let Some((start, end)) = ends {
+            write_point(writer, start, Some("sourcePoint"))?;
+            write_point(writer, end, Some("targetPoint"))?;
+        }
+
+        if !waypoints.is_empty() {
+            let mut array = BytesStart::new("Array");
+            array.push_attribute(("as", "points"));
             writer
-                .write_event(Event::End(BytesEnd::new("mxGeometry")))
+                .write_event(Event::Start(array))
                 .map_err(|e| e.to_string())?;
-        } else {
-            // Fallback: relative geometry
-            let mut geometry = BytesStart::new("mxGeometry");
-            geometry.push_attribute(("relative", "1"));
-            geometry.push_attribute(("as", "geometry"));
+            for point in waypoints {
+                write_point(writer, *point, None)?;
+            }
             writer
-                .write_event(Event::Empty(geometry))
+                .write_event(Event::End(BytesEnd::new("Array")))
                 .map_err(|e| e.to_string())?;
         }
-    } else {
-        // Connected edge: use relative geometry
-        let mut geometry = BytesStart::new("mxGeometry");
-        geometry.push_attribute(("relative", "1"));
-        geometry.push_attribute(("as", "geometry"));
+
         writer
-            .write_event(Event::Empty(geometry))
+            .write_event(Event::End(BytesEnd::new("mxGeometry")))
             .map_err(|e| e.to_string())?;
     }
 
@@ -456,22 +1126,23 @@ fn write_edge_cell_with_points(
         .map_err(|e| e.to_string())
 }
 
-/// Write an edge cell (legacy - kept for compatibility)
-fn write_edge_cell(
+/// Write an absolute mxPoint, named by `as` when it is an edge end
+fn write_point(
     writer: &mut Writer<Cursor<Vec<u8>>>,
-    id: &str,
-    parent: &str,
-    value: &str,
-    style: &str,
-    source: &str,
-    target: &str,
+    (x, y): (f64, f64),
+    role: Option<&str>,
 ) -> Result<(), String> {
-    write_edge_cell_with_points(
-        writer, id, parent, value, style,
-        if source.is_empty() { None } else { Some(source) },
-        if target.is_empty() { None } else { Some(target) },
-        None, None,
-    )
+    let x_str = format!("{:.0}", x);
+    let y_str = format!("{:.0}", y);
+    let mut point = BytesStart::new("mxPoint");
+    point.push_attribute(("x", x_str.as_str()));
+    point.push_attribute(("y", y_str.as_str()));
+    if let Some(role) = role {
+        point.push_attribute(("as", role));
+    }
+    writer
+        .write_event(Event::Empty(point))
+        .map_err(|e| e.to_string())
 }
 
 /// Find label text that belongs to a shape
@@ -479,16 +1150,7 @@ fn find_label_for_shape(shape: &DetectedShape, text_regions: &[TextRegion]) -> S
     let mut labels = Vec::new();
 
     for text in text_regions {
-        // Check if text center is within shape bounds (with some margin)
-        let text_cx = text.bounds.x + text.bounds.width / 2.0;
-        let text_cy = text.bounds.y + text.bounds.height / 2.0;
-
-        let margin = 20.0;
-        if text_cx >= shape.bounds.x - margin
-            && text_cx <= shape.bounds.x + shape.bounds.width + margin
-            && text_cy >= shape.bounds.y - margin
-            && text_cy <= shape.bounds.y + shape.bounds.height + margin
-        {
+        if crate::shapes::text_labels_shape(shape, text, LABEL_MARGIN) {
             labels.push(text.text.clone());
         }
     }
@@ -512,6 +1174,45 @@ fn get_style_for_shape(shape_type: &crate::shapes::ShapeType) -> String {
     }
 }
 
+/// Get swimlane container style; wide lanes carry their title on the left
+fn get_swimlane_style(shape: &DetectedShape) -> String {
+    if shape.bounds.width >= shape.bounds.height {
+        StylePresets::swimlane_horizontal().to_string()
+    } else {
+        StylePresets::swimlane_vertical().to_string()
+    }
+}
+
+/// Get BPMN style string for a shape, if it has a BPMN role
+fn get_bpmn_style_for_shape(shape: &DetectedShape) -> Option<String> {
+    use crate::shapes::{BpmnElement, ShapeType};
+    let style = match &shape.properties.bpmn {
+        Some(BpmnElement::StartEvent) => StylePresets::bpmn_start_event(),
+        Some(BpmnElement::IntermediateEvent) => StylePresets::bpmn_intermediate_event(),
+        Some(BpmnElement::EndEvent) => StylePresets::bpmn_end_event(),
+        Some(BpmnElement::ExclusiveGateway) => StylePresets::bpmn_exclusive_gateway(),
+        Some(BpmnElement::ParallelGateway) => StylePresets::bpmn_parallel_gateway(),
+        Some(BpmnElement::Task) => StylePresets::bpmn_task(),
+        Some(BpmnElement::Pool) => StylePresets::bpmn_pool(),
+        Some(BpmnElement::Lane) => StylePresets::bpmn_lane(),
+        None if shape.shape_type == ShapeType::Diamond => StylePresets::bpmn_blank_gateway(),
+        None => return None,
+    };
+    Some(style.to_string())
+}
+
+/// Get mockup style string for a shape, if it has a wireframe role
+fn get_wireframe_style_for_shape(shape: &DetectedShape) -> Option<String> {
+    use crate::wireframe::WireframeElement;
+    let style = match shape.properties.wireframe? {
+        WireframeElement::ImagePlaceholder => StylePresets::mockup_image(),
+        WireframeElement::Button => StylePresets::mockup_button(),
+        WireframeElement::Input => StylePresets::mockup_input(),
+        WireframeElement::Checkbox => StylePresets::mockup_checkbox(),
+    };
+    Some(style.to_string())
+}
+
 /// Get connector style string
 fn get_connector_style(shape_type: &crate::shapes::ShapeType) -> String {
     use crate::shapes::ShapeType;
@@ -528,7 +1229,7 @@ fn find_connection_endpoints(
     all_shapes: &[DetectedShape],
     id_map: &std::collections::HashMap<String, String>,
 ) -> (Option<String>, Option<String>) {
-    use crate::shapes::ShapeType;
+    use crate::shapes::{BpmnElement, ShapeType};
 
     let start = connector.properties.start_point;
     let end = connector.properties.end_point;
@@ -545,6 +1246,13 @@ fn find_connection_endpoints(
             continue;
         }
 
+        // Pools, lanes and highlights contain the real endpoints
+        if matches!(shape.shape_type, ShapeType::Swimlane | ShapeType::Highlight)
+            || matches!(shape.properties.bpmn, Some(BpmnElement::Pool | BpmnElement::Lane))
+        {
+            continue;
+        }
+
         if let Some(mapped_id) = id_map.get(&shape.id) {
             if let Some((sx, sy)) = start {
                 if point_near_shape(sx, sy, shape, 30.0) && source_id.is_none() {
@@ -635,6 +1343,14 @@ pub fn generate_xml_from_structure(
     write_cell(&mut writer, "0", "", "")?;
     write_cell_with_parent(&mut writer, "1", "0")?;
 
+    let cell_id = |node_id: &str| {
+        structure
+            .nodes
+            .iter()
+            .position(|n| n.id == node_id)
+            .map(|i| format!("{}", i + 2))
+    };
+
     // Nodes
     for (i, node) in structure.nodes.iter().enumerate() {
         let id = format!("{}", i + 2);
@@ -644,14 +1360,27 @@ pub fn generate_xml_from_structure(
             node.style.clone()
         };
 
+        // Children of a container are positioned relative to it
+        let container = node
+            .parent
+            .as_deref()
+            .and_then(|p| structure.nodes.iter().position(|n| n.id == p));
+        let (parent, x, y) = match container {
+            Some(j) => {
+                let c = &structure.nodes[j];
+                (format!("{}", j + 2), node.x - c.x, node.y - c.y)
+            }
+            None => ("1".to_string(), node.x, node.y),
+        };
+
         write_shape_cell(
             &mut writer,
             &id,
-            "1",
+            &parent,
             &node.label,
             &style,
-            node.x,
-            node.y,
+            x,
+            y,
             node.width,
             node.height,
         )?;
@@ -668,27 +1397,20 @@ pub fn generate_xml_from_structure(
         };
 
         // Map source/target IDs
-        let source_idx = structure
-            .nodes
-            .iter()
-            .position(|n| n.id == edge.source)
-            .map(|i| format!("{}", i + 2))
-            .unwrap_or_default();
-        let target_idx = structure
-            .nodes
-            .iter()
-            .position(|n| n.id == edge.target)
-            .map(|i| format!("{}", i + 2))
-            .unwrap_or_default();
+        let source_idx = cell_id(&edge.source);
+        let target_idx = cell_id(&edge.target);
 
-        write_edge_cell(
+        write_edge_cell_with_points(
             &mut writer,
             &id,
             "1",
             edge.label.as_deref().unwrap_or(""),
             &style,
-            &source_idx,
-            &target_idx,
+            source_idx.as_deref(),
+            target_idx.as_deref(),
+            edge.source_point,
+            edge.target_point,
+            &edge.waypoints,
         )?;
     }
 
@@ -716,6 +1438,8 @@ fn get_style_for_type(shape_type: &str) -> String {
         "process" | "rectangle" => StylePresets::rounded_rectangle().to_string(),
         "decision" | "diamond" => StylePresets::diamond().to_string(),
         "terminator" | "circle" | "ellipse" => StylePresets::terminator().to_string(),
+        "note" => StylePresets::note().to_string(),
+        "text" => StylePresets::text().to_string(),
         "data" | "triangle" => {
             "shape=parallelogram;whiteSpace=wrap;html=1;fillColor=#ffe6cc;strokeColor=#d79b00;"
                 .to_string()
@@ -727,20 +1451,18 @@ fn get_style_for_type(shape_type: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shapes::test_stroke;
 
     #[test]
     fn test_generate_empty_xml() {
         let shapes = vec![];
         let text_regions = vec![];
         let options = ExportOptions {
-            filename: "test".to_string(),
             include_grid: true,
-            page_width: 800.0,
-            page_height: 600.0,
-            theme: "light".to_string(),
+            ..crate::test_export_options()
         };
 
-        let result = generate_xml(&shapes, &text_regions, &options);
+        let result = generate_xml(&shapes, &text_regions, &BoardContext::default(), &options);
         assert!(result.is_ok());
         let xml = result.unwrap();
         assert!(xml.contains("mxfile"));
@@ -768,6 +1490,7 @@ mod tests {
                 width: 100.0,
                 height: 50.0,
                 style: "".to_string(),
+                parent: None,
             }],
             edges: vec![],
             metadata: DiagramMetadata::default(),
@@ -776,4 +1499,195 @@ mod tests {
         let json = serde_json::to_string(&structure);
         assert!(json.is_ok());
     }
+
+    #[test]
+    fn test_bpmn_export_styles() {
+        use crate::shapes::{test_shape, BpmnElement, ShapeType};
+
+        let mut gateway = test_shape("gw", ShapeType::Diamond, (0.0, 0.0), (60.0, 60.0));
+        gateway.properties.bpmn = Some(BpmnElement::ParallelGateway);
+        let shapes = vec![gateway];
+        let options = ExportOptions {
+            diagram_type: Some("bpmn".to_string()),
+            ..crate::test_export_options()
+        };
+
+        let xml = generate_xml(&shapes, &[], &BoardContext::default(), &options).unwrap();
+        assert!(xml.contains("mxgraph.bpmn.gateway2"));
+        assert!(xml.contains("gwType=parallel"));
+    }
+
+    #[test]
+    fn test_swimlane_children_use_relative_geometry() {
+        use crate::shapes::{test_shape, ShapeType};
+
+        let mut task = test_shape("task", ShapeType::Rectangle, (300.0, 250.0), (420.0, 310.0));
+        task.parent_id = Some("lane".to_string());
+        let mut lane = test_shape("lane", ShapeType::Swimlane, (0.0, 200.0), (1000.0, 400.0));
+        lane.label = Some("Sales".to_string());
+
+        let options = crate::test_export_options();
+
+        let xml = generate_xml(&[task, lane], &[], &BoardContext::default(), &options).unwrap();
+        assert!(xml.contains(r#"value="Sales" style="swimlane;horizontal=0;"#));
+        assert!(xml.contains(r#"vertex="1" parent="2"><mxGeometry x="300" y="50""#));
+    }
+
+    #[test]
+    fn test_free_text_exported_as_text_cell() {
+        use crate::ocr::TextBounds;
+
+        let text_regions = vec![TextRegion {
+            id: "t".to_string(),
+            text: "remember to add caching".to_string(),
+            bounds: TextBounds { x: 400.0, y: 50.0, width: 160.0, height: 24.0 },
+            confidence: 0.8,
+            font_size_estimate: 18.0,
+        }];
+        let options = crate::test_export_options();
+
+        let xml = generate_xml(&[], &text_regions, &BoardContext::default(), &options).unwrap();
+        assert!(xml.contains(r#"value="remember to add caching" style="text;"#));
+        assert!(xml.contains("fontSize=18;"));
+    }
+
+    #[test]
+    fn test_layers_exported_as_layer_cells() {
+        use crate::shapes::{test_shape, ShapeType};
+
+        let shape = |id: &str, x: f64| test_shape(id, ShapeType::Rectangle, (x, 0.0), (x + 100.0, 60.0));
+        let layers = vec![
+            Layer { id: "base".to_string(), name: "Diagram".to_string(), visible: true, locked: true },
+            Layer { id: "notes".to_string(), name: "Notes".to_string(), visible: false, locked: false },
+        ];
+        let board = BoardContext {
+            layers: &layers,
+            shape_layers: HashMap::from([("b".to_string(), "notes".to_string())]),
+            ..BoardContext::default()
+        };
+        let options = crate::test_export_options();
+
+        let xml = generate_xml(&[shape("a", 0.0), shape("b", 200.0)], &[], &board, &options).unwrap();
+        assert!(xml.contains(r#"<mxCell id="1" value="Diagram" style="locked=1;" parent="0"/>"#));
+        assert!(xml.contains(r#"<mxCell id="2" value="Notes" parent="0" visible="0"/>"#));
+        assert!(xml.contains(r#"vertex="1" parent="1"><mxGeometry x="0""#));
+        assert!(xml.contains(r#"vertex="1" parent="2"><mxGeometry x="200""#));
+
+        // Free text goes on the layer of the ink under it
+        let ink = Stroke {
+            layer_id: "notes".to_string(),
+            ..test_stroke("s1", vec![
+                crate::Point { x: 500.0, y: 310.0, pressure: None, timestamp: 0 },
+                crate::Point { x: 580.0, y: 310.0, pressure: None, timestamp: 0 },
+            ])
+        };
+        let text = |id: &str, x: f64| TextRegion {
+            id: id.to_string(),
+            text: id.to_string(),
+            bounds: crate::ocr::TextBounds { x, y: 300.0, width: 80.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 18.0,
+        };
+        let strokes = [ink];
+        let board = BoardContext { strokes: &strokes, ..board };
+        let xml = generate_xml(&[], &[text("inked", 500.0), text("bare", 100.0)], &board, &options).unwrap();
+        assert!(xml.contains(r#"vertex="1" parent="2"><mxGeometry x="500""#));
+        assert!(xml.contains(r#"vertex="1" parent="1"><mxGeometry x="100""#));
+    }
+
+    #[test]
+    fn test_freeform_shape_exported_as_freehand_stencil() {
+        use crate::shapes::{test_shape, ShapeType};
+        use crate::Point;
+
+        let points: Vec<Point> = (0..=20)
+            .map(|i| {
+                let t = i as f64 / 20.0 * std::f64::consts::PI;
+                Point { x: 10.0 + 30.0 * t, y: 50.0 + 20.0 * t.sin(), pressure: None, timestamp: 0 }
+            })
+            .collect();
+        let stroke = Stroke {
+            width: 3.0,
+            curves: crate::canvas::fit_bezier(&points, crate::canvas::CURVE_TOLERANCE),
+            ..test_stroke("s1", points)
+        };
+        let shape = DetectedShape {
+            stroke_ids: vec!["s1".to_string()],
+            ..test_shape("f", ShapeType::Freeform, (10.0, 50.0), (104.2, 70.0))
+        };
+        let options = crate::test_export_options();
+
+        let strokes = [stroke];
+        let board = BoardContext { strokes: &strokes, ..BoardContext::default() };
+        let xml = generate_xml(std::slice::from_ref(&shape), &[], &board, &options).unwrap();
+        assert!(xml.contains("shape=stencil("));
+        assert!(xml.contains("fillColor=none;strokeColor=#000000;strokeWidth=3.0;"));
+        assert!(xml.contains(r#"width="94.2" height="20""#));
+
+        // Without the strokes the old placeholder style is kept
+        let xml = generate_xml(&[shape], &[], &BoardContext::default(), &options).unwrap();
+        assert!(!xml.contains("shape=stencil("));
+    }
+
+    #[test]
+    fn test_ink_style_carried_into_cells() {
+        use crate::shapes::{test_shape, ShapeType};
+        use crate::Point;
+
+        let stroke = Stroke {
+            color: "#ff0000".to_string(),
+            width: 6.0,
+            ..test_stroke("s1", vec![
+                Point { x: 0.0, y: 0.0, pressure: None, timestamp: 0 },
+                Point { x: 100.0, y: 60.0, pressure: None, timestamp: 0 },
+            ])
+        };
+        let shape = DetectedShape {
+            stroke_ids: vec!["s1".to_string()],
+            ..test_shape("r", ShapeType::Rectangle, (0.0, 0.0), (100.0, 60.0))
+        };
+        let strokes = [stroke];
+        let board = BoardContext { strokes: &strokes, ..BoardContext::default() };
+        let export = |ink_style: InkStyle| {
+            let options = ExportOptions {
+                ink_style,
+                ..crate::test_export_options()
+            };
+            generate_xml(std::slice::from_ref(&shape), &[], &board, &options).unwrap()
+        };
+
+        assert!(export(InkStyle::Theme).contains("fillColor=#dae8fc;strokeColor=#6c8ebf;"));
+        assert!(export(InkStyle::Palette).contains("fillColor=#f8cecc;strokeColor=#b85450;strokeWidth=6.0;"));
+        assert!(export(InkStyle::Preserve).contains("fillColor=none;strokeColor=#ff0000;strokeWidth=6.0;"));
+    }
+
+    #[test]
+    fn test_highlights_export_as_regions_or_fills() {
+        use crate::shapes::{test_shape, ShapeType};
+
+        let make = |id: &str, shape_type: ShapeType, x: f64, width: f64| {
+            test_shape(id, shape_type, (x, 0.0), (x + width, 60.0))
+        };
+        let shapes = vec![
+            make("box", ShapeType::Rectangle, 0.0, 100.0),
+            make("mark", ShapeType::Highlight, -10.0, 120.0),
+        ];
+        let export = |highlight_style: HighlightStyle| {
+            let options = ExportOptions {
+                highlight_style,
+                ..crate::test_export_options()
+            };
+            generate_xml(&shapes, &[], &BoardContext::default(), &options).unwrap()
+        };
+
+        // The region is written first, so it sits behind the box
+        let xml = export(HighlightStyle::Region);
+        let region = xml.find("fillColor=#ffeb3b;strokeColor=none;opacity=40;").unwrap();
+        assert!(region < xml.find("fillColor=#dae8fc").unwrap());
+        assert!(xml.contains(r#"x="-10" y="0" width="120" height="60""#));
+
+        let xml = export(HighlightStyle::Fill);
+        assert!(!xml.contains("opacity=40"));
+        assert!(xml.contains("fillColor=#ffeb3b;strokeColor=#6c8ebf;"));
+    }
 }
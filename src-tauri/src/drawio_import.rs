@@ -0,0 +1,540 @@
+//! Draw.io import module
+//!
+//! Reads `.drawio` files and mxGraph `.xml` documents back into a
+//! [`DiagramStructure`] so exported diagrams can be reopened. Both plain and
+//! compressed `<diagram>` contents are read; only the first page is imported.
+//! Geometry is converted to absolute coordinates, with container cells kept
+//! as node parents.
+
+use crate::drawio::{self, DiagramEdge, DiagramMetadata, DiagramNode, DiagramStructure};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// A cell as written in the file, before parents and references are resolved
+#[derive(Debug, Default)]
+struct RawCell {
+    id: String,
+    parent: Option<String>,
+    value: String,
+    style: String,
+    vertex: bool,
+    edge: bool,
+    source: Option<String>,
+    target: Option<String>,
+    geometry: Option<RawGeometry>,
+}
+
+/// An `mxGeometry`, in its parent's coordinates
+#[derive(Debug, Default)]
+struct RawGeometry {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    relative: bool,
+    points: Vec<(f64, f64)>,
+    source_point: Option<(f64, f64)>,
+    target_point: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellKind {
+    /// The parentless cell everything hangs off
+    Root,
+    /// A child of the root that is neither vertex nor edge
+    Layer,
+    Vertex,
+    Edge,
+}
+
+/// What the file says about itself and where its first page's cells are
+#[derive(Debug, Default)]
+struct FileInfo {
+    title: Option<String>,
+    modified: Option<String>,
+    version: Option<String>,
+    /// The page holds an `mxGraphModel` element rather than compressed text
+    inline: bool,
+    compressed: String,
+}
+
+/// Parse a draw.io file into a diagram structure
+pub fn parse_drawio(xml: &str) -> Result<DiagramStructure, String> {
+    let info = read_file_info(xml)?;
+    let cells = if info.inline {
+        read_cells(xml)?
+    } else if !info.compressed.trim().is_empty() {
+        read_cells(&drawio::decompress(&info.compressed)?)?
+    } else {
+        return Err("The draw.io file has no diagram content".to_string());
+    };
+
+    let (nodes, edges) = build_cells(&cells)?;
+    Ok(DiagramStructure {
+        diagram_type: infer_diagram_type(&nodes, &edges),
+        nodes,
+        edges,
+        metadata: DiagramMetadata {
+            title: info.title,
+            author: None,
+            created_at: info.modified,
+            version: info.version,
+        },
+    })
+}
+
+/// Read the file's attributes and the first page's name and contents
+fn read_file_info(xml: &str) -> Result<FileInfo, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut info = FileInfo::default();
+    let mut in_diagram = false;
+    let mut saw_root = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid draw.io XML at byte {}: {}", reader.buffer_position(), e))?;
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+                match e.local_name().as_ref() {
+                    b"mxfile" => {
+                        saw_root = true;
+                        let attrs = attributes(e)?;
+                        info.modified = attrs.get("modified").cloned();
+                        info.version = attrs.get("version").cloned();
+                    }
+                    b"diagram" => {
+                        saw_root = true;
+                        info.title = attributes(e)?.get("name").cloned();
+                        if empty {
+                            break;
+                        }
+                        in_diagram = true;
+                    }
+                    // A bare model, or a page's uncompressed model
+                    b"mxGraphModel" => {
+                        saw_root = true;
+                        info.inline = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(ref t) if in_diagram => {
+                info.compressed.push_str(&t.unescape().map_err(|e| e.to_string())?);
+            }
+            Event::End(ref e) if e.local_name().as_ref() == b"diagram" => break,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !saw_root {
+        return Err("Not a draw.io file: missing <mxfile> or <mxGraphModel>".to_string());
+    }
+    Ok(info)
+}
+
+/// Collect the cells of the first model in a document
+fn read_cells(xml: &str) -> Result<Vec<RawCell>, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut cells: Vec<RawCell> = Vec::new();
+    // Id and label of an enclosing <object> or <UserObject>
+    let mut wrapper: Option<(Option<String>, Option<String>)> = None;
+    let mut in_cell = false;
+    let mut in_geometry = false;
+    let mut in_points = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid draw.io XML at byte {}: {}", reader.buffer_position(), e))?;
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+                let attrs = attributes(e)?;
+                match e.local_name().as_ref() {
+                    b"object" | b"UserObject" if !empty => {
+                        wrapper = Some((attrs.get("id").cloned(), attrs.get("label").cloned()));
+                    }
+                    b"mxCell" => {
+                        let (wrapper_id, wrapper_label) = wrapper.clone().unwrap_or_default();
+                        let id = attrs
+                            .get("id")
+                            .cloned()
+                            .or(wrapper_id)
+                            .ok_or_else(|| format!("Cell {} has no id", cells.len() + 1))?;
+                        cells.push(RawCell {
+                            id,
+                            parent: attrs.get("parent").cloned(),
+                            value: attrs.get("value").cloned().or(wrapper_label).unwrap_or_default(),
+                            style: attrs.get("style").cloned().unwrap_or_default(),
+                            vertex: attrs.get("vertex").is_some_and(|v| v == "1"),
+                            edge: attrs.get("edge").is_some_and(|v| v == "1"),
+                            source: attrs.get("source").cloned(),
+                            target: attrs.get("target").cloned(),
+                            geometry: None,
+                        });
+                        in_cell = !empty;
+                    }
+                    b"mxGeometry" if in_cell && attrs.get("as").is_none_or(|a| a == "geometry") => {
+                        let geometry = RawGeometry {
+                            x: number(&attrs, "x"),
+                            y: number(&attrs, "y"),
+                            width: number(&attrs, "width"),
+                            height: number(&attrs, "height"),
+                            relative: attrs.get("relative").is_some_and(|v| v == "1"),
+                            ..Default::default()
+                        };
+                        if let Some(cell) = cells.last_mut() {
+                            cell.geometry = Some(geometry);
+                        }
+                        in_geometry = !empty;
+                    }
+                    b"Array" if in_geometry && attrs.get("as").is_some_and(|a| a == "points") => {
+                        in_points = !empty;
+                    }
+                    b"mxPoint" if in_geometry => {
+                        let point = (number(&attrs, "x"), number(&attrs, "y"));
+                        if let Some(geometry) = cells.last_mut().and_then(|c| c.geometry.as_mut()) {
+                            match attrs.get("as").map(String::as_str) {
+                                None if in_points => geometry.points.push(point),
+                                Some("sourcePoint") => geometry.source_point = Some(point),
+                                Some("targetPoint") => geometry.target_point = Some(point),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(ref e) => match e.local_name().as_ref() {
+                b"object" | b"UserObject" => wrapper = None,
+                b"mxCell" => in_cell = false,
+                b"mxGeometry" => in_geometry = false,
+                b"Array" => in_points = false,
+                // Later pages of an uncompressed file are not imported
+                b"diagram" | b"mxGraphModel" => break,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(cells)
+}
+
+/// Resolve parents and references into nodes and edges with absolute geometry
+fn build_cells(cells: &[RawCell]) -> Result<(Vec<DiagramNode>, Vec<DiagramEdge>), String> {
+    let mut index: HashMap<&str, usize> = HashMap::with_capacity(cells.len());
+    for (i, cell) in cells.iter().enumerate() {
+        if index.insert(cell.id.as_str(), i).is_some() {
+            return Err(format!("Duplicate cell id {}", cell.id));
+        }
+    }
+
+    let parent_of = |cell: &RawCell| -> Result<Option<usize>, String> {
+        match cell.parent.as_deref() {
+            Some(parent) => index
+                .get(parent)
+                .copied()
+                .map(Some)
+                .ok_or_else(|| format!("Cell {} has unknown parent {}", cell.id, parent)),
+            None => Ok(None),
+        }
+    };
+
+    let mut kinds = Vec::with_capacity(cells.len());
+    for cell in cells {
+        let kind = match parent_of(cell)? {
+            None => CellKind::Root,
+            Some(_) if cell.vertex => CellKind::Vertex,
+            Some(_) if cell.edge => CellKind::Edge,
+            Some(p) if cells[p].parent.is_none() => CellKind::Layer,
+            Some(_) => return Err(format!("Unsupported cell {}: neither a shape nor a connector", cell.id)),
+        };
+        kinds.push(kind);
+    }
+
+    // Vertices inside an edge are its labels
+    let is_edge_label = |i: usize| {
+        kinds[i] == CellKind::Vertex && parent_of(&cells[i]).ok().flatten().is_some_and(|p| kinds[p] == CellKind::Edge)
+    };
+
+    for (i, cell) in cells.iter().enumerate() {
+        if kinds[i] != CellKind::Vertex || is_edge_label(i) {
+            continue;
+        }
+        match &cell.geometry {
+            None => return Err(format!("Unsupported cell {}: shape without geometry", cell.id)),
+            Some(g) if g.relative => {
+                return Err(format!("Unsupported cell {}: shape positioned relative to its parent's size", cell.id))
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Absolute position of the coordinate space each cell's geometry is in
+    let origin = |mut i: usize| -> Result<(f64, f64), String> {
+        let (mut x, mut y) = (0.0, 0.0);
+        for _ in 0..cells.len() {
+            match parent_of(&cells[i])? {
+                Some(p) if kinds[p] == CellKind::Vertex => {
+                    let g = cells[p].geometry.as_ref().map_or((0.0, 0.0), |g| (g.x, g.y));
+                    x += g.0;
+                    y += g.1;
+                    i = p;
+                }
+                _ => return Ok((x, y)),
+            }
+        }
+        Err(format!("Cell {} is nested inside itself", cells[i].id))
+    };
+
+    let mut nodes = Vec::new();
+    let mut edge_labels: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if kinds[i] != CellKind::Vertex {
+            continue;
+        }
+        let parent = parent_of(cell)?;
+        if is_edge_label(i) {
+            if let Some(p) = parent.filter(|_| !cell.value.is_empty()) {
+                edge_labels.entry(p).or_default().push(&cell.value);
+            }
+            continue;
+        }
+
+        let g = cell.geometry.as_ref().expect("shape geometry checked above");
+        let (ox, oy) = origin(i)?;
+        nodes.push(DiagramNode {
+            id: cell.id.clone(),
+            label: cell.value.clone(),
+            shape_type: shape_type(&cell.style),
+            x: ox + g.x,
+            y: oy + g.y,
+            width: g.width,
+            height: g.height,
+            style: cell.style.clone(),
+            parent: parent.filter(|&p| kinds[p] == CellKind::Vertex).map(|p| cells[p].id.clone()),
+        });
+    }
+
+    let mut edges = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if kinds[i] != CellKind::Edge {
+            continue;
+        }
+        let end = |reference: &Option<String>| -> Result<Option<String>, String> {
+            let Some(id) = reference else { return Ok(None) };
+            match index.get(id.as_str()) {
+                None => Err(format!("Edge {} references missing cell {}", cell.id, id)),
+                Some(&j) if kinds[j] != CellKind::Vertex || is_edge_label(j) => {
+                    Err(format!("Unsupported cell {}: connects to {}, which is not a shape", cell.id, id))
+                }
+                Some(_) => Ok(Some(id.clone())),
+            }
+        };
+        let source = end(&cell.source)?;
+        let target = end(&cell.target)?;
+
+        let (ox, oy) = origin(i)?;
+        let offset = |(x, y): (f64, f64)| (ox + x, oy + y);
+        let geometry = cell.geometry.as_ref();
+
+        let label = if cell.value.is_empty() {
+            edge_labels.get(&i).map(|labels| labels.join(" "))
+        } else {
+            Some(cell.value.clone())
+        };
+
+        edges.push(DiagramEdge {
+            id: cell.id.clone(),
+            label,
+            style: cell.style.clone(),
+            waypoints: geometry.map_or_else(Vec::new, |g| g.points.iter().copied().map(offset).collect()),
+            source_point: geometry.and_then(|g| g.source_point).filter(|_| source.is_none()).map(offset),
+            target_point: geometry.and_then(|g| g.target_point).filter(|_| target.is_none()).map(offset),
+            source: source.unwrap_or_default(),
+            target: target.unwrap_or_default(),
+        });
+    }
+
+    Ok((nodes, edges))
+}
+
+/// The node type named by a style, in the names the exporter uses
+fn shape_type(style: &str) -> String {
+    let mut bare = None;
+    for entry in style.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some(("shape", shape)) => return shape_name(shape).to_string(),
+            Some(_) => {}
+            None => {
+                bare.get_or_insert(entry);
+            }
+        }
+    }
+    bare.map_or("rectangle", shape_name).to_string()
+}
+
+fn shape_name(name: &str) -> &str {
+    match name {
+        "rhombus" => "diamond",
+        "parallelogram" => "data",
+        other => other,
+    }
+}
+
+/// Pick the diagram type from the stencils the cells use
+fn infer_diagram_type(nodes: &[DiagramNode], edges: &[DiagramEdge]) -> String {
+    let styles = || nodes.iter().map(|n| &n.style).chain(edges.iter().map(|e| &e.style));
+    if styles().any(|s| s.contains("mxgraph.bpmn")) {
+        "bpmn".to_string()
+    } else if styles().any(|s| s.contains("mxgraph.mockup")) {
+        "wireframe".to_string()
+    } else {
+        "flowchart".to_string()
+    }
+}
+
+/// A numeric attribute; draw.io leaves out zeros
+fn number(attrs: &HashMap<String, String>, key: &str) -> f64 {
+    attrs.get(key).and_then(|v| v.parse().ok()).unwrap_or(0.0)
+}
+
+/// Collect attributes by local name
+fn attributes(element: &BytesStart) -> Result<HashMap<String, String>, String> {
+    let mut map = HashMap::new();
+    for attr in element.attributes() {
+        let attr = attr.map_err(|e| e.to_string())?;
+        let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).to_string();
+        let value = attr.unescape_value().map_err(|e| e.to_string())?.to_string();
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &str = r#"<mxfile host="app.diagrams.net" modified="2024-05-01T10:00:00Z" version="24.2.0">
+  <diagram id="p1" name="Checkout">
+    <mxGraphModel grid="1">
+      <root>
+        <mxCell id="0"/>
+        <mxCell id="1" parent="0"/>
+        <mxCell id="lane" value="Shop" style="swimlane;whiteSpace=wrap;html=1;startSize=30;" vertex="1" parent="1">
+          <mxGeometry x="100" y="50" width="400" height="300" as="geometry"/>
+        </mxCell>
+        <object id="a" label="Pay &amp; ship">
+          <mxCell style="rounded=1;whiteSpace=wrap;html=1;" vertex="1" parent="lane">
+            <mxGeometry x="20" y="40" width="120" height="60" as="geometry"/>
+          </mxCell>
+        </object>
+        <mxCell id="b" value="OK?" style="rhombus;whiteSpace=wrap;html=1;" vertex="1" parent="1">
+          <mxGeometry x="600" y="80" width="80" height="80" as="geometry"/>
+        </mxCell>
+        <mxCell id="e1" style="edgeStyle=orthogonalEdgeStyle;html=1;" edge="1" parent="1" source="a" target="b">
+          <mxGeometry relative="1" as="geometry">
+            <Array as="points">
+              <mxPoint x="400" y="120"/>
+            </Array>
+          </mxGeometry>
+        </mxCell>
+        <mxCell id="e1-label" value="next" style="edgeLabel;html=1;" vertex="1" connectable="0" parent="e1">
+          <mxGeometry x="-0.2" relative="1" as="geometry"><mxPoint as="offset"/></mxGeometry>
+        </mxCell>
+        <mxCell id="e2" value="" style="endArrow=none;html=1;" edge="1" parent="1">
+          <mxGeometry relative="1" as="geometry">
+            <mxPoint x="10" y="10" as="sourcePoint"/>
+            <mxPoint x="90" y="10" as="targetPoint"/>
+          </mxGeometry>
+        </mxCell>
+      </root>
+    </mxGraphModel>
+  </diagram>
+</mxfile>"#;
+
+    #[test]
+    fn test_plain_file() {
+        let structure = parse_drawio(PLAIN).unwrap();
+        assert_eq!(structure.metadata.title.as_deref(), Some("Checkout"));
+        assert_eq!(structure.metadata.version.as_deref(), Some("24.2.0"));
+        assert_eq!(structure.diagram_type, "flowchart");
+
+        let ids: Vec<&str> = structure.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["lane", "a", "b"]);
+
+        let a = &structure.nodes[1];
+        assert_eq!(a.label, "Pay & ship");
+        assert_eq!(a.parent.as_deref(), Some("lane"));
+        assert_eq!((a.x, a.y), (120.0, 90.0));
+        assert_eq!(structure.nodes[0].shape_type, "swimlane");
+        assert_eq!(structure.nodes[2].shape_type, "diamond");
+
+        let e1 = &structure.edges[0];
+        assert_eq!((e1.source.as_str(), e1.target.as_str()), ("a", "b"));
+        assert_eq!(e1.label.as_deref(), Some("next"));
+        assert_eq!(e1.waypoints, vec![(400.0, 120.0)]);
+
+        let e2 = &structure.edges[1];
+        assert!(e2.source.is_empty() && e2.label.is_none());
+        assert_eq!((e2.source_point, e2.target_point), (Some((10.0, 10.0)), Some((90.0, 10.0))));
+    }
+
+    #[test]
+    fn test_compressed_file() {
+        let start = PLAIN.find("<mxGraphModel").unwrap();
+        let end = PLAIN.find("</mxGraphModel>").unwrap() + "</mxGraphModel>".len();
+        let compressed = drawio::compress(&PLAIN[start..end]).unwrap();
+        let file = format!(r#"<mxfile><diagram name="Checkout">{}</diagram></mxfile>"#, compressed);
+
+        let structure = parse_drawio(&file).unwrap();
+        assert_eq!(structure.nodes.len(), 3);
+        assert_eq!(structure.edges.len(), 2);
+        assert_eq!(structure.nodes[1].label, "Pay & ship");
+    }
+
+    #[test]
+    fn test_round_trips_export() {
+        let options = crate::ExportOptions {
+            include_grid: true,
+            ..crate::test_export_options()
+        };
+        let original = parse_drawio(PLAIN).unwrap();
+        let xml = drawio::generate_xml_from_structure(&original, &options).unwrap();
+        let reopened = parse_drawio(&xml).unwrap();
+
+        assert_eq!(reopened.nodes.len(), 3);
+        let a = &reopened.nodes[1];
+        assert_eq!((a.x, a.y, a.label.as_str()), (120.0, 90.0, "Pay & ship"));
+        assert_eq!(a.parent.as_deref(), Some(reopened.nodes[0].id.as_str()));
+        assert_eq!(reopened.edges[0].waypoints, vec![(400.0, 120.0)]);
+        assert_eq!(reopened.edges[1].target_point, Some((90.0, 10.0)));
+    }
+
+    #[test]
+    fn test_unsupported_cells_are_reported() {
+        assert!(parse_drawio("<svg/>").unwrap_err().starts_with("Not a draw.io file"));
+
+        let dangling = PLAIN.replace(r#"target="b""#, r#"target="zz""#);
+        assert_eq!(parse_drawio(&dangling).unwrap_err(), "Edge e1 references missing cell zz");
+
+        let port = PLAIN.replace(
+            r#"<mxGeometry x="600" y="80" width="80" height="80" as="geometry"/>"#,
+            r#"<mxGeometry x="1" y="0.5" width="10" height="10" relative="1" as="geometry"/>"#,
+        );
+        assert!(parse_drawio(&port).unwrap_err().starts_with("Unsupported cell b"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Stroke editing module
+//!
+//! Point-eraser support: strokes touched by an eraser circle or path are cut
+//! into their remaining segments, and detected shapes built from edited
+//! strokes are invalidated.
+
+use crate::shapes::DetectedShape;
+use crate::{Point, Stroke};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Eraser geometry in canvas coordinates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Eraser {
+    /// A single eraser dab
+    Circle { x: f64, y: f64, radius: f64 },
+    /// An eraser drag; every point along the path erases within `radius`
+    Path { points: Vec<(f64, f64)>, radius: f64 },
+}
+
+impl Eraser {
+    /// Whether the eraser's bounding box overlaps the stroke's; strokes for
+    /// which this is false can't be cut
+    pub fn may_reach(&self, stroke: &Stroke) -> bool {
+        let (eraser_min, eraser_max) = match self {
+            Eraser::Circle { x, y, .. } => bounding_box([(*x, *y)]),
+            Eraser::Path { points, .. } => bounding_box(points.iter().copied()),
+        };
+        let (stroke_min, stroke_max) = bounding_box(stroke.points.iter().map(|p| (p.x, p.y)));
+        let r = self.radius();
+        stroke_min.0 <= eraser_max.0 + r
+            && stroke_max.0 >= eraser_min.0 - r
+            && stroke_min.1 <= eraser_max.1 + r
+            && stroke_max.1 >= eraser_min.1 - r
+    }
+
+    fn radius(&self) -> f64 {
+        match self {
+            Eraser::Circle { radius, .. } | Eraser::Path { radius, .. } => *radius,
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Eraser::Circle { x: cx, y: cy, radius } => (x - cx).powi(2) + (y - cy).powi(2) <= radius * radius,
+            Eraser::Path { points, radius } => match points.len() {
+                0 => false,
+                1 => (x - points[0].0).powi(2) + (y - points[0].1).powi(2) <= radius * radius,
+                _ => points
+                    .windows(2)
+                    .any(|w| segment_distance((x, y), w[0], w[1]) <= *radius),
+            },
+        }
+    }
+}
+
+/// Smallest and largest corner of a set of points
+fn bounding_box(points: impl IntoIterator<Item = (f64, f64)>) -> ((f64, f64), (f64, f64)) {
+    points.into_iter().fold(
+        ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)),
+        |(min, max), (x, y)| ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y))),
+    )
+}
+
+/// Outcome of an erase operation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EraseResult {
+    /// Strokes that were removed or split
+    pub removed_ids: Vec<String>,
+    /// Remaining segments of split strokes, with new ids
+    pub added: Vec<Stroke>,
+}
+
+/// Erase everything under the eraser, splitting strokes into the parts that remain.
+///
+/// Split segments take the original stroke's place in the drawing order.
+/// Strokes for which `editable` returns false are left untouched.
+pub fn erase(strokes: &mut Vec<Stroke>, eraser: &Eraser, editable: impl Fn(&Stroke) -> bool) -> EraseResult {
+    let mut result = EraseResult::default();
+    let mut kept = Vec::with_capacity(strokes.len());
+
+    for stroke in strokes.drain(..) {
+        let split = if editable(&stroke) { split_stroke(&stroke, eraser) } else { None };
+        match split {
+            None => kept.push(stroke),
+            Some(segments) => {
+                result.removed_ids.push(stroke.id);
+                result.added.extend(segments.iter().cloned());
+                kept.extend(segments);
+            }
+        }
+    }
+
+    *strokes = kept;
+    result
+}
+
+/// Remaining segments of a stroke, or `None` when the eraser doesn't touch it
+fn split_stroke(stroke: &Stroke, eraser: &Eraser) -> Option<Vec<Stroke>> {
+    // Densify so segments passing through the eraser between two samples are cut too
+    let step = (eraser.radius() / 2.0).max(0.5);
+    let points = densify(&stroke.points, step);
+
+    let erased: Vec<bool> = points.iter().map(|p| eraser.contains(p.x, p.y)).collect();
+    if !erased.iter().any(|&e| e) {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    let mut run: Vec<Point> = Vec::new();
+    for (point, erased) in points.into_iter().zip(erased) {
+        if erased {
+            push_segment(&mut segments, stroke, std::mem::take(&mut run));
+        } else {
+            run.push(point);
+        }
+    }
+    push_segment(&mut segments, stroke, run);
+
+    Some(segments)
+}
+
+fn push_segment(segments: &mut Vec<Stroke>, original: &Stroke, points: Vec<Point>) {
+    // A lone sample left between two erased stretches isn't visible ink
+    if points.len() < 2 {
+        return;
+    }
+    segments.push(Stroke {
+        id: uuid::Uuid::new_v4().to_string(),
+        curves: crate::canvas::fit_bezier(&points, crate::canvas::CURVE_TOLERANCE),
+        points,
+        color: original.color.clone(),
+        width: original.width,
+        tool: original.tool.clone(),
+        layer_id: original.layer_id.clone(),
+    });
+}
+
+/// Insert interpolated points so no segment is longer than `step`
+fn densify(points: &[Point], step: f64) -> Vec<Point> {
+    let mut dense = Vec::with_capacity(points.len());
+    for (i, point) in points.iter().enumerate() {
+        if let Some(prev) = i.checked_sub(1).map(|j| &points[j]) {
+            let length = ((point.x - prev.x).powi(2) + (point.y - prev.y).powi(2)).sqrt();
+            let pieces = (length / step).ceil() as usize;
+            for k in 1..pieces {
+                let t = k as f64 / pieces as f64;
+                dense.push(Point {
+                    x: prev.x + (point.x - prev.x) * t,
+                    y: prev.y + (point.y - prev.y) * t,
+                    pressure: match (prev.pressure, point.pressure) {
+                        (Some(a), Some(b)) => Some(a + (b - a) * t),
+                        (a, b) => a.or(b),
+                    },
+                    timestamp: prev.timestamp
+                        + ((point.timestamp.saturating_sub(prev.timestamp)) as f64 * t) as u64,
+                });
+            }
+        }
+        dense.push(point.clone());
+    }
+    dense
+}
+
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+/// Drop detected shapes built from any of the given strokes.
+///
+/// Returns the number of shapes removed; children of removed containers are
+/// detached rather than dropped.
+pub fn invalidate_shapes(shapes: &mut Vec<DetectedShape>, stroke_ids: &HashSet<String>) -> usize {
+    let before = shapes.len();
+    let mut removed = HashSet::new();
+    shapes.retain(|shape| {
+        let stale = shape.stroke_ids.iter().any(|id| stroke_ids.contains(id));
+        if stale {
+            removed.insert(shape.id.clone());
+        }
+        !stale
+    });
+
+    for shape in shapes.iter_mut() {
+        if shape.parent_id.as_ref().is_some_and(|p| removed.contains(p)) {
+            shape.parent_id = None;
+        }
+    }
+
+    before - shapes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+
+    fn horizontal_stroke(id: &str) -> Stroke {
+        let points = (0..=10)
+            .map(|i| Point { x: i as f64 * 10.0, y: 50.0, pressure: Some(0.5), timestamp: i * 10 })
+            .collect();
+        test_stroke(id, points)
+    }
+
+    #[test]
+    fn test_circle_splits_stroke() {
+        let mut strokes = vec![horizontal_stroke("a"), horizontal_stroke("b")];
+        strokes[1].points.iter_mut().for_each(|p| p.y = 200.0);
+
+        let result = erase(&mut strokes, &Eraser::Circle { x: 50.0, y: 50.0, radius: 8.0 }, |_| true);
+
+        assert_eq!(result.removed_ids, vec!["a".to_string()]);
+        assert_eq!(result.added.len(), 2);
+        assert_eq!(strokes.len(), 3);
+        assert_eq!(strokes[2].id, "b");
+
+        let left = &strokes[0];
+        let right = &strokes[1];
+        assert!(left.id != "a" && right.id != "a");
+        assert!(left.points.iter().all(|p| p.x < 42.0 + 1e-9));
+        assert!(right.points.iter().all(|p| p.x > 58.0 - 1e-9));
+    }
+
+    #[test]
+    fn test_path_eraser_cuts_between_samples() {
+        // The path crosses the stroke between two of its samples
+        let mut strokes = vec![horizontal_stroke("a")];
+        let eraser = Eraser::Path { points: vec![(55.0, 0.0), (55.0, 100.0)], radius: 2.0 };
+
+        let result = erase(&mut strokes, &eraser, |_| true);
+        assert_eq!(result.added.len(), 2);
+        assert!(strokes[0].points.last().unwrap().x < 55.0);
+        assert!(strokes[1].points[0].x > 55.0);
+    }
+
+    #[test]
+    fn test_invalidate_shapes() {
+        use crate::shapes::{test_shape, ShapeType};
+
+        let shape = |id: &str, strokes: &[&str], parent: Option<&str>| DetectedShape {
+            stroke_ids: strokes.iter().map(|s| s.to_string()).collect(),
+            parent_id: parent.map(str::to_string),
+            ..test_shape(id, ShapeType::Rectangle, (0.0, 0.0), (10.0, 10.0))
+        };
+
+        let mut shapes = vec![shape("lane", &["s1"], None), shape("box", &["s2"], Some("lane"))];
+        let removed = invalidate_shapes(&mut shapes, &HashSet::from(["s1".to_string()]));
+
+        assert_eq!(removed, 1);
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].parent_id, None);
+    }
+}
@@ -0,0 +1,413 @@
+//! Operation history module
+//!
+//! Backend undo/redo log. Every board-changing command records a delta (the
+//! strokes it removed and added, plus analysis results when they changed)
+//! rather than a full snapshot, and the log is saved alongside the strokes.
+
+use crate::ocr::TextRegion;
+use crate::shapes::DetectedShape;
+use crate::Stroke;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Kind of operation recorded in the log
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    AddStroke,
+    DeleteStrokes,
+    UpdateStroke,
+    Erase,
+    Transform,
+    Clear,
+    Import,
+    Analysis,
+}
+
+/// A stroke together with its position in the drawing order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedStroke {
+    pub index: usize,
+    pub stroke: Stroke,
+}
+
+/// Detected shapes and OCR text at one point in time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalysisSnapshot {
+    pub shapes: Vec<DetectedShape>,
+    pub text_regions: Vec<TextRegion>,
+}
+
+/// Change made by one operation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Delta {
+    /// Strokes removed, indexed by their position before the operation
+    #[serde(with = "crate::codec::packed_strokes")]
+    pub removed: Vec<IndexedStroke>,
+    /// Strokes added, indexed by their position after the operation
+    #[serde(with = "crate::codec::packed_strokes")]
+    pub added: Vec<IndexedStroke>,
+    /// Analysis results before and after, when the operation changed them
+    pub analysis: Option<(AnalysisSnapshot, AnalysisSnapshot)>,
+}
+
+impl Delta {
+    /// A single stroke appended at `index`
+    pub fn added(index: usize, stroke: Stroke) -> Self {
+        Self {
+            added: vec![IndexedStroke { index, stroke }],
+            ..Self::default()
+        }
+    }
+
+    /// Difference between two versions of the stroke list, matched by id
+    pub fn between(before: &[Stroke], after: &[Stroke]) -> Self {
+        let after_by_id: HashMap<&str, &Stroke> = after.iter().map(|s| (s.id.as_str(), s)).collect();
+        let before_by_id: HashMap<&str, &Stroke> = before.iter().map(|s| (s.id.as_str(), s)).collect();
+
+        let removed = before
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| after_by_id.get(s.id.as_str()) != Some(s))
+            .map(|(index, s)| IndexedStroke { index, stroke: s.clone() })
+            .collect();
+        let added = after
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| before_by_id.get(s.id.as_str()) != Some(s))
+            .map(|(index, s)| IndexedStroke { index, stroke: s.clone() })
+            .collect();
+
+        Self { removed, added, analysis: None }
+    }
+
+    /// Difference for an edit that only removed or replaced the strokes in `touched`.
+    ///
+    /// `touched` holds those strokes as they were before the edit and
+    /// `ids_before` every id on the board at that time; strokes in `after`
+    /// whose ids are in neither were added by the edit. Strokes outside
+    /// `touched` are assumed unchanged and are not compared.
+    pub fn between_touched(touched: Vec<IndexedStroke>, ids_before: &HashSet<String>, after: &[Stroke]) -> Self {
+        let touched_ids: HashSet<&str> = touched.iter().map(|s| s.stroke.id.as_str()).collect();
+        let after_by_id: HashMap<&str, (usize, &Stroke)> = after
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| touched_ids.contains(s.id.as_str()))
+            .map(|(index, s)| (s.id.as_str(), (index, s)))
+            .collect();
+
+        let mut added: Vec<IndexedStroke> = after
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !ids_before.contains(&s.id))
+            .map(|(index, s)| IndexedStroke { index, stroke: s.clone() })
+            .collect();
+        let mut removed = Vec::new();
+        for before in touched {
+            match after_by_id.get(before.stroke.id.as_str()) {
+                Some((_, s)) if **s == before.stroke => {}
+                Some(&(index, s)) => {
+                    added.push(IndexedStroke { index, stroke: s.clone() });
+                    removed.push(before);
+                }
+                None => removed.push(before),
+            }
+        }
+        added.sort_by_key(|s| s.index);
+
+        Self { removed, added, analysis: None }
+    }
+
+    /// Attach the analysis change
+    pub fn with_analysis(mut self, before: AnalysisSnapshot, after: AnalysisSnapshot) -> Self {
+        self.analysis = Some((before, after));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.analysis.is_none()
+    }
+
+    /// Rough memory weight, in stored points
+    fn weight(&self) -> usize {
+        let strokes: usize = self
+            .removed
+            .iter()
+            .chain(&self.added)
+            .map(|s| s.stroke.points.len() + 1)
+            .sum();
+        let analysis = self.analysis.as_ref().map_or(0, |(before, after)| {
+            (before.shapes.len() + before.text_regions.len() + after.shapes.len() + after.text_regions.len())
+                * ANALYSIS_ITEM_WEIGHT
+        });
+        strokes + analysis
+    }
+}
+
+/// Approximate size of a shape or text region relative to a stroke point
+const ANALYSIS_ITEM_WEIGHT: usize = 8;
+
+/// One recorded operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub kind: OperationKind,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub delta: Delta,
+}
+
+/// Limits that keep the log's memory use bounded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryLimits {
+    pub max_entries: usize,
+    /// Total weight (roughly, stored points) across all entries
+    pub max_weight: usize,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 500,
+            max_weight: 2_000_000,
+        }
+    }
+}
+
+/// The board state an entry is applied to
+pub struct Board<'a> {
+    pub strokes: &'a mut Vec<Stroke>,
+    pub shapes: &'a mut Vec<DetectedShape>,
+    pub text_regions: &'a mut Vec<TextRegion>,
+}
+
+/// Undo/redo log; entries before `cursor` are applied
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    entries: Vec<Entry>,
+    cursor: usize,
+    next_id: u64,
+    #[serde(default)]
+    limits: HistoryLimits,
+}
+
+/// Entry summary for the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntrySummary {
+    pub id: u64,
+    pub kind: OperationKind,
+    pub timestamp: u64,
+    pub strokes_added: usize,
+    pub strokes_removed: usize,
+    /// Whether the entry is currently applied (false once undone)
+    pub applied: bool,
+}
+
+/// History overview returned to the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySummary {
+    pub entries: Vec<EntrySummary>,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+impl History {
+    /// Record an operation, dropping any undone entries and the oldest ones over the limits
+    pub fn record(&mut self, kind: OperationKind, delta: Delta) {
+        if delta.is_empty() {
+            return;
+        }
+
+        self.entries.truncate(self.cursor);
+        self.entries.push(Entry {
+            id: self.next_id,
+            kind,
+            timestamp: now_millis(),
+            delta,
+        });
+        self.next_id += 1;
+        self.cursor = self.entries.len();
+
+        let mut weight: usize = self.entries.iter().map(|e| e.delta.weight()).sum();
+        let mut drop = 0;
+        while self.entries.len() - drop > 1
+            && (self.entries.len() - drop > self.limits.max_entries || weight > self.limits.max_weight)
+        {
+            weight -= self.entries[drop].delta.weight();
+            drop += 1;
+        }
+        self.entries.drain(..drop);
+        self.cursor -= drop;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry the next undo (or, going `forward`, redo) would apply
+    pub fn next_entry(&self, forward: bool) -> Option<&Entry> {
+        if forward {
+            self.entries.get(self.cursor)
+        } else {
+            self.entries.get(self.cursor.checked_sub(1)?)
+        }
+    }
+
+    /// Revert the most recent applied entry
+    pub fn undo(&mut self, board: &mut Board) -> Option<OperationKind> {
+        let entry = self.entries.get(self.cursor.checked_sub(1)?)?;
+        let delta = &entry.delta;
+        apply_strokes(board.strokes, &delta.added, &delta.removed);
+        if let Some((before, _)) = &delta.analysis {
+            restore_analysis(board, before);
+        }
+        self.cursor -= 1;
+        Some(entry.kind)
+    }
+
+    /// Re-apply the next undone entry
+    pub fn redo(&mut self, board: &mut Board) -> Option<OperationKind> {
+        let entry = self.entries.get(self.cursor)?;
+        let delta = &entry.delta;
+        apply_strokes(board.strokes, &delta.removed, &delta.added);
+        if let Some((_, after)) = &delta.analysis {
+            restore_analysis(board, after);
+        }
+        self.cursor += 1;
+        Some(entry.kind)
+    }
+
+    pub fn summary(&self) -> HistorySummary {
+        HistorySummary {
+            entries: self
+                .entries
+                .iter()
+                .enumerate()
+                .map(|(i, e)| EntrySummary {
+                    id: e.id,
+                    kind: e.kind,
+                    timestamp: e.timestamp,
+                    strokes_added: e.delta.added.len(),
+                    strokes_removed: e.delta.removed.len(),
+                    applied: i < self.cursor,
+                })
+                .collect(),
+            can_undo: self.cursor > 0,
+            can_redo: self.cursor < self.entries.len(),
+        }
+    }
+}
+
+/// Remove `take` strokes (by id) and insert `put` at their recorded positions
+fn apply_strokes(strokes: &mut Vec<Stroke>, take: &[IndexedStroke], put: &[IndexedStroke]) {
+    let ids: HashSet<&str> = take.iter().map(|s| s.stroke.id.as_str()).collect();
+    strokes.retain(|s| !ids.contains(s.id.as_str()));
+
+    let mut put: Vec<&IndexedStroke> = put.iter().collect();
+    put.sort_by_key(|s| s.index);
+    for s in put {
+        let index = s.index.min(strokes.len());
+        strokes.insert(index, s.stroke.clone());
+    }
+}
+
+fn restore_analysis(board: &mut Board, snapshot: &AnalysisSnapshot) {
+    *board.shapes = snapshot.shapes.clone();
+    *board.text_regions = snapshot.text_regions.clone();
+}
+
+fn now_millis() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+    use crate::Point;
+
+    fn stroke(id: &str, x: f64) -> Stroke {
+        test_stroke(id, vec![Point { x, y: 0.0, pressure: None, timestamp: 0 }])
+    }
+
+    fn ids(strokes: &[Stroke]) -> Vec<&str> {
+        strokes.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_undo_redo_round_trip() {
+        let mut history = History::default();
+        let mut strokes = vec![stroke("a", 0.0), stroke("b", 1.0), stroke("c", 2.0)];
+        let mut shapes = Vec::new();
+        let mut text = Vec::new();
+
+        // Split "b" into two segments and move "c"
+        let before = strokes.clone();
+        strokes = vec![stroke("a", 0.0), stroke("b1", 1.0), stroke("b2", 1.5), stroke("c", 5.0)];
+        history.record(OperationKind::Erase, Delta::between(&before, &strokes));
+
+        let after = strokes.clone();
+        let mut board = Board { strokes: &mut strokes, shapes: &mut shapes, text_regions: &mut text };
+        assert_eq!(history.undo(&mut board), Some(OperationKind::Erase));
+        assert_eq!(ids(board.strokes), vec!["a", "b", "c"]);
+        assert_eq!(board.strokes[2].points[0].x, 2.0);
+
+        assert_eq!(history.redo(&mut board), Some(OperationKind::Erase));
+        assert_eq!(*board.strokes, after);
+        assert!(history.redo(&mut board).is_none());
+    }
+
+    #[test]
+    fn test_between_touched_matches_full_diff() {
+        let before = vec![stroke("a", 0.0), stroke("b", 1.0), stroke("c", 2.0), stroke("d", 3.0)];
+        let after = vec![stroke("a", 0.0), stroke("b1", 1.0), stroke("c", 7.0), stroke("d", 3.0), stroke("e", 4.0)];
+
+        let ids_before: HashSet<String> = before.iter().map(|s| s.id.clone()).collect();
+        let touched = before
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.id == "b" || s.id == "c")
+            .map(|(index, s)| IndexedStroke { index, stroke: s.clone() })
+            .collect();
+        let scoped = Delta::between_touched(touched, &ids_before, &after);
+        let full = Delta::between(&before, &after);
+
+        let entries = |strokes: &[IndexedStroke]| -> Vec<(usize, String)> {
+            strokes.iter().map(|s| (s.index, s.stroke.id.clone())).collect()
+        };
+        assert_eq!(entries(&scoped.removed), entries(&full.removed));
+        assert_eq!(entries(&scoped.added), entries(&full.added));
+    }
+
+    #[test]
+    fn test_record_truncates_redo_and_caps_entries() {
+        let mut history = History {
+            limits: HistoryLimits { max_entries: 2, max_weight: usize::MAX },
+            ..History::default()
+        };
+        let mut strokes = Vec::new();
+        let mut shapes = Vec::new();
+        let mut text = Vec::new();
+
+        for (i, id) in ["a", "b", "c"].iter().enumerate() {
+            strokes.push(stroke(id, i as f64));
+            history.record(OperationKind::AddStroke, Delta::added(i, stroke(id, i as f64)));
+        }
+        assert_eq!(history.summary().entries.len(), 2);
+
+        let mut board = Board { strokes: &mut strokes, shapes: &mut shapes, text_regions: &mut text };
+        history.undo(&mut board);
+        assert_eq!(ids(board.strokes), vec!["a", "b"]);
+
+        board.strokes.push(stroke("d", 9.0));
+        history.record(OperationKind::AddStroke, Delta::added(2, stroke("d", 9.0)));
+        let summary = history.summary();
+        assert!(!summary.can_redo);
+        assert_eq!(summary.entries.len(), 2);
+        assert!(summary.entries.iter().all(|e| e.applied));
+    }
+}
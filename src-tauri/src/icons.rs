@@ -0,0 +1,655 @@
+//! Icon recognition module
+//!
+//! Recognizes conventional multi-stroke infrastructure glyphs (database
+//! cylinders, clouds, servers, firewalls) among detected shapes and maps
+//! them to draw.io stencil libraries.
+
+use crate::ocr::TextRegion;
+use crate::shapes::{bounds_center, bounds_contain, DetectedShape, ShapeBounds, ShapeType};
+use crate::Stroke;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Kinds of infrastructure icons that can be recognized
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum IconKind {
+    Database,
+    Cloud,
+    Server,
+    Firewall,
+}
+
+/// draw.io stencil library used for recognized icons
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IconLibrary {
+    #[default]
+    Network,
+    Aws,
+    Azure,
+}
+
+/// Icon style configuration for export
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct IconStyleConfig {
+    #[serde(default)]
+    pub library: IconLibrary,
+    /// Per-icon style strings that replace the library style
+    #[serde(default)]
+    pub overrides: HashMap<IconKind, String>,
+}
+
+impl IconStyleConfig {
+    /// Get the draw.io style string for an icon kind
+    pub fn style_for(&self, kind: IconKind) -> String {
+        if let Some(style) = self.overrides.get(&kind) {
+            return style.clone();
+        }
+
+        let style = match (self.library, kind) {
+            (IconLibrary::Network, IconKind::Database) => {
+                "shape=cylinder3;whiteSpace=wrap;html=1;boundedLbl=1;backgroundOutline=1;size=15;"
+            }
+            (IconLibrary::Network, IconKind::Cloud) => {
+                "html=1;outlineConnect=0;fillColor=#CCCCCC;strokeColor=#6881B3;verticalLabelPosition=bottom;verticalAlign=top;align=center;shape=mxgraph.networks.cloud;"
+            }
+            (IconLibrary::Network, IconKind::Server) => {
+                "html=1;outlineConnect=0;fillColor=#CCCCCC;strokeColor=#6881B3;verticalLabelPosition=bottom;verticalAlign=top;align=center;shape=mxgraph.networks.server;"
+            }
+            (IconLibrary::Network, IconKind::Firewall) => {
+                "html=1;outlineConnect=0;fillColor=#CCCCCC;strokeColor=#6881B3;verticalLabelPosition=bottom;verticalAlign=top;align=center;shape=mxgraph.networks.firewall;"
+            }
+            (IconLibrary::Aws, IconKind::Database) => {
+                "outlineConnect=0;fontColor=#232F3E;fillColor=#C925D1;strokeColor=#ffffff;verticalLabelPosition=bottom;verticalAlign=top;align=center;html=1;aspect=fixed;shape=mxgraph.aws4.resourceIcon;resIcon=mxgraph.aws4.rds;"
+            }
+            (IconLibrary::Aws, IconKind::Cloud) => {
+                "outlineConnect=0;fontColor=#232F3E;fillColor=#8C4FFF;strokeColor=#ffffff;verticalLabelPosition=bottom;verticalAlign=top;align=center;html=1;aspect=fixed;shape=mxgraph.aws4.resourceIcon;resIcon=mxgraph.aws4.internet_gateway;"
+            }
+            (IconLibrary::Aws, IconKind::Server) => {
+                "outlineConnect=0;fontColor=#232F3E;fillColor=#ED7100;strokeColor=#ffffff;verticalLabelPosition=bottom;verticalAlign=top;align=center;html=1;aspect=fixed;shape=mxgraph.aws4.resourceIcon;resIcon=mxgraph.aws4.ec2;"
+            }
+            (IconLibrary::Aws, IconKind::Firewall) => {
+                "outlineConnect=0;fontColor=#232F3E;fillColor=#DD344C;strokeColor=#ffffff;verticalLabelPosition=bottom;verticalAlign=top;align=center;html=1;aspect=fixed;shape=mxgraph.aws4.resourceIcon;resIcon=mxgraph.aws4.network_firewall;"
+            }
+            (IconLibrary::Azure, IconKind::Database) => {
+                "image;aspect=fixed;html=1;align=center;verticalLabelPosition=bottom;verticalAlign=top;image=img/lib/azure2/databases/SQL_Database.svg;"
+            }
+            (IconLibrary::Azure, IconKind::Cloud) => {
+                "image;aspect=fixed;html=1;align=center;verticalLabelPosition=bottom;verticalAlign=top;image=img/lib/azure2/networking/Public_IP_Addresses.svg;"
+            }
+            (IconLibrary::Azure, IconKind::Server) => {
+                "image;aspect=fixed;html=1;align=center;verticalLabelPosition=bottom;verticalAlign=top;image=img/lib/azure2/compute/Virtual_Machine.svg;"
+            }
+            (IconLibrary::Azure, IconKind::Firewall) => {
+                "image;aspect=fixed;html=1;align=center;verticalLabelPosition=bottom;verticalAlign=top;image=img/lib/azure2/networking/Firewalls.svg;"
+            }
+        };
+        style.to_string()
+    }
+}
+
+/// Icon recognition parameters
+#[derive(Debug, Clone)]
+pub struct IconParams {
+    /// Minimum width/height ratio of a cylinder's top ellipse
+    pub cylinder_min_flatness: f64,
+    /// Maximum mean radial error for a stroke to count as an ellipse
+    pub ellipse_fit_tolerance: f64,
+    /// Minimum number of bumps along a cloud outline
+    pub cloud_min_lobes: usize,
+    /// Minimum rise of a cloud bump above its neighbouring dips, relative to
+    /// the mean radius; wobbly circles stay below it
+    pub cloud_lobe_prominence: f64,
+    /// Minimum span of an inner divider relative to the enclosing box
+    pub divider_span_ratio: f64,
+    /// Maximum distance from an icon to its label, relative to icon size
+    pub label_distance_ratio: f64,
+}
+
+impl Default for IconParams {
+    fn default() -> Self {
+        Self {
+            cylinder_min_flatness: 1.5,
+            ellipse_fit_tolerance: 0.2,
+            cloud_min_lobes: 4,
+            cloud_lobe_prominence: 0.1,
+            divider_span_ratio: 0.7,
+            label_distance_ratio: 1.5,
+        }
+    }
+}
+
+/// Recognize multi-stroke infrastructure icons among detected shapes.
+///
+/// Runs after single-stroke detection: the strokes that make up an icon are
+/// folded into one shape tagged with its [`IconKind`], and the icon label is
+/// taken from the nearest OCR text.
+pub fn recognize_icons(
+    shapes: &mut Vec<DetectedShape>,
+    strokes: &[Stroke],
+    text_regions: &[TextRegion],
+) {
+    let params = IconParams::default();
+    let mut absorbed: HashSet<String> = HashSet::new();
+
+    for i in 0..shapes.len() {
+        if absorbed.contains(&shapes[i].id) || shapes[i].properties.icon.is_some() {
+            continue;
+        }
+
+        let found = detect_cylinder(i, shapes, strokes, &absorbed, &params)
+            .map(|parts| (IconKind::Database, parts))
+            .or_else(|| detect_boxed_icon(i, shapes, text_regions, &absorbed, &params))
+            .or_else(|| is_cloud(&shapes[i], strokes, &params).then(|| (IconKind::Cloud, Vec::new())));
+
+        let Some((kind, parts)) = found else {
+            continue;
+        };
+
+        let mut bounds = shapes[i].bounds.clone();
+        let mut stroke_ids = shapes[i].stroke_ids.clone();
+        for &p in &parts {
+            bounds = union_bounds(&bounds, &shapes[p].bounds);
+            stroke_ids.extend(shapes[p].stroke_ids.clone());
+            absorbed.insert(shapes[p].id.clone());
+        }
+
+        let icon = &mut shapes[i];
+        let (cx, cy) = bounds_center(&bounds);
+        icon.bounds = bounds;
+        icon.stroke_ids = stroke_ids;
+        icon.properties.center_x = cx;
+        icon.properties.center_y = cy;
+        icon.properties.icon = Some(kind);
+        icon.properties.bpmn = None;
+        icon.label = nearest_label(&icon.bounds, text_regions, &params);
+    }
+
+    shapes.retain(|s| !absorbed.contains(&s.id));
+}
+
+/// A flat ellipse with vertical lines hanging from its left and right ends
+fn detect_cylinder(
+    top: usize,
+    shapes: &[DetectedShape],
+    strokes: &[Stroke],
+    absorbed: &HashSet<String>,
+    params: &IconParams,
+) -> Option<Vec<usize>> {
+    let ellipse = &shapes[top];
+    if is_connector(ellipse) || ellipse.bounds.height <= 0.0 {
+        return None;
+    }
+    if ellipse.bounds.width / ellipse.bounds.height < params.cylinder_min_flatness {
+        return None;
+    }
+    let points = &stroke_for(ellipse, strokes)?.points;
+    if ellipse_fit_error(points, &ellipse.bounds) > params.ellipse_fit_tolerance {
+        return None;
+    }
+
+    let b = &ellipse.bounds;
+    let side_tolerance = b.width * 0.2;
+    let top_y = b.y + b.height / 2.0;
+
+    let mut left = None;
+    let mut right = None;
+    for (j, shape) in shapes.iter().enumerate() {
+        if j == top || absorbed.contains(&shape.id) || shape.shape_type != ShapeType::Line {
+            continue;
+        }
+        let Some((x, y0, y1)) = vertical_segment(shape) else {
+            continue;
+        };
+        if (y0 - top_y).abs() > b.height || y1 - y0 < b.height {
+            continue;
+        }
+        if (x - b.x).abs() < side_tolerance && left.is_none() {
+            left = Some(j);
+        } else if (x - (b.x + b.width)).abs() < side_tolerance && right.is_none() {
+            right = Some(j);
+        }
+    }
+
+    let (left, right) = (left?, right?);
+    let mut parts = vec![left, right];
+
+    // The bottom arc, if drawn as its own stroke
+    let bottom = vertical_segment(&shapes[left])?.2.max(vertical_segment(&shapes[right])?.2);
+    if let Some(arc) = shapes.iter().enumerate().position(|(j, s)| {
+        j != top
+            && !parts.contains(&j)
+            && !absorbed.contains(&s.id)
+            && matches!(s.shape_type, ShapeType::Connector | ShapeType::Line)
+            && (s.bounds.y + s.bounds.height / 2.0 - bottom).abs() < b.height
+            && s.bounds.x >= b.x - side_tolerance
+            && s.bounds.x + s.bounds.width <= b.x + b.width + side_tolerance
+    }) {
+        parts.push(arc);
+    }
+
+    Some(parts)
+}
+
+/// A rectangle split by inner dividers: horizontal lines plus small
+/// indicator marks in the bays make a server, horizontal plus short vertical
+/// lines make a (brick wall) firewall. Stacked rectangles of the same width
+/// with indicator marks also make a server.
+///
+/// Boxes with text inside are left alone: a box split into compartments
+/// around text is a UML class, not an icon.
+fn detect_boxed_icon(
+    i: usize,
+    shapes: &[DetectedShape],
+    text_regions: &[TextRegion],
+    absorbed: &HashSet<String>,
+    params: &IconParams,
+) -> Option<(IconKind, Vec<usize>)> {
+    let b = &shapes[i].bounds;
+    if shapes[i].shape_type != ShapeType::Rectangle {
+        return None;
+    }
+    if text_regions.iter().any(|t| {
+        bounds_contain(b, t.bounds.x + t.bounds.width / 2.0, t.bounds.y + t.bounds.height / 2.0)
+    }) {
+        return None;
+    }
+
+    let mut horizontal = Vec::new();
+    let mut vertical = Vec::new();
+    for (j, shape) in shapes.iter().enumerate() {
+        if j == i || absorbed.contains(&shape.id) || shape.shape_type != ShapeType::Line {
+            continue;
+        }
+        let (cx, cy) = bounds_center(&shape.bounds);
+        if !bounds_contain(b, cx, cy) {
+            continue;
+        }
+        if let Some((_, x0, x1)) = horizontal_segment(shape) {
+            if x1 - x0 >= b.width * params.divider_span_ratio {
+                horizontal.push(j);
+            }
+        } else if let Some((_, y0, y1)) = vertical_segment(shape) {
+            if y1 - y0 < b.height * 0.6 {
+                vertical.push(j);
+            }
+        }
+    }
+
+    if horizontal.len() >= 2 {
+        let mut edges: Vec<f64> = horizontal
+            .iter()
+            .filter_map(|&j| horizontal_segment(&shapes[j]).map(|(y, _, _)| y))
+            .collect();
+        edges.push(b.y);
+        edges.push(b.y + b.height);
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let bays: Vec<ShapeBounds> = edges
+            .windows(2)
+            .map(|w| ShapeBounds { x: b.x, y: w[0], width: b.width, height: w[1] - w[0], rotation: 0.0 })
+            .collect();
+
+        // Brick joints span most of a bay; shorter vertical strokes may be indicators
+        let bay_height = b.height / bays.len() as f64;
+        let bricks: Vec<usize> = vertical
+            .into_iter()
+            .filter(|&j| vertical_segment(&shapes[j]).is_some_and(|(_, y0, y1)| y1 - y0 >= bay_height * 0.6))
+            .collect();
+        if bricks.len() >= 2 {
+            horizontal.extend(bricks);
+            return Some((IconKind::Firewall, horizontal));
+        }
+
+        let mut skip = horizontal.clone();
+        skip.extend(&bricks);
+        skip.push(i);
+        if let Some(marks) = bay_indicators(&bays, shapes, &skip, absorbed) {
+            horizontal.extend(marks);
+            return Some((IconKind::Server, horizontal));
+        }
+    }
+
+    // Stacked boxes below this one
+    let mut stack = Vec::new();
+    let mut bottom = b.y + b.height;
+    loop {
+        let next = shapes.iter().enumerate().position(|(j, s)| {
+            j != i
+                && !stack.contains(&j)
+                && !absorbed.contains(&s.id)
+                && s.shape_type == ShapeType::Rectangle
+                && (s.bounds.x - b.x).abs() < b.width * 0.15
+                && (s.bounds.width - b.width).abs() < b.width * 0.15
+                && (s.bounds.y - bottom).abs() < b.height * 0.3
+        });
+        match next {
+            Some(j) => {
+                bottom = shapes[j].bounds.y + shapes[j].bounds.height;
+                stack.push(j);
+            }
+            None => break,
+        }
+    }
+
+    if stack.len() < 2 {
+        return None;
+    }
+    let mut skip = stack.clone();
+    skip.push(i);
+    let bays: Vec<ShapeBounds> = skip.iter().map(|&j| shapes[j].bounds.clone()).collect();
+    let marks = bay_indicators(&bays, shapes, &skip, absorbed)?;
+    stack.extend(marks);
+    Some((IconKind::Server, stack))
+}
+
+/// Small marks (drive lights, slots) inside the bays of a server.
+///
+/// At least two bays need one to three marks each, and no bay may hold more:
+/// a crowd of small strokes is handwriting, not indicators.
+fn bay_indicators(
+    bays: &[ShapeBounds],
+    shapes: &[DetectedShape],
+    skip: &[usize],
+    absorbed: &HashSet<String>,
+) -> Option<Vec<usize>> {
+    let mut marks = Vec::new();
+    let mut marked_bays = 0;
+    for bay in bays {
+        let inside: Vec<usize> = shapes
+            .iter()
+            .enumerate()
+            .filter(|(j, s)| {
+                let (cx, cy) = bounds_center(&s.bounds);
+                !skip.contains(j)
+                    && !absorbed.contains(&s.id)
+                    && bounds_contain(bay, cx, cy)
+                    && s.bounds.width <= bay.width * 0.25
+                    && s.bounds.height <= bay.height * 0.8
+            })
+            .map(|(j, _)| j)
+            .collect();
+        if inside.len() > 3 {
+            return None;
+        }
+        if !inside.is_empty() {
+            marked_bays += 1;
+        }
+        marks.extend(inside);
+    }
+    (marked_bays >= 2).then_some(marks)
+}
+
+/// A closed outline with several outward bumps
+fn is_cloud(shape: &DetectedShape, strokes: &[Stroke], params: &IconParams) -> bool {
+    if !matches!(shape.shape_type, ShapeType::Freeform | ShapeType::Circle) {
+        return false;
+    }
+    let Some(stroke) = stroke_for(shape, strokes) else {
+        return false;
+    };
+    count_lobes(&stroke.points, &shape.bounds, params.cloud_lobe_prominence) >= params.cloud_min_lobes
+}
+
+/// Count radial maxima along an outline, ignoring bumps below `prominence`
+/// (relative to the mean radius)
+fn count_lobes(points: &[crate::Point], bounds: &ShapeBounds, prominence: f64) -> usize {
+    if points.len() < 12 {
+        return 0;
+    }
+
+    let (cx, cy) = bounds_center(bounds);
+    let radii: Vec<f64> = points
+        .iter()
+        .map(|p| ((p.x - cx).powi(2) + (p.y - cy).powi(2)).sqrt())
+        .collect();
+
+    // Moving average over the closed outline
+    let window = (radii.len() / 24).max(1);
+    let n = radii.len();
+    let smoothed: Vec<f64> = (0..n)
+        .map(|i| {
+            let sum: f64 = (0..=2 * window).map(|k| radii[(i + n + k - window) % n]).sum();
+            sum / (2 * window + 1) as f64
+        })
+        .collect();
+
+    let mean = smoothed.iter().sum::<f64>() / n as f64;
+    let prominence = mean * prominence;
+
+    // Alternate between "looking for a peak" and "looking for a valley"
+    let mut lobes = 0;
+    let mut seeking_peak = true;
+    let mut extreme = smoothed[0];
+    for &r in &smoothed[1..] {
+        if seeking_peak {
+            if r > extreme {
+                extreme = r;
+            } else if extreme - r > prominence {
+                lobes += 1;
+                seeking_peak = false;
+                extreme = r;
+            }
+        } else if r < extreme {
+            extreme = r;
+        } else if r - extreme > prominence {
+            seeking_peak = true;
+            extreme = r;
+        }
+    }
+    lobes
+}
+
+/// Mean radial error of points against the ellipse inscribed in `bounds`
+fn ellipse_fit_error(points: &[crate::Point], bounds: &ShapeBounds) -> f64 {
+    let a = bounds.width / 2.0;
+    let b = bounds.height / 2.0;
+    if points.is_empty() || a <= 0.0 || b <= 0.0 {
+        return f64::MAX;
+    }
+    let (cx, cy) = bounds_center(bounds);
+    points
+        .iter()
+        .map(|p| {
+            let r = (((p.x - cx) / a).powi(2) + ((p.y - cy) / b).powi(2)).sqrt();
+            (r - 1.0).abs()
+        })
+        .sum::<f64>()
+        / points.len() as f64
+}
+
+/// Nearest OCR text to an icon, within a distance proportional to its size
+fn nearest_label(
+    bounds: &ShapeBounds,
+    text_regions: &[TextRegion],
+    params: &IconParams,
+) -> Option<String> {
+    let (cx, cy) = bounds_center(bounds);
+    let max_distance = bounds.width.max(bounds.height) * params.label_distance_ratio;
+
+    text_regions
+        .iter()
+        .map(|t| {
+            let tx = t.bounds.x + t.bounds.width / 2.0;
+            let ty = t.bounds.y + t.bounds.height / 2.0;
+            (((tx - cx).powi(2) + (ty - cy).powi(2)).sqrt(), t)
+        })
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, t)| t.text.clone())
+}
+
+/// `(x, top, bottom)` of a near-vertical line shape
+fn vertical_segment(shape: &DetectedShape) -> Option<(f64, f64, f64)> {
+    let (sx, sy) = shape.properties.start_point?;
+    let (ex, ey) = shape.properties.end_point?;
+    let angle = (ey - sy).atan2(ex - sx).to_degrees().rem_euclid(180.0);
+    if (angle - 90.0).abs() > 15.0 {
+        return None;
+    }
+    Some(((sx + ex) / 2.0, sy.min(ey), sy.max(ey)))
+}
+
+/// `(y, left, right)` of a near-horizontal line shape
+fn horizontal_segment(shape: &DetectedShape) -> Option<(f64, f64, f64)> {
+    let (sx, sy) = shape.properties.start_point?;
+    let (ex, ey) = shape.properties.end_point?;
+    let angle = (ey - sy).atan2(ex - sx).to_degrees().rem_euclid(180.0);
+    if angle.min(180.0 - angle) > 15.0 {
+        return None;
+    }
+    Some(((sy + ey) / 2.0, sx.min(ex), sx.max(ex)))
+}
+
+fn is_connector(shape: &DetectedShape) -> bool {
+    matches!(
+        shape.shape_type,
+        ShapeType::Arrow | ShapeType::Line | ShapeType::Connector
+    )
+}
+
+fn stroke_for<'a>(shape: &DetectedShape, strokes: &'a [Stroke]) -> Option<&'a Stroke> {
+    let id = shape.stroke_ids.first()?;
+    strokes.iter().find(|s| &s.id == id)
+}
+
+fn union_bounds(a: &ShapeBounds, b: &ShapeBounds) -> ShapeBounds {
+    let min_x = a.x.min(b.x);
+    let min_y = a.y.min(b.y);
+    let max_x = (a.x + a.width).max(b.x + b.width);
+    let max_y = (a.y + a.height).max(b.y + b.height);
+    ShapeBounds {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x,
+        height: max_y - min_y,
+        rotation: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{test_shape, test_stroke};
+    use crate::Point;
+
+    fn outline(id: &str, f: impl Fn(f64) -> (f64, f64)) -> Stroke {
+        let points = (0..=72)
+            .map(|i| {
+                let (x, y) = f(i as f64 / 72.0 * std::f64::consts::TAU);
+                Point { x, y, pressure: None, timestamp: i }
+            })
+            .collect();
+        test_stroke(id, points)
+    }
+
+    #[test]
+    fn test_recognize_database_cylinder() {
+        let top = outline("top", |t| (100.0 + 50.0 * t.cos(), 100.0 + 15.0 * t.sin()));
+        let mut shapes = vec![
+            test_shape("top", ShapeType::Freeform, (50.0, 85.0), (150.0, 115.0)),
+            test_shape("left", ShapeType::Line, (50.0, 100.0), (50.0, 200.0)),
+            test_shape("right", ShapeType::Line, (150.0, 100.0), (150.0, 200.0)),
+        ];
+        let text = vec![TextRegion {
+            id: "t".to_string(),
+            text: "Orders DB".to_string(),
+            bounds: crate::ocr::TextBounds { x: 70.0, y: 210.0, width: 60.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 14.0,
+        }];
+
+        recognize_icons(&mut shapes, &[top], &text);
+
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].properties.icon, Some(IconKind::Database));
+        assert_eq!(shapes[0].stroke_ids.len(), 3);
+        assert_eq!(shapes[0].label.as_deref(), Some("Orders DB"));
+    }
+
+    #[test]
+    fn test_recognize_cloud_and_firewall() {
+        let cloud = outline("cloud", |t| {
+            let r = 60.0 + 12.0 * (5.0 * t).cos();
+            (400.0 + r * t.cos(), 100.0 + r * t.sin())
+        });
+        let mut shapes = vec![
+            test_shape("cloud", ShapeType::Freeform, (328.0, 28.0), (472.0, 172.0)),
+            test_shape("wall", ShapeType::Rectangle, (0.0, 0.0), (120.0, 90.0)),
+            test_shape("h1", ShapeType::Line, (5.0, 30.0), (115.0, 30.0)),
+            test_shape("h2", ShapeType::Line, (5.0, 60.0), (115.0, 60.0)),
+            test_shape("v1", ShapeType::Line, (40.0, 2.0), (40.0, 28.0)),
+            test_shape("v2", ShapeType::Line, (80.0, 32.0), (80.0, 58.0)),
+        ];
+
+        recognize_icons(&mut shapes, &[cloud], &[]);
+
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0].properties.icon, Some(IconKind::Cloud));
+        assert_eq!(shapes[1].properties.icon, Some(IconKind::Firewall));
+    }
+
+    #[test]
+    fn test_server_needs_bay_indicators() {
+        let rack = |marks: bool| {
+            let mut shapes = vec![
+                test_shape("box", ShapeType::Rectangle, (0.0, 0.0), (160.0, 120.0)),
+                test_shape("h1", ShapeType::Line, (5.0, 40.0), (155.0, 40.0)),
+                test_shape("h2", ShapeType::Line, (5.0, 80.0), (155.0, 80.0)),
+            ];
+            if marks {
+                shapes.push(test_shape("led1", ShapeType::Circle, (130.0, 15.0), (140.0, 25.0)));
+                shapes.push(test_shape("led2", ShapeType::Circle, (130.0, 55.0), (140.0, 65.0)));
+            }
+            shapes
+        };
+
+        let mut server = rack(true);
+        recognize_icons(&mut server, &[], &[]);
+        assert_eq!(server.len(), 1);
+        assert_eq!(server[0].properties.icon, Some(IconKind::Server));
+
+        // Bare compartments are a UML class box
+        let mut class_box = rack(false);
+        recognize_icons(&mut class_box, &[], &[]);
+        assert_eq!(class_box.len(), 3);
+        assert!(class_box.iter().all(|s| s.properties.icon.is_none()));
+
+        // So are compartments around text, even with small strokes in them
+        let text = vec![TextRegion {
+            id: "t".to_string(),
+            text: "Customer".to_string(),
+            bounds: crate::ocr::TextBounds { x: 40.0, y: 10.0, width: 80.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 14.0,
+        }];
+        let mut labelled = rack(true);
+        recognize_icons(&mut labelled, &[], &text);
+        assert!(labelled.iter().all(|s| s.properties.icon.is_none()));
+    }
+
+    #[test]
+    fn test_wobbly_circle_is_not_a_cloud() {
+        let wobbly = outline("circle", |t| {
+            let r = 60.0 + 2.5 * (6.0 * t).cos();
+            (100.0 + r * t.cos(), 100.0 + r * t.sin())
+        });
+        let mut shapes = vec![test_shape("circle", ShapeType::Circle, (38.0, 38.0), (162.0, 162.0))];
+
+        recognize_icons(&mut shapes, &[wobbly], &[]);
+
+        assert!(shapes[0].properties.icon.is_none());
+    }
+
+    #[test]
+    fn test_icon_style_library_and_overrides() {
+        let mut config = IconStyleConfig { library: IconLibrary::Aws, ..Default::default() };
+        assert!(config.style_for(IconKind::Server).contains("mxgraph.aws4.ec2"));
+
+        config.overrides.insert(IconKind::Server, "shape=custom;".to_string());
+        assert_eq!(config.style_for(IconKind::Server), "shape=custom;");
+        assert!(IconStyleConfig::default()
+            .style_for(IconKind::Firewall)
+            .contains("mxgraph.networks.firewall"));
+    }
+}
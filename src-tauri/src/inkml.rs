@@ -0,0 +1,620 @@
+//! InkML import/export module
+//!
+//! Converts strokes to and from W3C InkML so ink can be exchanged with
+//! tablets and other ink tools. Point x/y/pressure/timestamp map to the
+//! X/Y/F/T channels, stroke colour/width/tool to brushes and stroke ids to
+//! trace ids.
+
+use crate::color::{self, Color};
+use crate::{Point, Stroke};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+const INKML_NS: &str = "http://www.w3.org/2003/InkML";
+
+/// Context for traces without pressure
+const CONTEXT_PLAIN: &str = "ctxXYT";
+
+/// Context for traces with a pressure (force) channel
+const CONTEXT_PRESSURE: &str = "ctxXYFT";
+
+/// Pressure written for points that lack one in a pressure trace
+const DEFAULT_PRESSURE: f64 = 0.5;
+
+/// Serialize strokes as an InkML document
+pub fn to_inkml(strokes: &[Stroke]) -> Result<String, String> {
+    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 2);
+
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .map_err(|e| e.to_string())?;
+
+    let mut ink = BytesStart::new("ink");
+    ink.push_attribute(("xmlns", INKML_NS));
+    writer.write_event(Event::Start(ink)).map_err(|e| e.to_string())?;
+
+    // Brushes are shared by strokes with the same colour, width and tool
+    let mut brushes: Vec<(String, f64, String)> = Vec::new();
+    let mut brush_refs = Vec::with_capacity(strokes.len());
+    for stroke in strokes {
+        let key = (stroke.color.clone(), stroke.width, stroke.tool.clone());
+        let index = match brushes.iter().position(|b| *b == key) {
+            Some(i) => i,
+            None => {
+                brushes.push(key);
+                brushes.len() - 1
+            }
+        };
+        brush_refs.push(index);
+    }
+
+    write_start(&mut writer, BytesStart::new("definitions"))?;
+    write_context(&mut writer, CONTEXT_PLAIN, false)?;
+    write_context(&mut writer, CONTEXT_PRESSURE, true)?;
+    for (i, (color, width, tool)) in brushes.iter().enumerate() {
+        write_brush(&mut writer, &format!("br{}", i), color, *width, tool)?;
+    }
+    write_end(&mut writer, "definitions")?;
+
+    for (stroke, brush) in strokes.iter().zip(brush_refs) {
+        let has_pressure = stroke.points.iter().any(|p| p.pressure.is_some());
+        let context = if has_pressure { CONTEXT_PRESSURE } else { CONTEXT_PLAIN };
+
+        let mut trace = BytesStart::new("trace");
+        trace.push_attribute(("xml:id", stroke.id.as_str()));
+        trace.push_attribute(("contextRef", format!("#{}", context).as_str()));
+        trace.push_attribute(("brushRef", format!("#br{}", brush).as_str()));
+        writer.write_event(Event::Start(trace)).map_err(|e| e.to_string())?;
+
+        let data: Vec<String> = stroke
+            .points
+            .iter()
+            .map(|p| {
+                if has_pressure {
+                    format!(
+                        "{} {} {} {}",
+                        p.x,
+                        p.y,
+                        p.pressure.unwrap_or(DEFAULT_PRESSURE),
+                        p.timestamp
+                    )
+                } else {
+                    format!("{} {} {}", p.x, p.y, p.timestamp)
+                }
+            })
+            .collect();
+        writer
+            .write_event(Event::Text(BytesText::new(&data.join(", "))))
+            .map_err(|e| e.to_string())?;
+        write_end(&mut writer, "trace")?;
+    }
+
+    write_end(&mut writer, "ink")?;
+
+    String::from_utf8(writer.into_inner().into_inner()).map_err(|e| e.to_string())
+}
+
+fn write_start(writer: &mut Writer<Cursor<Vec<u8>>>, element: BytesStart) -> Result<(), String> {
+    writer.write_event(Event::Start(element)).map_err(|e| e.to_string())
+}
+
+fn write_end(writer: &mut Writer<Cursor<Vec<u8>>>, name: &str) -> Result<(), String> {
+    writer.write_event(Event::End(BytesEnd::new(name))).map_err(|e| e.to_string())
+}
+
+fn write_context(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    id: &str,
+    pressure: bool,
+) -> Result<(), String> {
+    let mut context = BytesStart::new("context");
+    context.push_attribute(("xml:id", id));
+    write_start(writer, context)?;
+    write_start(writer, BytesStart::new("traceFormat"))?;
+
+    let mut channels = vec![("X", "decimal", None), ("Y", "decimal", None)];
+    if pressure {
+        channels.push(("F", "decimal", Some("1")));
+    }
+    channels.push(("T", "integer", None));
+
+    for (name, kind, max) in channels {
+        let mut channel = BytesStart::new("channel");
+        channel.push_attribute(("name", name));
+        channel.push_attribute(("type", kind));
+        if let Some(max) = max {
+            channel.push_attribute(("max", max));
+        }
+        if name == "T" {
+            channel.push_attribute(("units", "ms"));
+        }
+        writer.write_event(Event::Empty(channel)).map_err(|e| e.to_string())?;
+    }
+
+    write_end(writer, "traceFormat")?;
+    write_end(writer, "context")
+}
+
+fn write_brush(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    id: &str,
+    color: &str,
+    width: f64,
+    tool: &str,
+) -> Result<(), String> {
+    let mut brush = BytesStart::new("brush");
+    brush.push_attribute(("xml:id", id));
+    write_start(writer, brush)?;
+
+    // InkML colours are #RRGGBB; alpha goes into the transparency property
+    let color = color::ink(color);
+    let rgb = color.rgb_hex();
+    let transparency = (color.a < 255).then(|| 255 - color.a);
+
+    let width = width.to_string();
+    let transparency = transparency.map(|t| t.to_string());
+    let mut properties = vec![("color", rgb.as_str(), None), ("width", width.as_str(), Some("px"))];
+    if let Some(t) = &transparency {
+        properties.push(("transparency", t.as_str(), None));
+    }
+    properties.push(("tool", tool, None));
+
+    for (name, value, units) in properties {
+        let mut property = BytesStart::new("brushProperty");
+        property.push_attribute(("name", name));
+        property.push_attribute(("value", value));
+        if let Some(units) = units {
+            property.push_attribute(("units", units));
+        }
+        writer.write_event(Event::Empty(property)).map_err(|e| e.to_string())?;
+    }
+
+    write_end(writer, "brush")
+}
+
+/// Channel layout of a trace format
+#[derive(Debug, Clone)]
+struct TraceFormat {
+    channels: Vec<String>,
+    /// Upper bound of the F channel, used to normalize pressure to 0..1
+    force_max: f64,
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        Self { channels: vec!["X".to_string(), "Y".to_string()], force_max: 1.0 }
+    }
+}
+
+/// Brush properties that map onto a stroke
+#[derive(Debug, Clone)]
+struct Brush {
+    color: String,
+    width: f64,
+    tool: String,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self { color: "#000000".to_string(), width: 2.0, tool: "pen".to_string() }
+    }
+}
+
+/// Trace element being read
+struct PendingTrace {
+    id: Option<String>,
+    context: Option<String>,
+    brush: Option<String>,
+    data: String,
+}
+
+/// Parse an InkML document into strokes
+pub fn from_inkml(xml: &str) -> Result<Vec<Stroke>, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut formats: HashMap<String, TraceFormat> = HashMap::new();
+    let mut brushes: HashMap<String, Brush> = HashMap::new();
+    let mut default_format: Option<TraceFormat> = None;
+
+    // Element currently being defined
+    let mut current_format: Option<(Option<String>, TraceFormat)> = None;
+    let mut context_id: Option<String> = None;
+    let mut current_brush: Option<(String, Brush, Option<u8>)> = None;
+
+    // Inherited contextRef/brushRef from enclosing traceGroups
+    let mut groups: Vec<(Option<String>, Option<String>)> = Vec::new();
+    let mut trace: Option<PendingTrace> = None;
+    let mut strokes = Vec::new();
+    let mut saw_ink = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid InkML at byte {}: {}", reader.buffer_position(), e))?;
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+                let attrs = attributes(e)?;
+                match e.local_name().as_ref() {
+                    b"ink" => saw_ink = true,
+                    b"context" => context_id = attrs.get("id").cloned(),
+                    b"traceFormat" => {
+                        let id = attrs.get("id").cloned().or_else(|| context_id.clone());
+                        current_format = Some((id, TraceFormat { channels: Vec::new(), force_max: 1.0 }));
+                        if empty {
+                            current_format = None;
+                        }
+                    }
+                    b"channel" => {
+                        if let Some((_, format)) = current_format.as_mut() {
+                            let name = attrs.get("name").cloned().unwrap_or_default();
+                            if name == "F" {
+                                if let Some(max) = attrs.get("max").and_then(|m| m.parse::<f64>().ok()) {
+                                    format.force_max = max.max(f64::EPSILON);
+                                }
+                            }
+                            format.channels.push(name);
+                        }
+                    }
+                    b"brush" => {
+                        let id = attrs.get("id").cloned().unwrap_or_default();
+                        current_brush = Some((id, Brush::default(), None));
+                        if empty {
+                            if let Some((id, brush, _)) = current_brush.take() {
+                                brushes.insert(id, brush);
+                            }
+                        }
+                    }
+                    b"brushProperty" => {
+                        if let Some((_, brush, transparency)) = current_brush.as_mut() {
+                            let value = attrs.get("value").cloned().unwrap_or_default();
+                            match attrs.get("name").map(String::as_str) {
+                                Some("color") => {
+                                    brush.color = color::normalize(&value)
+                                        .ok_or_else(|| format!("Unsupported brush colour: {}", value))?
+                                }
+                                Some("width") => {
+                                    if let Some(width) = value.parse::<f64>().ok().filter(|w| w.is_finite()) {
+                                        brush.width = width;
+                                    }
+                                }
+                                Some("transparency") => *transparency = value.parse().ok(),
+                                Some("tool") => brush.tool = value,
+                                _ => {}
+                            }
+                        }
+                    }
+                    b"traceGroup" if !empty => {
+                        let inherited = groups.last().cloned().unwrap_or((None, None));
+                        groups.push((
+                            attrs.get("contextRef").cloned().or(inherited.0),
+                            attrs.get("brushRef").cloned().or(inherited.1),
+                        ));
+                    }
+                    b"trace" => {
+                        let inherited = groups.last().cloned().unwrap_or((None, None));
+                        let pending = PendingTrace {
+                            id: attrs.get("id").cloned(),
+                            context: attrs.get("contextRef").cloned().or(inherited.0),
+                            brush: attrs.get("brushRef").cloned().or(inherited.1),
+                            data: String::new(),
+                        };
+                        if empty {
+                            strokes.push(build_stroke(pending, &formats, &default_format, &brushes)?);
+                        } else {
+                            trace = Some(pending);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(ref t) => {
+                if let Some(pending) = trace.as_mut() {
+                    pending.data.push_str(&t.unescape().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::End(ref e) => match e.local_name().as_ref() {
+                b"context" => context_id = None,
+                b"traceFormat" => {
+                    if let Some((id, format)) = current_format.take() {
+                        match id {
+                            Some(id) => {
+                                formats.insert(id, format);
+                            }
+                            None => default_format = Some(format),
+                        }
+                    }
+                }
+                b"brush" => {
+                    if let Some((id, mut brush, transparency)) = current_brush.take() {
+                        if let Some(t) = transparency {
+                            let opaque = color::ink(&brush.color);
+                            brush.color = Color { a: 255 - t, ..opaque }.to_hex();
+                        }
+                        brushes.insert(id, brush);
+                    }
+                }
+                b"traceGroup" => {
+                    groups.pop();
+                }
+                b"trace" => {
+                    if let Some(pending) = trace.take() {
+                        strokes.push(build_stroke(pending, &formats, &default_format, &brushes)?);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !saw_ink {
+        return Err("Not an InkML document: missing <ink> root".to_string());
+    }
+
+    Ok(strokes)
+}
+
+/// Collect attributes by local name (so `xml:id` is found as `id`)
+fn attributes(element: &BytesStart) -> Result<HashMap<String, String>, String> {
+    let mut map = HashMap::new();
+    for attr in element.attributes() {
+        let attr = attr.map_err(|e| e.to_string())?;
+        let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).to_string();
+        let value = attr.unescape_value().map_err(|e| e.to_string())?.to_string();
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+/// Strip the leading `#` of an InkML URI reference
+fn reference(uri: &Option<String>) -> Option<&str> {
+    uri.as_deref().map(|r| r.trim_start_matches('#'))
+}
+
+fn build_stroke(
+    trace: PendingTrace,
+    formats: &HashMap<String, TraceFormat>,
+    default_format: &Option<TraceFormat>,
+    brushes: &HashMap<String, Brush>,
+) -> Result<Stroke, String> {
+    let format = reference(&trace.context)
+        .and_then(|id| formats.get(id))
+        .or(default_format.as_ref())
+        .cloned()
+        .unwrap_or_default();
+    let brush = reference(&trace.brush)
+        .and_then(|id| brushes.get(id))
+        .cloned()
+        .unwrap_or_default();
+
+    let channel = |name: &str| format.channels.iter().position(|c| c == name);
+    let (x, y) = match (channel("X"), channel("Y")) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err("InkML trace format has no X/Y channels".to_string()),
+    };
+    let force = channel("F");
+    let time = channel("T");
+
+    let id = trace.id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let values = decode_trace(&trace.data, format.channels.len())
+        .map_err(|e| format!("Trace {}: {}", id, e))?;
+    // Difference encodings can still overflow to infinity
+    if values.iter().flatten().any(|v| !v.is_finite()) {
+        return Err(format!("Trace {}: value out of range", id));
+    }
+
+    let points = values
+        .iter()
+        .map(|v| Point {
+            x: v[x],
+            y: v[y],
+            pressure: force.map(|f| (v[f] / format.force_max).clamp(0.0, 1.0)),
+            timestamp: time.map(|t| v[t].max(0.0).round() as u64).unwrap_or(0),
+        })
+        .collect();
+
+    Ok(Stroke {
+        id,
+        points,
+        color: brush.color,
+        width: brush.width,
+        tool: brush.tool,
+        layer_id: crate::layers::DEFAULT_LAYER_ID.to_string(),
+        curves: Vec::new(),
+    })
+}
+
+/// Value encoding of a channel in trace data
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Explicit,
+    FirstDifference,
+    SecondDifference,
+}
+
+/// Decode trace text into per-point channel values.
+///
+/// Handles explicit values as well as InkML's `'` (first difference) and
+/// `"` (second difference) prefixes, which persist per channel until changed.
+fn decode_trace(data: &str, channel_count: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut encodings = vec![Encoding::Explicit; channel_count];
+    let mut previous = vec![0.0; channel_count];
+    let mut velocity = vec![0.0; channel_count];
+    let mut points = Vec::new();
+
+    for (index, raw) in data.split(',').enumerate() {
+        let tokens = tokenize(raw)?;
+        if tokens.is_empty() {
+            continue;
+        }
+        if tokens.len() < channel_count {
+            return Err(format!(
+                "point {} has {} values, expected {}",
+                index,
+                tokens.len(),
+                channel_count
+            ));
+        }
+
+        let mut values = Vec::with_capacity(channel_count);
+        for (c, (marker, value)) in tokens.into_iter().take(channel_count).enumerate() {
+            if let Some(encoding) = marker {
+                encodings[c] = encoding;
+            }
+            let decoded = match encodings[c] {
+                Encoding::Explicit => {
+                    velocity[c] = if points.is_empty() { 0.0 } else { value - previous[c] };
+                    value
+                }
+                Encoding::FirstDifference => {
+                    velocity[c] = value;
+                    previous[c] + value
+                }
+                Encoding::SecondDifference => {
+                    velocity[c] += value;
+                    previous[c] + velocity[c]
+                }
+            };
+            previous[c] = decoded;
+            values.push(decoded);
+        }
+        points.push(values);
+    }
+
+    Ok(points)
+}
+
+/// Split one point's text into values and optional encoding markers
+fn tokenize(raw: &str) -> Result<Vec<(Option<Encoding>, f64)>, String> {
+    let mut tokens = Vec::new();
+    let mut marker = None;
+    let mut number = String::new();
+
+    let flush = |number: &mut String,
+                 marker: &mut Option<Encoding>,
+                 tokens: &mut Vec<(Option<Encoding>, f64)>|
+     -> Result<(), String> {
+        if !number.is_empty() {
+            // Rust also parses "inf" and "NaN", which InkML doesn't allow
+            let value = number
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| format!("invalid number '{}'", number))?;
+            tokens.push((marker.take(), value));
+            number.clear();
+        }
+        Ok(())
+    };
+
+    for ch in raw.chars() {
+        match ch {
+            '!' | '\'' | '"' => {
+                flush(&mut number, &mut marker, &mut tokens)?;
+                marker = Some(match ch {
+                    '!' => Encoding::Explicit,
+                    '\'' => Encoding::FirstDifference,
+                    _ => Encoding::SecondDifference,
+                });
+            }
+            // A sign that doesn't follow an exponent starts a new value
+            '-' | '+' if !number.is_empty() && !number.ends_with(['e', 'E']) => {
+                flush(&mut number, &mut marker, &mut tokens)?;
+                number.push(ch);
+            }
+            c if c.is_whitespace() => flush(&mut number, &mut marker, &mut tokens)?,
+            // Boolean channel values and wildcards are not used by strokes
+            'T' | 'F' | '?' | '*' => {
+                flush(&mut number, &mut marker, &mut tokens)?;
+                tokens.push((marker.take(), 0.0));
+            }
+            c => number.push(c),
+        }
+    }
+    flush(&mut number, &mut marker, &mut tokens)?;
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+
+    #[test]
+    fn test_round_trip() {
+        let strokes = vec![
+            Stroke {
+                color: "#ff000080".to_string(),
+                width: 3.0,
+                tool: "highlighter".to_string(),
+                ..test_stroke("stroke-1", vec![
+                    Point { x: 10.0, y: 20.0, pressure: Some(0.25), timestamp: 100 },
+                    Point { x: 12.5, y: 22.0, pressure: Some(0.75), timestamp: 116 },
+                ])
+            },
+            test_stroke("stroke-2", vec![Point { x: 1.0, y: 2.0, pressure: None, timestamp: 5 }]),
+        ];
+
+        let xml = to_inkml(&strokes).unwrap();
+        assert!(xml.contains("xml:id=\"stroke-1\""));
+
+        let parsed = from_inkml(&xml).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].id, "stroke-1");
+        assert_eq!(parsed[0].color, "#ff000080");
+        assert_eq!(parsed[0].width, 3.0);
+        assert_eq!(parsed[0].tool, "highlighter");
+        assert_eq!(parsed[0].points[1].x, 12.5);
+        assert_eq!(parsed[0].points[1].pressure, Some(0.75));
+        assert_eq!(parsed[0].points[1].timestamp, 116);
+        assert_eq!(parsed[1].points[0].pressure, None);
+    }
+
+    #[test]
+    fn test_difference_encoding_and_groups() {
+        let xml = r##"<ink xmlns="http://www.w3.org/2003/InkML">
+            <traceFormat>
+                <channel name="X" type="integer"/>
+                <channel name="Y" type="integer"/>
+                <channel name="F" type="integer" max="1024"/>
+            </traceFormat>
+            <brush xml:id="thick"><brushProperty name="width" value="6"/></brush>
+            <traceGroup brushRef="#thick">
+                <trace>10 20 512, '5'-5'0, "0"0"512</trace>
+            </traceGroup>
+        </ink>"##;
+
+        let strokes = from_inkml(xml).unwrap();
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].width, 6.0);
+
+        let coords: Vec<(f64, f64)> = strokes[0].points.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(coords, vec![(10.0, 20.0), (15.0, 15.0), (20.0, 10.0)]);
+        assert_eq!(strokes[0].points[0].pressure, Some(0.5));
+        assert_eq!(strokes[0].points[2].pressure, Some(1.0));
+    }
+
+    #[test]
+    fn test_rejects_non_finite_values() {
+        let trace = |data: &str| format!(r#"<ink><trace xml:id="t1">{}</trace></ink>"#, data);
+
+        let err = from_inkml(&trace("0 0, inf 5")).unwrap_err();
+        assert!(err.starts_with("Trace t1:"), "{}", err);
+        assert!(from_inkml(&trace("0 0, NaN 5")).is_err());
+        // Second differences that overflow
+        assert!(from_inkml(&trace("0 0, \"1e308\"0, \"1e308\"0, \"1e308\"0")).is_err());
+        assert!(from_inkml(&trace("0 0, 1e3 5")).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_non_inkml() {
+        assert!(from_inkml("<svg></svg>").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Layers module
+//!
+//! Named, ordered layers that strokes belong to. Layers can be hidden
+//! (excluded from analysis and rendering) or locked (protected from edits).
+
+use crate::shapes::DetectedShape;
+use crate::Stroke;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Id of the layer every board starts with; strokes saved before layers existed land here
+pub const DEFAULT_LAYER_ID: &str = "default";
+
+/// A named layer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub id: String,
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+}
+
+/// The board's layers, bottom to top
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerStack {
+    layers: Vec<Layer>,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self {
+            layers: vec![Layer {
+                id: DEFAULT_LAYER_ID.to_string(),
+                name: "Layer 1".to_string(),
+                visible: true,
+                locked: false,
+            }],
+        }
+    }
+}
+
+impl LayerStack {
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn get(&self, id: &str) -> Result<&Layer, String> {
+        self.layers
+            .iter()
+            .find(|l| l.id == id)
+            .ok_or_else(|| format!("Layer not found: {}", id))
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut Layer, String> {
+        self.layers
+            .iter_mut()
+            .find(|l| l.id == id)
+            .ok_or_else(|| format!("Layer not found: {}", id))
+    }
+
+    /// Add a new layer on top
+    pub fn create(&mut self, name: String) -> Layer {
+        let layer = Layer {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            visible: true,
+            locked: false,
+        };
+        self.layers.push(layer.clone());
+        layer
+    }
+
+    /// Reorder layers; `ids` lists every layer id, bottom to top
+    pub fn reorder(&mut self, ids: &[String]) -> Result<(), String> {
+        if ids.len() != self.layers.len() {
+            return Err(format!("Expected {} layer ids, got {}", self.layers.len(), ids.len()));
+        }
+        // Pick from a copy so a bad list leaves the stack as it was
+        let mut remaining = self.layers.clone();
+        let mut reordered = Vec::with_capacity(ids.len());
+        for id in ids {
+            let index = remaining.iter().position(|l| &l.id == id).ok_or_else(|| {
+                if self.get(id).is_ok() {
+                    format!("Layer listed twice: {}", id)
+                } else {
+                    format!("Layer not found: {}", id)
+                }
+            })?;
+            reordered.push(remaining.swap_remove(index));
+        }
+        self.layers = reordered;
+        Ok(())
+    }
+
+    pub fn set_visible(&mut self, id: &str, visible: bool) -> Result<Layer, String> {
+        let layer = self.get_mut(id)?;
+        layer.visible = visible;
+        Ok(layer.clone())
+    }
+
+    pub fn set_locked(&mut self, id: &str, locked: bool) -> Result<Layer, String> {
+        let layer = self.get_mut(id)?;
+        layer.locked = locked;
+        Ok(layer.clone())
+    }
+
+    /// Error unless strokes can be added to or changed on the layer
+    pub fn check_editable(&self, id: &str) -> Result<(), String> {
+        let layer = self.get(id)?;
+        if layer.locked {
+            return Err(format!("Layer is locked: {}", layer.name));
+        }
+        Ok(())
+    }
+
+    /// Whether a stroke on this layer may be edited; unknown layers count as editable
+    pub fn is_editable(&self, id: &str) -> bool {
+        !self.get(id).is_ok_and(|l| l.locked)
+    }
+
+    /// Add a layer for every stroke layer id the stack doesn't know, e.g. after loading a backup
+    pub fn ensure_layers(&mut self, strokes: &[Stroke]) {
+        for stroke in strokes {
+            if self.get(&stroke.layer_id).is_err() {
+                self.layers.push(Layer {
+                    id: stroke.layer_id.clone(),
+                    name: stroke.layer_id.clone(),
+                    visible: true,
+                    locked: false,
+                });
+            }
+        }
+    }
+
+    /// Strokes on the chosen layers (every visible layer when `ids` is `None`), in layer order
+    pub fn strokes_on(&self, strokes: &[Stroke], ids: Option<&[String]>) -> Result<Vec<Stroke>, String> {
+        let chosen: Vec<&str> = match ids {
+            Some(ids) => {
+                for id in ids {
+                    self.get(id)?;
+                }
+                self.layers
+                    .iter()
+                    .filter(|l| ids.contains(&l.id))
+                    .map(|l| l.id.as_str())
+                    .collect()
+            }
+            None => self.layers.iter().filter(|l| l.visible).map(|l| l.id.as_str()).collect(),
+        };
+
+        // Stable per-layer grouping keeps the drawing order within each layer
+        Ok(chosen
+            .iter()
+            .flat_map(|id| strokes.iter().filter(move |s| s.layer_id == *id))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Layer of each shape: the layer of its first stroke still on the board
+pub fn shape_layers(shapes: &[DetectedShape], strokes: &[Stroke]) -> HashMap<String, String> {
+    let stroke_layers: HashMap<&str, &str> = strokes
+        .iter()
+        .map(|s| (s.id.as_str(), s.layer_id.as_str()))
+        .collect();
+
+    shapes
+        .iter()
+        .filter_map(|shape| {
+            let layer = shape.stroke_ids.iter().find_map(|id| stroke_layers.get(id.as_str()))?;
+            Some((shape.id.clone(), layer.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+    use crate::Point;
+
+    fn stroke(id: &str, layer: &str) -> Stroke {
+        Stroke {
+            layer_id: layer.to_string(),
+            ..test_stroke(id, vec![Point { x: 0.0, y: 0.0, pressure: None, timestamp: 0 }])
+        }
+    }
+
+    #[test]
+    fn test_strokes_on_layers_follow_layer_order() {
+        let mut stack = LayerStack::default();
+        let notes = stack.create("Notes".to_string());
+        let strokes = vec![stroke("a", &notes.id), stroke("b", DEFAULT_LAYER_ID), stroke("c", &notes.id)];
+
+        let ids = |s: Vec<Stroke>| s.into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(stack.strokes_on(&strokes, None).unwrap()), vec!["b", "a", "c"]);
+
+        stack.reorder(&[notes.id.clone(), DEFAULT_LAYER_ID.to_string()]).unwrap();
+        assert_eq!(ids(stack.strokes_on(&strokes, None).unwrap()), vec!["a", "c", "b"]);
+
+        stack.set_visible(&notes.id, false).unwrap();
+        assert_eq!(ids(stack.strokes_on(&strokes, None).unwrap()), vec!["b"]);
+        assert_eq!(ids(stack.strokes_on(&strokes, Some(std::slice::from_ref(&notes.id))).unwrap()), vec!["a", "c"]);
+        assert!(stack.strokes_on(&strokes, Some(&["missing".to_string()])).is_err());
+    }
+
+    #[test]
+    fn test_locked_layer_rejects_edits() {
+        let mut stack = LayerStack::default();
+        stack.set_locked(DEFAULT_LAYER_ID, true).unwrap();
+        assert!(stack.check_editable(DEFAULT_LAYER_ID).is_err());
+        assert!(!stack.is_editable(DEFAULT_LAYER_ID));
+        assert!(stack.reorder(&[]).is_err());
+    }
+
+    #[test]
+    fn test_bad_reorder_keeps_layers() {
+        let mut stack = LayerStack::default();
+        let notes = stack.create("Notes".to_string());
+
+        assert!(stack.reorder(&[notes.id.clone(), notes.id.clone()]).is_err());
+        assert!(stack.reorder(&[notes.id.clone(), "missing".to_string()]).is_err());
+        let ids: Vec<&str> = stack.layers().iter().map(|l| l.id.as_str()).collect();
+        assert_eq!(ids, vec![DEFAULT_LAYER_ID, notes.id.as_str()]);
+    }
+}
@@ -3,9 +3,9 @@
 //! Provides diagram enhancement and formatting using local LLM inference
 //! or optional Ollama API integration.
 
-use crate::drawio::DiagramStructure;
+use crate::drawio::{DiagramStructure, StylePresets, LABEL_MARGIN};
 use crate::ocr::TextRegion;
-use crate::shapes::DetectedShape;
+use crate::shapes::{Annotation, DetectedShape};
 use serde::{Deserialize, Serialize};
 
 /// LLM configuration options
@@ -150,13 +150,21 @@ fn enhance_with_rules(
                 width: shape.bounds.width.max(80.0),
                 height: shape.bounds.height.max(40.0),
                 style: get_default_style(&shape.shape_type),
+                parent: None,
             });
         }
     }
 
+    // Free text becomes text or note nodes; leader lines become dashed note links
+    let annotations = crate::shapes::detect_annotations(shapes, text_regions, LABEL_MARGIN);
+    let leader_ids: Vec<String> = annotations
+        .iter()
+        .filter_map(|a| a.leader_shape_id.clone())
+        .collect();
+
     // Convert arrows/lines to edges
     for shape in shapes {
-        if is_connector_shape(&shape.shape_type) {
+        if is_connector_shape(&shape.shape_type) && !leader_ids.contains(&shape.id) {
             if let (Some(start), Some(end)) = (
                 &shape.properties.start_point,
                 &shape.properties.end_point,
@@ -171,11 +179,17 @@ fn enhance_with_rules(
                     target: target.unwrap_or_default(),
                     label: None,
                     style: get_edge_style(&shape.shape_type),
+                    waypoints: Vec::new(),
+                    source_point: None,
+                    target_point: None,
                 });
             }
         }
     }
 
+    // Added after the edges so connectors can't attach to annotation text
+    add_annotation_nodes(&mut structure, &annotations);
+
     // Apply layout improvements
     improve_layout(&mut structure);
 
@@ -315,13 +329,61 @@ fn create_basic_structure(
                 width: shape.bounds.width,
                 height: shape.bounds.height,
                 style: String::new(),
+                parent: None,
             });
         }
     }
 
+    let annotations = crate::shapes::detect_annotations(shapes, text_regions, LABEL_MARGIN);
+    add_annotation_nodes(&mut structure, &annotations);
+
     structure
 }
 
+/// Add annotation text as nodes, linking notes to their target shapes.
+///
+/// Targets that didn't become nodes (freeform shapes, icons) get no link,
+/// and their annotations are plain text.
+fn add_annotation_nodes(structure: &mut DiagramStructure, annotations: &[Annotation]) {
+    for annotation in annotations {
+        let target = annotation
+            .target_shape_id
+            .clone()
+            .filter(|id| structure.nodes.iter().any(|n| &n.id == id));
+        let font_size = format!("fontSize={:.0};", annotation.font_size);
+        let (shape_type, style) = if target.is_some() {
+            ("note", format!("{}{}", StylePresets::note(), font_size))
+        } else {
+            ("text", format!("{}{}", StylePresets::text(), font_size))
+        };
+
+        structure.nodes.push(DiagramNode {
+            id: annotation.text_id.clone(),
+            label: annotation.text.clone(),
+            shape_type: shape_type.to_string(),
+            x: annotation.bounds.x,
+            y: annotation.bounds.y,
+            width: annotation.bounds.width,
+            height: annotation.bounds.height,
+            style,
+            parent: None,
+        });
+
+        if let Some(target) = target {
+            structure.edges.push(DiagramEdge {
+                id: format!("{}-link", annotation.text_id),
+                source: annotation.text_id.clone(),
+                target,
+                label: None,
+                style: StylePresets::note_link().to_string(),
+                waypoints: Vec::new(),
+                source_point: None,
+                target_point: None,
+            });
+        }
+    }
+}
+
 /// Detect overall diagram type
 fn detect_diagram_type(shapes: &[DetectedShape], text_regions: &[TextRegion]) -> String {
     use crate::shapes::ShapeType;
@@ -409,15 +471,13 @@ fn get_edge_style(shape_type: &crate::shapes::ShapeType) -> String {
 
 /// Find text that belongs to a shape
 fn find_text_for_shape(shape: &DetectedShape, text_regions: &[TextRegion]) -> Option<String> {
-    // Find text regions that overlap with the shape bounds
-    let mut matching_texts = Vec::new();
-    
-    for text in text_regions {
-        if bounds_overlap(&shape.bounds, &text.bounds) {
-            matching_texts.push(text.text.clone());
-        }
-    }
-    
+    // Same rule as annotation detection, so no text is both a label and a note
+    let matching_texts: Vec<String> = text_regions
+        .iter()
+        .filter(|text| crate::shapes::text_labels_shape(shape, text, LABEL_MARGIN))
+        .map(|text| text.text.clone())
+        .collect();
+
     if matching_texts.is_empty() {
         None
     } else {
@@ -425,21 +485,6 @@ fn find_text_for_shape(shape: &DetectedShape, text_regions: &[TextRegion]) -> Op
     }
 }
 
-/// Check if two bounds overlap
-fn bounds_overlap(shape_bounds: &crate::shapes::ShapeBounds, text_bounds: &crate::ocr::TextBounds) -> bool {
-    let s_left = shape_bounds.x;
-    let s_right = shape_bounds.x + shape_bounds.width;
-    let s_top = shape_bounds.y;
-    let s_bottom = shape_bounds.y + shape_bounds.height;
-    
-    let t_left = text_bounds.x;
-    let t_right = text_bounds.x + text_bounds.width;
-    let t_top = text_bounds.y;
-    let t_bottom = text_bounds.y + text_bounds.height;
-    
-    s_left < t_right && s_right > t_left && s_top < t_bottom && s_bottom > t_top
-}
-
 /// Find node at a given point
 fn find_node_at_point(nodes: &[DiagramNode], point: (f64, f64)) -> Option<String> {
     for node in nodes {
@@ -523,6 +568,36 @@ mod tests {
         assert!(!is_container_shape(&ShapeType::Arrow));
     }
 
+    #[test]
+    fn test_annotations_only_link_to_nodes() {
+        use crate::ocr::TextBounds;
+        use crate::shapes::{test_shape, ShapeType};
+
+        let text = |id: &str, x: f64, y: f64| TextRegion {
+            id: id.to_string(),
+            text: id.to_string(),
+            bounds: TextBounds { x, y, width: 80.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 18.0,
+        };
+        let shapes = vec![
+            test_shape("box", ShapeType::Rectangle, (0.0, 100.0), (120.0, 160.0)),
+            test_shape("blob", ShapeType::Freeform, (400.0, 100.0), (520.0, 160.0)),
+            test_shape("leader", ShapeType::Line, (440.0, 245.0), (460.0, 160.0)),
+            // A real arrow from the box that stops just short of the note
+            test_shape("flow", ShapeType::Arrow, (60.0, 130.0), (440.0, 300.0)),
+        ];
+        let texts = vec![text("aside", 400.0, 250.0)];
+
+        let structure = enhance_with_rules(&shapes, &texts, "").unwrap();
+
+        let edge = |id: &str| structure.edges.iter().find(|e| e.id == id);
+        assert!(edge("aside-link").is_none());
+        assert_eq!(edge("flow").unwrap().target, "");
+        let aside = structure.nodes.iter().find(|n| n.id == "aside").unwrap();
+        assert_eq!(aside.shape_type, "text");
+    }
+
     #[test]
     fn test_is_connector_shape() {
         use crate::shapes::ShapeType;
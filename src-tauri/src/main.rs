@@ -6,12 +6,29 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod canvas;
+mod codec;
+mod color;
 mod drawio;
+mod drawio_import;
+mod editing;
+mod history;
+mod icons;
+mod inkml;
+mod layers;
 mod llm;
+mod noise;
 mod ocr;
+mod pdf;
+mod rectify;
+mod selection;
 mod shapes;
+mod svg;
+mod tiles;
+mod vectorize;
+mod wireframe;
 
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
 use tauri::State;
 
@@ -25,6 +42,18 @@ pub struct AppState {
     pub ocr_text: Mutex<Vec<ocr::TextRegion>>,
     /// LLM configuration
     pub llm_config: Mutex<llm::LlmConfig>,
+    /// Undo/redo log of board changes
+    pub history: Mutex<history::History>,
+    /// Board layers, bottom to top
+    pub layers: Mutex<layers::LayerStack>,
+    /// Bumped whenever what the board renders changes (strokes or layers)
+    pub content_version: AtomicU64,
+    /// Rendered viewport tiles for the current content version
+    pub tile_cache: Mutex<tiles::TileCache>,
+    /// When each stroke last changed, for incremental stroke fetches
+    pub sync: Mutex<codec::SyncTracker>,
+    /// Spatial index of the selectable strokes, rebuilt when the content version moves
+    pub selection_index: Mutex<Option<selection::IndexedStrokes>>,
 }
 
 impl Default for AppState {
@@ -34,12 +63,18 @@ impl Default for AppState {
             detected_shapes: Mutex::new(Vec::new()),
             ocr_text: Mutex::new(Vec::new()),
             llm_config: Mutex::new(llm::LlmConfig::default()),
+            history: Mutex::new(history::History::default()),
+            layers: Mutex::new(layers::LayerStack::default()),
+            content_version: AtomicU64::new(0),
+            tile_cache: Mutex::new(tiles::TileCache::default()),
+            sync: Mutex::new(codec::SyncTracker::default()),
+            selection_index: Mutex::new(None),
         }
     }
 }
 
 /// A single point in a stroke
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -48,13 +83,37 @@ pub struct Point {
 }
 
 /// A stroke consisting of multiple points
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Stroke {
     pub id: String,
     pub points: Vec<Point>,
     pub color: String,
     pub width: f64,
     pub tool: String,
+    /// Layer the stroke belongs to
+    #[serde(default = "default_layer_id")]
+    pub layer_id: String,
+    /// Cubic Bézier fit of the points, maintained by the backend.
+    ///
+    /// Never written out: it is refitted whenever strokes are added, loaded or imported.
+    #[serde(default, skip_serializing)]
+    pub curves: Vec<canvas::CubicBezier>,
+}
+
+impl Stroke {
+    /// Reject infinite or NaN coordinates and widths, which geometry code can't handle
+    pub fn check_finite(&self) -> Result<(), String> {
+        let finite = self.width.is_finite() && self.points.iter().all(|p| p.x.is_finite() && p.y.is_finite());
+        if finite {
+            Ok(())
+        } else {
+            Err(format!("Stroke {} has non-finite coordinates", self.id))
+        }
+    }
+}
+
+fn default_layer_id() -> String {
+    layers::DEFAULT_LAYER_ID.to_string()
 }
 
 /// Result of diagram processing
@@ -64,6 +123,9 @@ pub struct ProcessingResult {
     pub text_regions: Vec<ocr::TextRegion>,
     pub suggested_diagram_type: String,
     pub confidence: f64,
+    /// Strokes left out of analysis as likely accidental
+    #[serde(default)]
+    pub stray_strokes: Vec<noise::StrayStroke>,
 }
 
 /// Draw.io export options
@@ -74,6 +136,38 @@ pub struct ExportOptions {
     pub page_width: f64,
     pub page_height: f64,
     pub theme: String,
+    /// Diagram type to export as (e.g. "bpmn"); detected automatically when absent
+    #[serde(default)]
+    pub diagram_type: Option<String>,
+    /// Stencil library and overrides for recognized icons
+    #[serde(default)]
+    pub icon_styles: icons::IconStyleConfig,
+    /// Draw.io fill and stroke pairs that ink colours map to
+    #[serde(default)]
+    pub palette: color::Palette,
+    /// Whether cells take their colours from the theme, the palette or the ink itself
+    #[serde(default)]
+    pub ink_style: drawio::InkStyle,
+    /// Whether highlighter regions export as their own cells or as fills of the cells they cover
+    #[serde(default)]
+    pub highlight_style: drawio::HighlightStyle,
+}
+
+/// Test fixture: a light-theme 800x600 export with every option at its default
+#[cfg(test)]
+pub(crate) fn test_export_options() -> ExportOptions {
+    ExportOptions {
+        filename: "test".to_string(),
+        include_grid: false,
+        page_width: 800.0,
+        page_height: 600.0,
+        theme: "light".to_string(),
+        diagram_type: None,
+        icon_styles: icons::IconStyleConfig::default(),
+        palette: color::Palette::default(),
+        ink_style: drawio::InkStyle::default(),
+        highlight_style: drawio::HighlightStyle::default(),
+    }
 }
 
 // ============================================================================
@@ -84,25 +178,386 @@ pub struct ExportOptions {
 #[tauri::command]
 async fn add_stroke(
     state: State<'_, AppState>,
-    stroke: Stroke,
+    mut stroke: Stroke,
 ) -> Result<(), String> {
+    state.layers.lock().map_err(|e| e.to_string())?.check_editable(&stroke.layer_id)?;
+    color::Color::parse(&stroke.color)?;
+    stroke.check_finite()?;
+    stroke.curves = canvas::fit_stroke_curves(&stroke);
+
     let mut strokes = state.strokes.lock().map_err(|e| e.to_string())?;
     println!("[STROKE] add_stroke: id={}, points={}, tool={}", stroke.id, stroke.points.len(), stroke.tool);
+    let delta = history::Delta::added(strokes.len(), stroke.clone());
     strokes.push(stroke);
+    state.content_version.fetch_add(1, Ordering::SeqCst);
     println!("[STROKE] Total strokes now: {}", strokes.len());
+    state
+        .history
+        .lock()
+        .map_err(|e| e.to_string())?
+        .record(history::OperationKind::AddStroke, delta);
     Ok(())
 }
 
-/// Clear all strokes from the canvas
+/// Clear all strokes from the canvas.
+///
+/// Strokes on locked layers are kept, along with the shapes built from them.
 #[tauri::command]
 async fn clear_strokes(state: State<'_, AppState>) -> Result<(), String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let editable = |s: &Stroke| layer_stack.is_editable(&s.layer_id);
+
+    edit_board(&state, history::OperationKind::Clear, editable, |strokes, shapes, text| {
+        let cleared: std::collections::HashSet<String> =
+            strokes.iter().filter(|s| editable(s)).map(|s| s.id.clone()).collect();
+        strokes.retain(|s| !cleared.contains(&s.id));
+        if strokes.is_empty() {
+            shapes.clear();
+            text.clear();
+        } else {
+            editing::invalidate_shapes(shapes, &cleared);
+        }
+        Ok(())
+    })
+}
+
+/// Delete strokes by id; returns how many were removed.
+///
+/// Strokes on locked layers are kept.
+#[tauri::command]
+async fn delete_strokes(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+) -> Result<usize, String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let requested: std::collections::HashSet<String> = ids.iter().cloned().collect();
+
+    edit_board(&state, history::OperationKind::DeleteStrokes, |s| requested.contains(&s.id), |strokes, shapes, _| {
+        let ids = editable_ids(&layer_stack, strokes, ids);
+        let before = strokes.len();
+        strokes.retain(|s| !ids.contains(&s.id));
+        let removed = before - strokes.len();
+
+        let invalidated = editing::invalidate_shapes(shapes, &ids);
+        println!("[STROKE] delete_strokes: removed {} strokes, invalidated {} shapes", removed, invalidated);
+        Ok(removed)
+    })
+}
+
+/// Replace a stored stroke with an edited version (matched by id)
+#[tauri::command]
+async fn update_stroke(
+    state: State<'_, AppState>,
+    mut stroke: Stroke,
+) -> Result<(), String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    layer_stack.check_editable(&stroke.layer_id)?;
+    color::Color::parse(&stroke.color)?;
+    stroke.check_finite()?;
+    stroke.curves = canvas::fit_stroke_curves(&stroke);
+
+    edit_board(&state, history::OperationKind::UpdateStroke, |s| s.id == stroke.id, |strokes, shapes, _| {
+        let existing = strokes
+            .iter_mut()
+            .find(|s| s.id == stroke.id)
+            .ok_or_else(|| format!("Stroke not found: {}", stroke.id))?;
+        layer_stack.check_editable(&existing.layer_id)?;
+        *existing = stroke.clone();
+
+        editing::invalidate_shapes(shapes, &std::collections::HashSet::from([stroke.id.clone()]));
+        Ok(())
+    })
+}
+
+/// Erase under an eraser circle or path, splitting strokes into remaining segments.
+///
+/// Only strokes on visible, unlocked layers are erased.
+#[tauri::command]
+async fn erase_at(
+    state: State<'_, AppState>,
+    eraser: editing::Eraser,
+) -> Result<editing::EraseResult, String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+
+    edit_board(&state, history::OperationKind::Erase, |s| eraser.may_reach(s), |strokes, shapes, _| {
+        let result = editing::erase(strokes, &eraser, |s| {
+            layer_stack.is_editable(&s.layer_id) && layer_stack.get(&s.layer_id).is_ok_and(|l| l.visible)
+        });
+
+        if !result.removed_ids.is_empty() {
+            let ids = result.removed_ids.iter().cloned().collect();
+            let invalidated = editing::invalidate_shapes(shapes, &ids);
+            println!(
+                "[STROKE] erase_at: split {} strokes into {} segments, invalidated {} shapes",
+                result.removed_ids.len(),
+                result.added.len(),
+                invalidated
+            );
+        }
+
+        Ok(result)
+    })
+}
+
+/// Select strokes inside a rectangle
+#[tauri::command]
+async fn select_strokes_in_rect(
+    state: State<'_, AppState>,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    mode: Option<selection::Containment>,
+) -> Result<Vec<String>, String> {
+    with_selection_index(&state, |strokes, index| {
+        selection::select_in_rect(strokes, index, x, y, width, height, mode.unwrap_or_default())
+    })
+}
+
+/// Select strokes inside a lasso polygon
+#[tauri::command]
+async fn select_strokes_in_polygon(
+    state: State<'_, AppState>,
+    points: Vec<(f64, f64)>,
+    mode: Option<selection::Containment>,
+) -> Result<Vec<String>, String> {
+    with_selection_index(&state, |strokes, index| {
+        selection::select_in_polygon(strokes, index, &points, mode.unwrap_or_default())
+    })
+}
+
+/// Move, scale or rotate strokes (or copies of them) and their detected shapes
+#[tauri::command]
+async fn transform_strokes(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+    matrix: selection::Affine,
+    duplicate: Option<bool>,
+) -> Result<selection::TransformResult, String> {
+    let duplicate = duplicate.unwrap_or(false);
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let requested: std::collections::HashSet<String> = ids.iter().cloned().collect();
+
+    edit_board(&state, history::OperationKind::Transform, |s| requested.contains(&s.id), |strokes, shapes, _| {
+        let ids = editable_ids(&layer_stack, strokes, ids);
+        let result = selection::transform_strokes(strokes, shapes, &ids, &matrix, duplicate);
+        println!(
+            "[STROKE] transform_strokes: {} strokes, {} shapes (duplicate={})",
+            result.strokes.len(),
+            result.shape_ids.len(),
+            duplicate
+        );
+        Ok(result)
+    })
+}
+
+/// Run a selection query against the selectable strokes, reusing their
+/// spatial index until the board content changes
+fn with_selection_index<R>(
+    state: &AppState,
+    select: impl FnOnce(&[Stroke], &selection::SpatialIndex) -> R,
+) -> Result<R, String> {
+    let mut cache = state.selection_index.lock().map_err(|e| e.to_string())?;
+    // Read the version first: a change racing with this call then only makes the cache stale early
+    let version = state.content_version.load(Ordering::SeqCst);
+    let indexed = match cache.take() {
+        Some(indexed) if indexed.version == version => indexed,
+        _ => {
+            let strokes = selectable_strokes(state)?;
+            let index = selection::SpatialIndex::build(&strokes);
+            selection::IndexedStrokes { version, strokes, index }
+        }
+    };
+    let indexed = cache.insert(indexed);
+    Ok(select(&indexed.strokes, &indexed.index))
+}
+
+/// Strokes on visible, unlocked layers
+fn selectable_strokes(state: &AppState) -> Result<Vec<Stroke>, String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+    Ok(layer_stack
+        .strokes_on(&strokes, None)?
+        .into_iter()
+        .filter(|s| layer_stack.is_editable(&s.layer_id))
+        .collect())
+}
+
+/// The requested stroke ids, minus strokes on locked layers
+fn editable_ids(
+    layer_stack: &layers::LayerStack,
+    strokes: &[Stroke],
+    ids: Vec<String>,
+) -> std::collections::HashSet<String> {
+    let locked: std::collections::HashSet<&str> = strokes
+        .iter()
+        .filter(|s| !layer_stack.is_editable(&s.layer_id))
+        .map(|s| s.id.as_str())
+        .collect();
+    ids.into_iter().filter(|id| !locked.contains(id.as_str())).collect()
+}
+
+/// Apply a board edit under the state locks and record it in the undo log.
+///
+/// `touches` must match every stroke the edit may remove or replace; only
+/// those are kept aside and diffed against the result, so small edits don't
+/// copy the whole board. Strokes the edit adds are found by their new ids.
+/// Analysis results are recorded too when the edit changed them.
+fn edit_board<R>(
+    state: &AppState,
+    kind: history::OperationKind,
+    touches: impl Fn(&Stroke) -> bool,
+    edit: impl FnOnce(
+        &mut Vec<Stroke>,
+        &mut Vec<shapes::DetectedShape>,
+        &mut Vec<ocr::TextRegion>,
+    ) -> Result<R, String>,
+) -> Result<R, String> {
     let mut strokes = state.strokes.lock().map_err(|e| e.to_string())?;
-    strokes.clear();
     let mut shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
-    shapes.clear();
     let mut text = state.ocr_text.lock().map_err(|e| e.to_string())?;
-    text.clear();
-    Ok(())
+
+    let ids_before: std::collections::HashSet<String> = strokes.iter().map(|s| s.id.clone()).collect();
+    let touched_before: Vec<history::IndexedStroke> = strokes
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| touches(s))
+        .map(|(index, s)| history::IndexedStroke { index, stroke: s.clone() })
+        .collect();
+    let analysis_before = history::AnalysisSnapshot {
+        shapes: shapes.clone(),
+        text_regions: text.clone(),
+    };
+
+    let result = edit(&mut strokes, &mut shapes, &mut text)?;
+
+    let mut delta = history::Delta::between_touched(touched_before, &ids_before, &strokes);
+    if analysis_before.shapes != *shapes || analysis_before.text_regions != *text {
+        let analysis_after = history::AnalysisSnapshot {
+            shapes: shapes.clone(),
+            text_regions: text.clone(),
+        };
+        delta = delta.with_analysis(analysis_before, analysis_after);
+    }
+
+    state.content_version.fetch_add(1, Ordering::SeqCst);
+    state.history.lock().map_err(|e| e.to_string())?.record(kind, delta);
+    Ok(result)
+}
+
+/// Board contents after an undo or redo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardSnapshot {
+    pub strokes: Vec<Stroke>,
+    pub shapes: Vec<shapes::DetectedShape>,
+    pub text_regions: Vec<ocr::TextRegion>,
+    pub history: history::HistorySummary,
+}
+
+/// Step through the undo log in either direction
+fn step_history(state: &AppState, forward: bool) -> Result<BoardSnapshot, String> {
+    let mut layer_stack = state.layers.lock().map_err(|e| e.to_string())?;
+    let mut strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+    let mut shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
+    let mut text = state.ocr_text.lock().map_err(|e| e.to_string())?;
+    let mut log = state.history.lock().map_err(|e| e.to_string())?;
+
+    // Stepping must not change strokes on locked layers, so refuse the whole entry
+    if let Some(entry) = log.next_entry(forward) {
+        let delta = &entry.delta;
+        if let Some(s) = delta
+            .removed
+            .iter()
+            .chain(&delta.added)
+            .find(|s| !layer_stack.is_editable(&s.stroke.layer_id))
+        {
+            layer_stack.check_editable(&s.stroke.layer_id)?;
+        }
+    }
+
+    let mut board = history::Board {
+        strokes: &mut strokes,
+        shapes: &mut shapes,
+        text_regions: &mut text,
+    };
+    let kind = if forward { log.redo(&mut board) } else { log.undo(&mut board) };
+    // Restored strokes may sit on layers that were removed since; keep them reachable
+    layer_stack.ensure_layers(&strokes);
+    state.content_version.fetch_add(1, Ordering::SeqCst);
+    println!("[HISTORY] {} {:?}", if forward { "redo" } else { "undo" }, kind);
+
+    Ok(BoardSnapshot {
+        strokes: strokes.clone(),
+        shapes: shapes.clone(),
+        text_regions: text.clone(),
+        history: log.summary(),
+    })
+}
+
+/// Undo the most recent board change
+#[tauri::command]
+async fn undo(state: State<'_, AppState>) -> Result<BoardSnapshot, String> {
+    step_history(&state, false)
+}
+
+/// Redo the most recently undone board change
+#[tauri::command]
+async fn redo(state: State<'_, AppState>) -> Result<BoardSnapshot, String> {
+    step_history(&state, true)
+}
+
+/// List the recorded operations
+#[tauri::command]
+async fn get_history(state: State<'_, AppState>) -> Result<history::HistorySummary, String> {
+    Ok(state.history.lock().map_err(|e| e.to_string())?.summary())
+}
+
+/// List the board's layers, bottom to top
+#[tauri::command]
+async fn get_layers(state: State<'_, AppState>) -> Result<Vec<layers::Layer>, String> {
+    Ok(state.layers.lock().map_err(|e| e.to_string())?.layers().to_vec())
+}
+
+/// Add a layer on top of the stack
+#[tauri::command]
+async fn create_layer(state: State<'_, AppState>, name: String) -> Result<layers::Layer, String> {
+    let layer = state.layers.lock().map_err(|e| e.to_string())?.create(name);
+    println!("[LAYER] Created layer {} ({})", layer.name, layer.id);
+    Ok(layer)
+}
+
+/// Reorder the layers; `ids` lists every layer, bottom to top
+#[tauri::command]
+async fn reorder_layers(state: State<'_, AppState>, ids: Vec<String>) -> Result<Vec<layers::Layer>, String> {
+    let mut layer_stack = state.layers.lock().map_err(|e| e.to_string())?;
+    layer_stack.reorder(&ids)?;
+    state.content_version.fetch_add(1, Ordering::SeqCst);
+    Ok(layer_stack.layers().to_vec())
+}
+
+/// Show or hide a layer
+#[tauri::command]
+async fn set_layer_visible(
+    state: State<'_, AppState>,
+    id: String,
+    visible: bool,
+) -> Result<layers::Layer, String> {
+    let layer = state.layers.lock().map_err(|e| e.to_string())?.set_visible(&id, visible)?;
+    state.content_version.fetch_add(1, Ordering::SeqCst);
+    Ok(layer)
+}
+
+/// Lock or unlock a layer against edits
+#[tauri::command]
+async fn set_layer_locked(
+    state: State<'_, AppState>,
+    id: String,
+    locked: bool,
+) -> Result<layers::Layer, String> {
+    let layer = state.layers.lock().map_err(|e| e.to_string())?.set_locked(&id, locked)?;
+    // Locking doesn't change the rendered content, but it does change what can be selected
+    *state.selection_index.lock().map_err(|e| e.to_string())? = None;
+    Ok(layer)
 }
 
 /// Get all current strokes
@@ -112,6 +567,31 @@ async fn get_strokes(state: State<'_, AppState>) -> Result<Vec<Stroke>, String>
     Ok(strokes.clone())
 }
 
+/// Strokes changed since `version`, in the compact binary sync format.
+///
+/// Pass the version from the previous call; without one, or when it is too
+/// old to diff against, every stroke is sent.
+#[tauri::command]
+async fn get_strokes_since(
+    state: State<'_, AppState>,
+    version: Option<u64>,
+) -> Result<tauri::ipc::Response, String> {
+    let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+    // Stroke edits bump the version while holding the strokes lock, so this can't race them
+    let current = state.content_version.load(Ordering::SeqCst);
+    let sync = state.sync.lock().map_err(|e| e.to_string())?.since(&strokes, current, version);
+    drop(strokes);
+
+    println!(
+        "[SYNC] get_strokes_since({:?}): {} changed, {} removed, full={}",
+        version,
+        sync.changed.len(),
+        sync.removed.len(),
+        sync.full
+    );
+    Ok(tauri::ipc::Response::new(codec::encode_sync(&sync)))
+}
+
 /// Process the canvas strokes to detect shapes and text
 #[tauri::command]
 async fn process_canvas(
@@ -119,6 +599,8 @@ async fn process_canvas(
     image_data: String,
     width: u32,
     height: u32,
+    layers: Option<Vec<String>>,
+    diagram_type: Option<String>,
 ) -> Result<ProcessingResult, String> {
     println!("[PROCESS] process_canvas called with image {}x{}", width, height);
     
@@ -134,28 +616,124 @@ async fn process_canvas(
         .map_err(|e| format!("Failed to load image: {}", e))?;
 
     // Get strokes for shape detection
+    let strokes = strokes_on_layers(&state, layers.as_deref())?;
+    println!("[PROCESS] Found {} strokes on the analyzed layers", strokes.len());
+
+    // Perform OCR on the image
+    let text_regions = ocr::extract_text(&img, width, height);
+    println!("[PROCESS] Found {} text regions", text_regions.len());
+
+    run_analysis(&state, &strokes, text_regions, diagram_type.as_deref())
+}
+
+/// Rasterize the stored strokes on the backend and analyze them.
+///
+/// OCR and shape detection share one coordinate system: text boxes found in
+/// the raster are mapped back into stroke coordinates before analysis.
+#[tauri::command]
+async fn analyze_strokes(
+    state: State<'_, AppState>,
+    scale: Option<f64>,
+    layers: Option<Vec<String>>,
+    diagram_type: Option<String>,
+) -> Result<ProcessingResult, String> {
+    let scale = scale.unwrap_or(2.0);
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(format!("Invalid render scale: {}", scale));
+    }
+    println!("[ANALYZE] analyze_strokes called with scale {:.2}", scale);
+
+    let strokes = strokes_on_layers(&state, layers.as_deref())?;
+    println!("[ANALYZE] Found {} strokes on the analyzed layers", strokes.len());
+
+    let mut text_regions = Vec::new();
+    if let Some((img, transform)) = canvas::render_strokes_for_analysis(&strokes, scale, 20.0) {
+        println!(
+            "[ANALYZE] Rendered {}x{} raster at scale {:.2}",
+            img.width(),
+            img.height(),
+            transform.scale
+        );
+        text_regions = ocr::extract_text(&img, img.width(), img.height());
+        for region in &mut text_regions {
+            transform.text_region_to_canvas(region);
+        }
+    }
+    println!("[ANALYZE] Found {} text regions", text_regions.len());
+
+    run_analysis(&state, &strokes, text_regions, diagram_type.as_deref())
+}
+
+/// Strokes on the chosen layers, or on every visible layer
+fn strokes_on_layers(state: &AppState, layers: Option<&[String]>) -> Result<Vec<Stroke>, String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
     let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
-    println!("[PROCESS] Found {} strokes in state", strokes.len());
+    layer_stack.strokes_on(&strokes, layers)
+}
+
+/// Bring the tile cache up to the current content version, reading the visible strokes only when it moved
+fn sync_tile_cache(state: &AppState, cache: &mut tiles::TileCache) -> Result<(), String> {
+    // Read the version first: a change racing with this call then only makes the cache stale early
+    let version = state.content_version.load(Ordering::SeqCst);
+    cache.sync(version, || strokes_on_layers(state, None))
+}
+
+/// Shape detection, enrichment and classification shared by the analysis commands.
+///
+/// `requested_type` is the diagram type the user picked, if any; it turns on
+/// the BPMN and wireframe passes even when the board alone is not conclusive.
+fn run_analysis(
+    state: &AppState,
+    strokes: &[Stroke],
+    text_regions: Vec<ocr::TextRegion>,
+    requested_type: Option<&str>,
+) -> Result<ProcessingResult, String> {
+    // Leave out strokes that look accidental; they are reported so the UI can offer to delete them
+    let stray_strokes = noise::find_stray_strokes(strokes, &text_regions, &noise::NoiseParams::default());
+    let stray_ids: std::collections::HashSet<&str> = stray_strokes.iter().map(|s| s.stroke_id.as_str()).collect();
+    let strokes: Vec<Stroke> = strokes.iter().filter(|s| !stray_ids.contains(s.id.as_str())).cloned().collect();
+    let strokes = strokes.as_slice();
+    println!("[PROCESS] Left out {} likely accidental strokes", stray_strokes.len());
 
     // Detect shapes from strokes
-    let detected_shapes = shapes::detect_shapes(&strokes);
+    let mut detected_shapes = shapes::detect_shapes(strokes);
+    shapes::annotate_bpmn(&mut detected_shapes, strokes, requested_type == Some("bpmn"));
     println!("[PROCESS] Detected {} shapes", detected_shapes.len());
-    
-    // Store detected shapes
+
+    // Fold multi-stroke icons (databases, clouds, servers, firewalls) into single shapes
+    icons::recognize_icons(&mut detected_shapes, strokes, &text_regions);
+
+    // Tag UI mockup elements (placeholders, buttons, inputs, checkboxes)
+    wireframe::annotate_wireframe(&mut detected_shapes, &text_regions, requested_type == Some("wireframe"));
+
+    // Group shapes into swimlanes (lane headers come from the OCR text)
+    shapes::detect_swimlanes(&mut detected_shapes, &text_regions);
+
+    // Highlighter strokes become emphasis regions behind the shapes they cover
+    shapes::detect_emphasis(&mut detected_shapes, strokes);
+
+    // Store detected shapes and OCR results, keeping the previous ones for undo
     {
         let mut shapes_state = state.detected_shapes.lock().map_err(|e| e.to_string())?;
-        *shapes_state = detected_shapes.clone();
+        let mut ocr_state = state.ocr_text.lock().map_err(|e| e.to_string())?;
+        let before = history::AnalysisSnapshot {
+            shapes: std::mem::replace(&mut *shapes_state, detected_shapes.clone()),
+            text_regions: std::mem::replace(&mut *ocr_state, text_regions.clone()),
+        };
         println!("[PROCESS] Stored {} shapes in state", shapes_state.len());
-    }
 
-    // Perform OCR on the image
-    let text_regions = ocr::extract_text(&img, width, height);
-    println!("[PROCESS] Found {} text regions", text_regions.len());
-    
-    // Store OCR results
-    {
-        let mut ocr_state = state.ocr_text.lock().map_err(|e| e.to_string())?;
-        *ocr_state = text_regions.clone();
+        let after = history::AnalysisSnapshot {
+            shapes: detected_shapes.clone(),
+            text_regions: text_regions.clone(),
+        };
+        state
+            .history
+            .lock()
+            .map_err(|e| e.to_string())?
+            .record(
+                history::OperationKind::Analysis,
+                history::Delta::default().with_analysis(before, after),
+            );
     }
 
     // Determine diagram type
@@ -167,6 +745,7 @@ async fn process_canvas(
         text_regions,
         suggested_diagram_type: diagram_type,
         confidence,
+        stray_strokes,
     })
 }
 
@@ -203,8 +782,11 @@ async fn generate_drawio(
     state: State<'_, AppState>,
     options: ExportOptions,
 ) -> Result<String, String> {
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
     let shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
     let text_regions = state.ocr_text.lock().map_err(|e| e.to_string())?;
+    let shape_layers = layers::shape_layers(&shapes, &strokes);
 
     println!("[DRAWIO] generate_drawio: {} shapes, {} text_regions", shapes.len(), text_regions.len());
     for shape in shapes.iter() {
@@ -213,7 +795,12 @@ async fn generate_drawio(
             shape.bounds.width, shape.bounds.height);
     }
 
-    let result = drawio::generate_xml(&shapes, &text_regions, &options);
+    let board = drawio::BoardContext {
+        layers: layer_stack.layers(),
+        shape_layers,
+        strokes: &strokes,
+    };
+    let result = drawio::generate_xml(&shapes, &text_regions, &board, &options);
     match &result {
         Ok(xml) => println!("[DRAWIO] Generated XML length: {} bytes", xml.len()),
         Err(e) => println!("[DRAWIO] Error generating XML: {}", e),
@@ -244,6 +831,114 @@ async fn export_drawio_file(
     Ok(())
 }
 
+/// Export the recognized wireframe as a skeleton HTML page
+#[tauri::command]
+async fn export_wireframe_html(
+    state: State<'_, AppState>,
+    path: String,
+    title: Option<String>,
+) -> Result<(), String> {
+    let html = {
+        let shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
+        let text_regions = state.ocr_text.lock().map_err(|e| e.to_string())?;
+        wireframe::generate_html(&shapes, &text_regions, title.as_deref().unwrap_or("Wireframe"))
+    };
+
+    std::fs::write(&path, html).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    println!("[EXPORT] Wrote wireframe HTML to {}", path);
+    Ok(())
+}
+
+/// Export the raw strokes as an SVG document
+#[tauri::command]
+async fn export_svg(
+    state: State<'_, AppState>,
+    path: String,
+    options: Option<svg::SvgOptions>,
+) -> Result<(), String> {
+    let svg = {
+        let strokes = strokes_on_layers(&state, None)?;
+        svg::generate_svg(&strokes, &options.unwrap_or_default())
+    };
+
+    std::fs::write(&path, svg).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    println!("[EXPORT] Wrote SVG to {}", path);
+    Ok(())
+}
+
+/// Render the PNG tiles covering a world-space viewport at a zoom level (2^zoom pixels per unit)
+#[tauri::command]
+async fn render_tiles(
+    state: State<'_, AppState>,
+    viewport: tiles::Viewport,
+    zoom: i32,
+    background: Option<String>,
+) -> Result<Vec<tiles::RenderedTile>, String> {
+    let mut cache = state.tile_cache.lock().map_err(|e| e.to_string())?;
+    sync_tile_cache(&state, &mut cache)?;
+    cache.render_tiles(
+        &viewport,
+        zoom,
+        color::Color::parse(background.as_deref().unwrap_or("#00000000"))?,
+    )
+}
+
+/// Export a world-space region of the board as a PNG, assembled from tiles
+#[tauri::command]
+async fn export_png(
+    state: State<'_, AppState>,
+    path: String,
+    viewport: tiles::Viewport,
+    zoom: i32,
+    background: Option<String>,
+) -> Result<(), String> {
+    let img = {
+        let mut cache = state.tile_cache.lock().map_err(|e| e.to_string())?;
+        sync_tile_cache(&state, &mut cache)?;
+        cache.render_viewport(
+            &viewport,
+            zoom,
+            color::Color::parse(background.as_deref().unwrap_or("#ffffff"))?,
+        )?
+    };
+
+    img.save_with_format(&path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+
+    println!("[EXPORT] Wrote {}x{} PNG to {}", img.width(), img.height(), path);
+    Ok(())
+}
+
+/// Export the raw strokes as a PDF, optionally with the recognized shapes
+#[tauri::command]
+async fn export_pdf(
+    state: State<'_, AppState>,
+    path: String,
+    options: ExportOptions,
+    shape_layer: Option<pdf::ShapeLayer>,
+) -> Result<(), String> {
+    let bytes = {
+        let strokes = strokes_on_layers(&state, None)?;
+        let shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
+        let text_regions = state.ocr_text.lock().map_err(|e| e.to_string())?;
+        pdf::generate_pdf(
+            &strokes,
+            &shapes,
+            &text_regions,
+            options.page_width,
+            options.page_height,
+            shape_layer.unwrap_or_default(),
+        )?
+    };
+
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    println!("[EXPORT] Wrote PDF to {}", path);
+    Ok(())
+}
+
 /// Configure LLM settings
 #[tauri::command]
 async fn configure_llm(
@@ -255,7 +950,7 @@ async fn configure_llm(
     Ok(())
 }
 
-/// Save canvas state as JSON backup
+/// Save canvas state as a gzipped backup: binary strokes, then the undo log and layers as JSON
 #[tauri::command]
 async fn save_backup(
     state: State<'_, AppState>,
@@ -265,14 +960,23 @@ async fn save_backup(
     use flate2::Compression;
     use std::io::Write;
 
-    let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
-    let json = serde_json::to_string(&*strokes)
-        .map_err(|e| format!("Failed to serialize: {}", e))?;
+    let layer_stack = state.layers.lock().map_err(|e| e.to_string())?.clone();
+    let bytes = {
+        let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+        let history = state.history.lock().map_err(|e| e.to_string())?;
+        let metadata = BoardMetadata {
+            history: Some(history.clone()),
+            layers: Some(layer_stack),
+        };
+        let json = serde_json::to_vec(&metadata)
+            .map_err(|e| format!("Failed to serialize: {}", e))?;
+        codec::encode_backup(&strokes, &json)
+    };
 
     let file = std::fs::File::create(&path)
         .map_err(|e| format!("Failed to create file: {}", e))?;
     let mut encoder = GzEncoder::new(file, Compression::default());
-    encoder.write_all(json.as_bytes())
+    encoder.write_all(&bytes)
         .map_err(|e| format!("Failed to write: {}", e))?;
     encoder.finish()
         .map_err(|e| format!("Failed to finish compression: {}", e))?;
@@ -280,7 +984,7 @@ async fn save_backup(
     Ok(())
 }
 
-/// Load canvas state from JSON backup
+/// Load canvas state from a backup, binary or JSON
 #[tauri::command]
 async fn load_backup(
     state: State<'_, AppState>,
@@ -292,17 +996,238 @@ async fn load_backup(
     let file = std::fs::File::open(&path)
         .map_err(|e| format!("Failed to open file: {}", e))?;
     let mut decoder = GzDecoder::new(file);
-    let mut json = String::new();
-    decoder.read_to_string(&mut json)
+    let mut contents = Vec::new();
+    decoder.read_to_end(&mut contents)
         .map_err(|e| format!("Failed to read: {}", e))?;
 
-    let strokes: Vec<Stroke> = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to deserialize: {}", e))?;
+    let board = if codec::is_binary_backup(&contents) {
+        let (strokes, json) = codec::decode_backup(&contents)?;
+        let metadata: BoardMetadata = serde_json::from_slice(json)
+            .map_err(|e| format!("Failed to deserialize: {}", e))?;
+        BoardFile { strokes, history: metadata.history, layers: metadata.layers }
+    } else {
+        match serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to deserialize: {}", e))?
+        {
+            BackupContents::Board(board) => board,
+            BackupContents::Legacy(strokes) => BoardFile { strokes, history: None, layers: None },
+        }
+    };
+    let mut strokes = board.strokes;
+    // Backups only carry points; fitted curves are rebuilt here
+    for stroke in strokes.iter_mut().filter(|s| s.curves.is_empty()) {
+        stroke.curves = canvas::fit_stroke_curves(stroke);
+    }
+    // Older backups may hold colours in any CSS form, or ones we can't read
+    let mut unreadable = 0;
+    for stroke in strokes.iter_mut() {
+        match color::normalize(&stroke.color) {
+            Some(hex) => stroke.color = hex,
+            None => unreadable += 1,
+        }
+    }
+    if unreadable > 0 {
+        println!("[BACKUP] {} strokes have unreadable colours and will draw black", unreadable);
+    }
+
+    restore_board(&state, BoardFile { strokes, ..board })
+}
+
+/// Replace the whole board with a loaded backup.
+///
+/// The saved undo log describes how the backup reached its saved state, so it
+/// replaces ours and the load itself is not undoable: entries recorded against
+/// the previous board would not apply to the loaded one.
+fn restore_board(state: &AppState, board: BoardFile) -> Result<Vec<Stroke>, String> {
+    let mut layer_stack = state.layers.lock().map_err(|e| e.to_string())?;
+    let mut strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+    let mut shapes = state.detected_shapes.lock().map_err(|e| e.to_string())?;
+    let mut text = state.ocr_text.lock().map_err(|e| e.to_string())?;
+    let mut log = state.history.lock().map_err(|e| e.to_string())?;
+
+    *layer_stack = board.layers.unwrap_or_default();
+    layer_stack.ensure_layers(&board.strokes);
+    *strokes = board.strokes;
+    // Analysis results belong to the board being replaced
+    shapes.clear();
+    text.clear();
+    *log = board.history.unwrap_or_default();
+    state.content_version.fetch_add(1, Ordering::SeqCst);
+
+    Ok(strokes.clone())
+}
+
+/// Board contents read from a backup: the strokes, the undo log and the layers.
+///
+/// Backups written before the binary stroke format hold this as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BoardFile {
+    strokes: Vec<Stroke>,
+    #[serde(default)]
+    history: Option<history::History>,
+    #[serde(default)]
+    layers: Option<layers::LayerStack>,
+}
+
+/// What a binary backup stores as JSON after its strokes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BoardMetadata {
+    #[serde(default)]
+    history: Option<history::History>,
+    #[serde(default)]
+    layers: Option<layers::LayerStack>,
+}
+
+/// Backups written before the undo log was saved hold a bare stroke list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackupContents {
+    Board(BoardFile),
+    Legacy(Vec<Stroke>),
+}
 
-    let mut state_strokes = state.strokes.lock().map_err(|e| e.to_string())?;
-    *state_strokes = strokes.clone();
+fn check_import_layer(state: &AppState, layer_id: Option<&str>) -> Result<(), String> {
+    match layer_id {
+        Some(id) => state.layers.lock().map_err(|e| e.to_string())?.check_editable(id),
+        None => Ok(()),
+    }
+}
+
+/// Put imported strokes on the board.
+///
+/// With a target layer the strokes are moved onto it and only that layer's
+/// strokes are replaced; otherwise the whole board is replaced. Analysis
+/// results for replaced strokes are dropped, and imported strokes whose ids
+/// are already taken get fresh ones.
+fn place_imported(
+    strokes: &mut Vec<Stroke>,
+    shapes: &mut Vec<shapes::DetectedShape>,
+    text: &mut Vec<ocr::TextRegion>,
+    mut imported: Vec<Stroke>,
+    append: bool,
+    layer_id: Option<&str>,
+) {
+    for stroke in &mut imported {
+        stroke.curves = canvas::fit_stroke_curves(stroke);
+    }
+    match layer_id {
+        Some(layer_id) => {
+            for stroke in &mut imported {
+                stroke.layer_id = layer_id.to_string();
+            }
+            if !append {
+                let replaced: std::collections::HashSet<String> = strokes
+                    .iter()
+                    .filter(|s| s.layer_id == layer_id)
+                    .map(|s| s.id.clone())
+                    .collect();
+                strokes.retain(|s| !replaced.contains(&s.id));
+                editing::invalidate_shapes(shapes, &replaced);
+            }
+        }
+        None if !append => {
+            strokes.clear();
+            shapes.clear();
+            text.clear();
+        }
+        None => {}
+    }
 
-    Ok(strokes)
+    let mut taken: std::collections::HashSet<String> = strokes.iter().map(|s| s.id.clone()).collect();
+    for stroke in &mut imported {
+        if !taken.insert(stroke.id.clone()) {
+            stroke.id = uuid::Uuid::new_v4().to_string();
+            taken.insert(stroke.id.clone());
+        }
+    }
+    strokes.extend(imported);
+}
+
+/// Vectorize a whiteboard photo into strokes, replacing or appending to the canvas (or one layer).
+///
+/// The board is rectified first, from `corners` when the user picked them or
+/// by detecting its outline, so shapes and text keep their proportions.
+#[tauri::command]
+async fn import_image(
+    state: State<'_, AppState>,
+    path: String,
+    append: Option<bool>,
+    corners: Option<rectify::Quad>,
+    layer_id: Option<String>,
+) -> Result<Vec<Stroke>, String> {
+    check_import_layer(&state, layer_id.as_deref())?;
+    let img = image::open(&path).map_err(|e| format!("Failed to load image: {}", e))?;
+    println!("[IMPORT] import_image called with {}x{} photo", img.width(), img.height());
+
+    let img = rectify::rectify_image(&img, corners, &rectify::RectifyParams::default())?;
+    println!("[IMPORT] Rectified board to {}x{}", img.width(), img.height());
+
+    let imported = vectorize::vectorize_image(&img, &vectorize::VectorizeParams::default());
+    println!("[IMPORT] Traced {} strokes", imported.len());
+
+    edit_board(&state, history::OperationKind::Import, |_| true, |strokes, shapes, text| {
+        place_imported(strokes, shapes, text, imported, append.unwrap_or(false), layer_id.as_deref());
+        Ok(strokes.clone())
+    })
+}
+
+/// Detect the whiteboard's corners in a photo so the user can adjust them
+#[tauri::command]
+async fn detect_board_corners(path: String) -> Result<Option<rectify::Quad>, String> {
+    let img = image::open(&path).map_err(|e| format!("Failed to load image: {}", e))?;
+    Ok(rectify::detect_board(&img, &rectify::RectifyParams::default()))
+}
+
+/// Export strokes as an InkML document
+#[tauri::command]
+async fn export_inkml(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<(), String> {
+    let xml = {
+        let strokes = state.strokes.lock().map_err(|e| e.to_string())?;
+        inkml::to_inkml(&strokes)?
+    };
+
+    std::fs::write(&path, xml).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    println!("[INKML] Wrote InkML to {}", path);
+    Ok(())
+}
+
+/// Import strokes from an InkML document, replacing or appending to the canvas (or one layer)
+#[tauri::command]
+async fn import_inkml(
+    state: State<'_, AppState>,
+    path: String,
+    append: Option<bool>,
+    layer_id: Option<String>,
+) -> Result<Vec<Stroke>, String> {
+    check_import_layer(&state, layer_id.as_deref())?;
+    let xml = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let imported = inkml::from_inkml(&xml)?;
+    println!("[INKML] Imported {} strokes from {}", imported.len(), path);
+
+    edit_board(&state, history::OperationKind::Import, |_| true, |strokes, shapes, text| {
+        place_imported(strokes, shapes, text, imported, append.unwrap_or(false), layer_id.as_deref());
+        Ok(strokes.clone())
+    })
+}
+
+/// Open a .drawio or mxGraph .xml file as a diagram structure
+#[tauri::command]
+async fn import_drawio(path: String) -> Result<drawio::DiagramStructure, String> {
+    let xml = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let structure = drawio_import::parse_drawio(&xml)?;
+    println!(
+        "[IMPORT] Imported {} nodes and {} edges from {}",
+        structure.nodes.len(),
+        structure.edges.len(),
+        path
+    );
+    Ok(structure)
 }
 
 /// Get application info
@@ -330,14 +1255,40 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             add_stroke,
             clear_strokes,
+            delete_strokes,
+            update_stroke,
+            erase_at,
+            select_strokes_in_rect,
+            select_strokes_in_polygon,
+            transform_strokes,
             get_strokes,
+            get_strokes_since,
+            get_layers,
+            create_layer,
+            reorder_layers,
+            set_layer_visible,
+            set_layer_locked,
+            undo,
+            redo,
+            get_history,
             process_canvas,
+            analyze_strokes,
             enhance_with_llm,
             generate_drawio,
             export_drawio_file,
+            export_wireframe_html,
+            export_svg,
+            render_tiles,
+            export_png,
+            export_pdf,
             configure_llm,
             save_backup,
             load_backup,
+            export_inkml,
+            import_inkml,
+            import_image,
+            detect_board_corners,
+            import_drawio,
             get_app_info,
         ])
         .run(tauri::generate_context!())
@@ -347,6 +1298,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shapes::test_stroke;
 
     #[test]
     fn test_point_creation() {
@@ -362,13 +1314,87 @@ mod tests {
 
     #[test]
     fn test_stroke_creation() {
-        let stroke = Stroke {
-            id: "test-1".to_string(),
-            points: vec![],
-            color: "#000000".to_string(),
-            width: 2.0,
-            tool: "pen".to_string(),
-        };
+        let stroke = test_stroke("test-1", vec![]);
         assert_eq!(stroke.id, "test-1");
     }
+
+    #[test]
+    fn test_stroke_curves_are_not_serialized() {
+        let points = (0..10).map(|i| Point { x: i as f64 * 10.0, y: 0.0, pressure: None, timestamp: i }).collect();
+        let mut stroke = test_stroke("s", points);
+        stroke.curves = canvas::fit_stroke_curves(&stroke);
+        assert!(!stroke.curves.is_empty());
+
+        let json = serde_json::to_string(&stroke).unwrap();
+        assert!(!json.contains("curves"));
+        let read: Stroke = serde_json::from_str(&json).unwrap();
+        assert!(read.curves.is_empty());
+    }
+
+    #[test]
+    fn test_place_imported_renames_taken_ids() {
+        let stroke = |id: &str| test_stroke(id, vec![Point { x: 0.0, y: 0.0, pressure: None, timestamp: 0 }]);
+        let mut strokes = vec![stroke("a"), stroke("b")];
+        let mut shapes = Vec::new();
+        let mut text = Vec::new();
+
+        place_imported(&mut strokes, &mut shapes, &mut text, vec![stroke("a"), stroke("c"), stroke("c")], true, None);
+
+        let ids: std::collections::HashSet<&str> = strokes.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(strokes.len(), 5);
+        assert_eq!(ids.len(), 5);
+        assert_eq!(strokes[0].id, "a");
+        assert_eq!(strokes[3].id, "c");
+    }
+
+    #[test]
+    fn test_load_then_undo_walks_the_saved_log() {
+        let stroke = |id: &str, layer_id: &str| Stroke {
+            layer_id: layer_id.to_string(),
+            ..test_stroke(id, vec![Point { x: 0.0, y: 0.0, pressure: None, timestamp: 0 }])
+        };
+        let add = |state: &AppState, s: Stroke| {
+            edit_board(state, history::OperationKind::AddStroke, |_| false, |strokes, _, _| {
+                strokes.push(s);
+                Ok(())
+            })
+            .unwrap();
+        };
+
+        // The saved board drew "a", then a note on a layer it later removed
+        let saved = AppState::default();
+        add(&saved, stroke("a", layers::DEFAULT_LAYER_ID));
+        add(&saved, stroke("n", "notes"));
+        edit_board(&saved, history::OperationKind::DeleteStrokes, |s| s.id == "n", |strokes, _, _| {
+            strokes.retain(|s| s.id != "n");
+            Ok(())
+        })
+        .unwrap();
+        let board = BoardFile {
+            strokes: saved.strokes.lock().unwrap().clone(),
+            history: Some(saved.history.lock().unwrap().clone()),
+            layers: None,
+        };
+
+        let state = AppState::default();
+        add(&state, stroke("x", layers::DEFAULT_LAYER_ID));
+        restore_board(&state, board).unwrap();
+
+        let ids = |state: &AppState| -> Vec<String> {
+            state.strokes.lock().unwrap().iter().map(|s| s.id.clone()).collect()
+        };
+        assert_eq!(ids(&state), vec!["a"]);
+
+        step_history(&state, false).unwrap();
+        assert_eq!(ids(&state), vec!["a", "n"]);
+        assert!(state.layers.lock().unwrap().get("notes").is_ok());
+
+        step_history(&state, false).unwrap();
+        assert_eq!(ids(&state), vec!["a"]);
+
+        // The previous board's log is gone, so "x" never comes back
+        step_history(&state, false).unwrap();
+        step_history(&state, false).unwrap();
+        assert!(ids(&state).is_empty());
+    }
 }
@@ -0,0 +1,205 @@
+//! Stray stroke filtering module
+//!
+//! Flags strokes that were probably drawn by accident (dots, palm touches,
+//! tiny smudges) so analysis can leave them out and the UI can offer to
+//! delete them. Each signal adds to a score; a stroke is stray once the
+//! score reaches the threshold, so no single weak signal condemns a stroke.
+
+use crate::ocr::TextRegion;
+use crate::selection::{stroke_bounds, SpatialIndex};
+use crate::Stroke;
+use serde::{Deserialize, Serialize};
+
+/// Stray stroke detection parameters
+#[derive(Debug, Clone)]
+pub struct NoiseParams {
+    /// Path length below which a stroke is tiny, in canvas pixels
+    pub min_length: f64,
+    /// Bounding box area below which a stroke is tiny
+    pub min_area: f64,
+    /// Duration below which a stroke is a tap, in milliseconds
+    pub min_duration_ms: u64,
+    /// Distance to the nearest other stroke or text beyond which a stroke is isolated
+    pub isolation_distance: f64,
+    /// Width, relative to the median stroke width, from which a short stroke looks like a palm
+    pub palm_width_ratio: f64,
+    /// Peak pressure below which a stroke only grazed the surface
+    pub grazing_pressure: f64,
+    /// Score from which a stroke counts as stray
+    pub threshold: f64,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            min_length: 8.0,
+            min_area: 16.0,
+            min_duration_ms: 40,
+            isolation_distance: 60.0,
+            palm_width_ratio: 3.0,
+            grazing_pressure: 0.15,
+            threshold: 1.0,
+        }
+    }
+}
+
+/// Why a stroke looks accidental
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseReason {
+    /// Very short and covers almost no area
+    Tiny,
+    /// Much wider than the other ink but barely longer than it is wide
+    Palm,
+    /// Over almost as soon as it started
+    Tap,
+    /// Pen pressure never rose above a graze
+    Grazing,
+    /// Far from any other stroke or text
+    Isolated,
+}
+
+impl NoiseReason {
+    fn weight(self) -> f64 {
+        match self {
+            NoiseReason::Tiny | NoiseReason::Palm => 0.6,
+            NoiseReason::Isolated => 0.4,
+            NoiseReason::Tap | NoiseReason::Grazing => 0.3,
+        }
+    }
+}
+
+/// A stroke left out of analysis as likely accidental
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrayStroke {
+    pub stroke_id: String,
+    pub score: f64,
+    pub reasons: Vec<NoiseReason>,
+}
+
+/// Find strokes that are probably accidental
+pub fn find_stray_strokes(strokes: &[Stroke], text_regions: &[TextRegion], params: &NoiseParams) -> Vec<StrayStroke> {
+    let mut widths: Vec<f64> = strokes.iter().map(|s| s.width).collect();
+    widths.sort_by(f64::total_cmp);
+    let median_width = widths.get(widths.len() / 2).copied().unwrap_or(0.0);
+    let index = SpatialIndex::build(strokes);
+
+    strokes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, stroke)| {
+            let (min_x, min_y, max_x, max_y) = stroke_bounds(stroke)?;
+            let length: f64 = stroke
+                .points
+                .windows(2)
+                .map(|w| ((w[1].x - w[0].x).powi(2) + (w[1].y - w[0].y).powi(2)).sqrt())
+                .sum();
+            let area = (max_x - min_x) * (max_y - min_y);
+
+            let mut reasons = Vec::new();
+            if length < params.min_length && area < params.min_area {
+                reasons.push(NoiseReason::Tiny);
+            }
+            if median_width > 0.0 && stroke.width >= median_width * params.palm_width_ratio && length < 3.0 * stroke.width {
+                reasons.push(NoiseReason::Palm);
+            }
+            // Imported strokes carry no timing (all timestamps 0)
+            let first = stroke.points.first().map_or(0, |p| p.timestamp);
+            let last = stroke.points.last().map_or(0, |p| p.timestamp);
+            if (first != 0 || last != 0) && last.saturating_sub(first) < params.min_duration_ms {
+                reasons.push(NoiseReason::Tap);
+            }
+            let peak = stroke.points.iter().filter_map(|p| p.pressure).fold(None, |peak: Option<f64>, p| {
+                Some(peak.map_or(p, |peak| peak.max(p)))
+            });
+            if peak.is_some_and(|peak| peak < params.grazing_pressure) {
+                reasons.push(NoiseReason::Grazing);
+            }
+            // Isolation only adds weight to strokes that are suspect already
+            if !reasons.is_empty() {
+                let d = params.isolation_distance;
+                let near_stroke = index
+                    .candidates(min_x - d, min_y - d, max_x + d, max_y + d)
+                    .into_iter()
+                    .filter(|&j| j != i)
+                    .filter_map(|j| stroke_bounds(&strokes[j]))
+                    .any(|b| box_gap((min_x, min_y, max_x, max_y), b) <= d);
+                let near_text = text_regions.iter().any(|t| {
+                    let b = &t.bounds;
+                    box_gap((min_x, min_y, max_x, max_y), (b.x, b.y, b.x + b.width, b.y + b.height)) <= d
+                });
+                if !near_stroke && !near_text {
+                    reasons.push(NoiseReason::Isolated);
+                }
+            }
+
+            let score: f64 = reasons.iter().map(|r| r.weight()).sum();
+            (score >= params.threshold).then(|| StrayStroke { stroke_id: stroke.id.clone(), score, reasons })
+        })
+        .collect()
+}
+
+/// Distance between two boxes; 0 when they overlap
+fn box_gap(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> f64 {
+    let dx = (b.0 - a.2).max(a.0 - b.2).max(0.0);
+    let dy = (b.1 - a.3).max(a.1 - b.3).max(0.0);
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+    use crate::Point;
+
+    fn stroke(id: &str, points: &[(f64, f64, u64)], width: f64) -> Stroke {
+        let points = points
+            .iter()
+            .map(|&(x, y, timestamp)| Point { x, y, pressure: None, timestamp })
+            .collect();
+        Stroke { width, ..test_stroke(id, points) }
+    }
+
+    fn line(id: &str, y: f64) -> Stroke {
+        stroke(id, &[(0.0, y, 1000), (200.0, y, 1400)], 2.0)
+    }
+
+    #[test]
+    fn test_isolated_dots_and_palms_are_stray() {
+        let strokes = vec![
+            line("a", 0.0),
+            line("b", 50.0),
+            line("c", 100.0),
+            // A dot just above a line, like the dot on an "i"
+            stroke("i-dot", &[(100.0, -10.0, 2000), (101.0, -10.0, 2100)], 2.0),
+            // A quick dot far from everything
+            stroke("speck", &[(900.0, 900.0, 3000), (901.0, 900.0, 3010)], 2.0),
+            // A wide, short smear next to the drawing
+            stroke("palm", &[(150.0, 120.0, 4000), (160.0, 125.0, 4300)], 12.0),
+        ];
+
+        let stray = find_stray_strokes(&strokes, &[], &NoiseParams::default());
+        let ids: Vec<&str> = stray.iter().map(|s| s.stroke_id.as_str()).collect();
+        assert_eq!(ids, vec!["speck"]);
+        assert_eq!(stray[0].reasons, vec![NoiseReason::Tiny, NoiseReason::Tap, NoiseReason::Isolated]);
+
+        // Far from the drawing the palm smear is flagged too
+        let mut strokes = strokes;
+        strokes[5] = stroke("palm", &[(600.0, 600.0, 4000), (610.0, 605.0, 4300)], 12.0);
+        let stray = find_stray_strokes(&strokes, &[], &NoiseParams::default());
+        assert!(stray.iter().any(|s| s.stroke_id == "palm" && s.reasons == vec![NoiseReason::Palm, NoiseReason::Isolated]));
+    }
+
+    #[test]
+    fn test_untimed_and_long_strokes_are_kept() {
+        // Imported strokes have no timestamps, so they are never taps
+        let strokes = vec![
+            stroke("dot", &[(0.0, 0.0, 0), (1.0, 0.0, 0)], 2.0),
+            stroke("lonely line", &[(500.0, 500.0, 10), (800.0, 500.0, 500)], 2.0),
+        ];
+        let stray = find_stray_strokes(&strokes, &[], &NoiseParams::default());
+        assert_eq!(stray.len(), 1);
+        assert_eq!(stray[0].reasons, vec![NoiseReason::Tiny, NoiseReason::Isolated]);
+        assert_eq!(stray[0].stroke_id, "dot");
+    }
+}
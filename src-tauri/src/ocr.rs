@@ -6,8 +6,11 @@
 use image::DynamicImage;
 use serde::{Deserialize, Serialize};
 
+/// Text used by the fallback recognizer for regions it cannot read
+pub const PLACEHOLDER_TEXT: &str = "[Handwritten text]";
+
 /// A detected text region with its content and location
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TextRegion {
     pub id: String,
     pub text: String,
@@ -17,7 +20,7 @@ pub struct TextRegion {
 }
 
 /// Bounding box for text region
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TextBounds {
     pub x: f64,
     pub y: f64,
@@ -191,7 +194,7 @@ fn find_text_like_regions(
                 // Likely text region
                 regions.push(TextRegion {
                     id: uuid::Uuid::new_v4().to_string(),
-                    text: "[Handwritten text]".to_string(), // Placeholder
+                    text: PLACEHOLDER_TEXT.to_string(),
                     bounds: TextBounds {
                         x: x as f64,
                         y: y as f64,
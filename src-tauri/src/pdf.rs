@@ -0,0 +1,372 @@
+//! PDF export module
+//!
+//! Writes the raw strokes onto a PDF page and, optionally, the recognized
+//! shapes and labels as vector graphics — either on a second page or as an
+//! optional content layer drawn over the ink.
+
+use crate::canvas::{fit_transform, normalize_strokes, point_radius, RasterTransform};
+use crate::color;
+use crate::ocr::{TextRegion, PLACEHOLDER_TEXT};
+use crate::shapes::{bounds_center, bounds_contain, DetectedShape, ShapeType};
+use crate::Stroke;
+use pdf_writer::types::{LineCapStyle, LineJoinStyle};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Where recognized shapes go in the PDF
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeLayer {
+    /// Ink only
+    #[default]
+    None,
+    /// Shapes and labels on a second page
+    Page,
+    /// Shapes and labels in a toggleable layer over the ink
+    Overlay,
+}
+
+/// Margin between the content and the page edge, in points
+const PAGE_PADDING: f64 = 36.0;
+
+/// Stroke colour of recognized shapes
+const SHAPE_COLOR: (f32, f32, f32) = (0.15, 0.35, 0.75);
+
+/// Line width of recognized shapes, in points
+const SHAPE_LINE_WIDTH: f32 = 1.5;
+
+/// Resource name of the shape overlay's optional content group
+const OVERLAY_TAG: Name<'static> = Name(b"ocShapes");
+
+/// Render strokes (and optionally shapes and labels) as a PDF document
+pub fn generate_pdf(
+    strokes: &[Stroke],
+    shapes: &[DetectedShape],
+    text_regions: &[TextRegion],
+    page_width: f64,
+    page_height: f64,
+    layer: ShapeLayer,
+) -> Result<Vec<u8>, String> {
+    if !(page_width > 2.0 * PAGE_PADDING && page_height > 2.0 * PAGE_PADDING) {
+        return Err(format!("Invalid page size {}x{}", page_width, page_height));
+    }
+
+    // Shapes share the strokes' canvas coordinates, so one transform fits both
+    let ink = normalize_strokes(strokes, page_width, page_height, PAGE_PADDING);
+    let transform = fit_transform(strokes, page_width, page_height, PAGE_PADDING)
+        .unwrap_or(RasterTransform { origin_x: 0.0, origin_y: 0.0, scale: 1.0 });
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let overlay_id = Ref::new(4);
+    let mut next_id = 5;
+    let mut alloc = || {
+        let id = Ref::new(next_id);
+        next_id += 1;
+        id
+    };
+
+    let alphas: BTreeSet<u8> = ink.iter().map(|s| color::ink(&s.color).a).collect();
+    let alpha_states: Vec<(u8, Ref)> = alphas.into_iter().map(|a| (a, alloc())).collect();
+
+    let mut pages = Vec::new();
+    {
+        let mut content = Content::new();
+        content.transform([1.0, 0.0, 0.0, -1.0, 0.0, page_height as f32]);
+        write_ink(&mut content, &ink);
+        if layer == ShapeLayer::Overlay {
+            content.begin_marked_content_with_properties(Name(b"OC")).properties_named(OVERLAY_TAG);
+            write_shapes(&mut content, shapes, text_regions, &transform);
+            content.end_marked_content();
+        }
+        pages.push((alloc(), alloc(), content.finish()));
+    }
+    if layer == ShapeLayer::Page {
+        let mut content = Content::new();
+        content.transform([1.0, 0.0, 0.0, -1.0, 0.0, page_height as f32]);
+        write_shapes(&mut content, shapes, text_regions, &transform);
+        pages.push((alloc(), alloc(), content.finish()));
+    }
+
+    let mut pdf = Pdf::new();
+    {
+        let mut catalog = pdf.catalog(catalog_id);
+        catalog.pages(page_tree_id);
+        if layer == ShapeLayer::Overlay {
+            let mut properties = catalog.insert(Name(b"OCProperties")).dict();
+            properties.insert(Name(b"OCGs")).array().item(overlay_id);
+            properties.insert(Name(b"D")).dict().insert(Name(b"ON")).array().item(overlay_id);
+        }
+    }
+    pdf.pages(page_tree_id)
+        .kids(pages.iter().map(|(page_id, _, _)| *page_id))
+        .count(pages.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    if layer == ShapeLayer::Overlay {
+        pdf.indirect(overlay_id)
+            .dict()
+            .pair(Name(b"Type"), Name(b"OCG"))
+            .pair(Name(b"Name"), TextStr("Recognized shapes"));
+    }
+    for (alpha, id) in &alpha_states {
+        let alpha = *alpha as f32 / 255.0;
+        pdf.ext_graphics(*id).stroking_alpha(alpha).non_stroking_alpha(alpha);
+    }
+
+    for (page_id, content_id, data) in &pages {
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, page_width as f32, page_height as f32))
+            .parent(page_tree_id)
+            .contents(*content_id);
+
+        let mut resources = page.resources();
+        resources.fonts().pair(Name(b"F1"), font_id);
+        {
+            let mut states = resources.ext_g_states();
+            for (alpha, id) in &alpha_states {
+                states.pair(Name(alpha_state_name(*alpha).as_bytes()), *id);
+            }
+        }
+        if layer == ShapeLayer::Overlay {
+            resources.insert(Name(b"Properties")).dict().pair(OVERLAY_TAG, overlay_id);
+        }
+        resources.finish();
+        page.finish();
+
+        pdf.stream(*content_id, data);
+    }
+
+    Ok(pdf.finish())
+}
+
+/// Draw the (already page-fitted) strokes
+fn write_ink(content: &mut Content, strokes: &[Stroke]) {
+    content.set_line_cap(LineCapStyle::RoundCap);
+    content.set_line_join(LineJoinStyle::RoundJoin);
+
+    for stroke in strokes {
+        let Some(first) = stroke.points.first() else { continue };
+        let color = color::ink(&stroke.color);
+        let (r, g, b) = color.components();
+
+        content.save_state();
+        content.set_parameters(Name(alpha_state_name(color.a).as_bytes()));
+        content.set_stroke_rgb(r, g, b);
+
+        if stroke.points.iter().any(|p| p.pressure != first.pressure) {
+            // PDF paths have a single width, so pressure changes go per segment
+            for pair in stroke.points.windows(2) {
+                let width = point_radius(stroke.width, pair[0].pressure)
+                    + point_radius(stroke.width, pair[1].pressure);
+                content.set_line_width(width as f32);
+                content.move_to(pair[0].x as f32, pair[0].y as f32);
+                content.line_to(pair[1].x as f32, pair[1].y as f32);
+                content.stroke();
+            }
+        } else {
+            content.set_line_width((point_radius(stroke.width, first.pressure) * 2.0) as f32);
+            content.move_to(first.x as f32, first.y as f32);
+            if stroke.points.len() == 1 {
+                content.line_to(first.x as f32, first.y as f32);
+            }
+            for p in &stroke.points[1..] {
+                content.line_to(p.x as f32, p.y as f32);
+            }
+            content.stroke();
+        }
+
+        content.restore_state();
+    }
+}
+
+/// Draw recognized shapes and their labels in page space
+fn write_shapes(
+    content: &mut Content,
+    shapes: &[DetectedShape],
+    text_regions: &[TextRegion],
+    transform: &RasterTransform,
+) {
+    let point = |x: f64, y: f64| {
+        let (px, py) = transform.raster_point(x, y);
+        (px as f32, py as f32)
+    };
+
+    content.save_state();
+    content.set_stroke_rgb(SHAPE_COLOR.0, SHAPE_COLOR.1, SHAPE_COLOR.2);
+    content.set_line_width(SHAPE_LINE_WIDTH);
+    content.set_line_cap(LineCapStyle::RoundCap);
+    content.set_line_join(LineJoinStyle::RoundJoin);
+
+    for shape in shapes {
+        let b = &shape.bounds;
+        let (x0, y0) = point(b.x, b.y);
+        let (x1, y1) = point(b.x + b.width, b.y + b.height);
+        let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+
+        match shape.shape_type {
+            ShapeType::Rectangle | ShapeType::Swimlane | ShapeType::Freeform => {
+                content.rect(x0, y0, x1 - x0, y1 - y0);
+                content.stroke();
+            }
+            ShapeType::Circle | ShapeType::Ellipse => {
+                write_ellipse(content, cx, cy, (x1 - x0) / 2.0, (y1 - y0) / 2.0);
+            }
+            ShapeType::Diamond => {
+                content.move_to(cx, y0);
+                content.line_to(x1, cy);
+                content.line_to(cx, y1);
+                content.line_to(x0, cy);
+                content.close_and_stroke();
+            }
+            ShapeType::Triangle => {
+                content.move_to(cx, y0);
+                content.line_to(x1, y1);
+                content.line_to(x0, y1);
+                content.close_and_stroke();
+            }
+            // The ink layer already shows the highlighter strokes
+            ShapeType::Highlight => {}
+            ShapeType::Arrow | ShapeType::Line | ShapeType::Connector => {
+                let (start, end) = match (shape.properties.start_point, shape.properties.end_point) {
+                    (Some(s), Some(e)) => (point(s.0, s.1), point(e.0, e.1)),
+                    _ => ((x0, y0), (x1, y1)),
+                };
+                content.move_to(start.0, start.1);
+                content.line_to(end.0, end.1);
+                content.stroke();
+                if shape.shape_type == ShapeType::Arrow {
+                    write_arrow_head(content, start, end);
+                }
+            }
+        }
+    }
+    content.restore_state();
+
+    content.save_state();
+    content.set_fill_rgb(0.0, 0.0, 0.0);
+    for shape in shapes {
+        if let Some(label) = &shape.label {
+            let (cx, cy) = bounds_center(&shape.bounds);
+            let (x, y) = point(cx, cy);
+            let size = 12.0;
+            let text_width = label.chars().count() as f32 * size * 0.5;
+            write_text(content, label, x - text_width / 2.0, y + size / 3.0, size);
+        }
+    }
+    for region in text_regions {
+        if region.text.contains(PLACEHOLDER_TEXT) || labelled_shape_contains(shapes, region) {
+            continue;
+        }
+        let (x, y) = point(region.bounds.x, region.bounds.y + region.bounds.height);
+        let size = (region.font_size_estimate * transform.scale).clamp(6.0, 48.0) as f32;
+        write_text(content, &region.text, x, y, size);
+    }
+    content.restore_state();
+}
+
+/// Whether the text already appears as the explicit label of a shape
+fn labelled_shape_contains(shapes: &[DetectedShape], region: &TextRegion) -> bool {
+    let cx = region.bounds.x + region.bounds.width / 2.0;
+    let cy = region.bounds.y + region.bounds.height / 2.0;
+    shapes.iter().any(|s| s.label.is_some() && bounds_contain(&s.bounds, cx, cy))
+}
+
+/// Ellipse from four Bézier quarter arcs
+fn write_ellipse(content: &mut Content, cx: f32, cy: f32, rx: f32, ry: f32) {
+    const K: f32 = 0.552_284_8;
+    content.move_to(cx + rx, cy);
+    content.cubic_to(cx + rx, cy + ry * K, cx + rx * K, cy + ry, cx, cy + ry);
+    content.cubic_to(cx - rx * K, cy + ry, cx - rx, cy + ry * K, cx - rx, cy);
+    content.cubic_to(cx - rx, cy - ry * K, cx - rx * K, cy - ry, cx, cy - ry);
+    content.cubic_to(cx + rx * K, cy - ry, cx + rx, cy - ry * K, cx + rx, cy);
+    content.close_and_stroke();
+}
+
+fn write_arrow_head(content: &mut Content, start: (f32, f32), end: (f32, f32)) {
+    let angle = (end.1 - start.1).atan2(end.0 - start.0);
+    let size = 8.0;
+    for side in [-0.5f32, 0.5] {
+        let a = angle + std::f32::consts::PI + side;
+        content.move_to(end.0, end.1);
+        content.line_to(end.0 + size * a.cos(), end.1 + size * a.sin());
+    }
+    content.stroke();
+}
+
+/// Show text with its baseline at (x, y); the page is y-down so the text matrix flips back
+fn write_text(content: &mut Content, text: &str, x: f32, y: f32, size: f32) {
+    let encoded: Vec<u8> = text
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c as u8 } else { b'?' })
+        .collect();
+
+    content.begin_text();
+    content.set_font(Name(b"F1"), size);
+    content.set_text_matrix([1.0, 0.0, 0.0, -1.0, x, y]);
+    content.show(Str(&encoded));
+    content.end_text();
+}
+
+fn alpha_state_name(alpha: u8) -> String {
+    format!("GS{}", alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{test_shape, test_stroke};
+    use crate::Point;
+
+    fn sample_strokes() -> Vec<Stroke> {
+        vec![Stroke {
+            color: "#1e1e1e".to_string(),
+            ..test_stroke("s1", vec![
+                Point { x: 100.0, y: 100.0, pressure: None, timestamp: 0 },
+                Point { x: 300.0, y: 100.0, pressure: None, timestamp: 1 },
+                Point { x: 300.0, y: 200.0, pressure: None, timestamp: 2 },
+            ])
+        }]
+    }
+
+    fn sample_shape() -> DetectedShape {
+        DetectedShape {
+            stroke_ids: vec!["s1".to_string()],
+            label: Some("Start".to_string()),
+            ..test_shape("shape1", ShapeType::Rectangle, (100.0, 100.0), (300.0, 200.0))
+        }
+    }
+
+    #[test]
+    fn test_ink_only_pdf() {
+        let pdf = generate_pdf(&sample_strokes(), &[], &[], 842.0, 595.0, ShapeLayer::None).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(text.contains("/MediaBox [0 0 842 595]"));
+        assert!(!text.contains("OCProperties"));
+    }
+
+    #[test]
+    fn test_shape_page_and_overlay() {
+        let strokes = sample_strokes();
+        let shapes = vec![sample_shape()];
+
+        let two_pages = generate_pdf(&strokes, &shapes, &[], 842.0, 595.0, ShapeLayer::Page).unwrap();
+        assert!(String::from_utf8_lossy(&two_pages).contains("/Count 2"));
+
+        let overlay = generate_pdf(&strokes, &shapes, &[], 842.0, 595.0, ShapeLayer::Overlay).unwrap();
+        let text = String::from_utf8_lossy(&overlay);
+        assert!(text.contains("/Count 1"));
+        assert!(text.contains("/OCProperties"));
+        assert!(text.contains("/Type /OCG"));
+    }
+
+    #[test]
+    fn test_invalid_page_size() {
+        assert!(generate_pdf(&sample_strokes(), &[], &[], 0.0, 595.0, ShapeLayer::None).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! Board rectification module
+//!
+//! Prepares whiteboard photos for vectorization: finds the board's
+//! quadrilateral (or uses user-picked corners), warps it to a fronto-parallel
+//! view and evens out glare and colour casts.
+
+use image::{DynamicImage, GenericImageView, GrayImage, Rgb, RgbImage};
+use nalgebra::{SMatrix, SVector};
+
+/// Board corners in image pixels: top-left, top-right, bottom-right, bottom-left
+pub type Quad = [(f64, f64); 4];
+
+/// Rectification parameters
+#[derive(Debug, Clone)]
+pub struct RectifyParams {
+    /// Longest side of the working copy used to find the board
+    pub detection_size: u32,
+    /// Smallest board area accepted, relative to the photo
+    pub min_board_area: f64,
+    /// Minimum fraction of the quad covered by bright board pixels
+    pub min_board_fill: f64,
+    /// Block size (pixels) for estimating the local board colour
+    pub illumination_block: u32,
+    /// Largest warped board, in pixels; bigger quads are scaled down to fit
+    pub max_output_pixels: f64,
+}
+
+impl Default for RectifyParams {
+    fn default() -> Self {
+        Self {
+            detection_size: 400,
+            min_board_area: 0.2,
+            min_board_fill: 0.75,
+            illumination_block: 32,
+            max_output_pixels: 16_000_000.0,
+        }
+    }
+}
+
+/// Warp the board to a fronto-parallel view and normalize its illumination.
+///
+/// Uses `corners` when given, otherwise tries to detect the board; photos
+/// without a recognizable board are only illumination-corrected.
+pub fn rectify_image(
+    image: &DynamicImage,
+    corners: Option<Quad>,
+    params: &RectifyParams,
+) -> Result<DynamicImage, String> {
+    let quad = match corners {
+        Some(corners) => Some(order_corners(clamp_corners(corners, image.dimensions())?)?),
+        None => detect_board(image, params),
+    };
+
+    let rgb = image.to_rgb8();
+    let warped = match quad {
+        Some(quad) => warp_perspective(&rgb, &quad, params.max_output_pixels)?,
+        None => rgb,
+    };
+
+    Ok(DynamicImage::ImageRgb8(normalize_illumination(&warped, params.illumination_block)))
+}
+
+/// Find the whiteboard as the largest bright region and return its corners
+pub fn detect_board(image: &DynamicImage, params: &RectifyParams) -> Option<Quad> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let small = if width.max(height) > params.detection_size {
+        image.resize(params.detection_size, params.detection_size, image::imageops::FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+    let gray = small.to_luma8();
+    let (sw, sh) = gray.dimensions();
+    let scale = width as f64 / sw as f64;
+
+    let threshold = otsu_threshold(&gray);
+    let component = largest_bright_component(&gray, threshold);
+
+    // Extreme points along the diagonals approximate the quad's corners
+    let (mut tl, mut tr, mut br, mut bl) = ((0, 0), (0, 0), (0, 0), (0, 0));
+    let (mut min_sum, mut max_sum) = (i64::MAX, i64::MIN);
+    let (mut min_diff, mut max_diff) = (i64::MAX, i64::MIN);
+    for &(x, y) in &component {
+        let (sum, diff) = (x + y, x - y);
+        if sum < min_sum {
+            min_sum = sum;
+            tl = (x, y);
+        }
+        if sum > max_sum {
+            max_sum = sum;
+            br = (x, y);
+        }
+        if diff > max_diff {
+            max_diff = diff;
+            tr = (x, y);
+        }
+        if diff < min_diff {
+            min_diff = diff;
+            bl = (x, y);
+        }
+    }
+
+    let corners = [tl, tr, br, bl].map(|(x, y)| (x as f64, y as f64));
+    let area = quad_area(&corners);
+    if area < params.min_board_area * (sw * sh) as f64 {
+        return None;
+    }
+    if (component.len() as f64) < params.min_board_fill * area {
+        return None;
+    }
+
+    // Map pixel centres of the working copy back to the photo
+    Some(corners.map(|(x, y)| ((x + 0.5) * scale, (y + 0.5) * scale)))
+}
+
+/// Order four corners as top-left, top-right, bottom-right, bottom-left
+pub fn order_corners(corners: Quad) -> Result<Quad, String> {
+    let cx = corners.iter().map(|c| c.0).sum::<f64>() / 4.0;
+    let cy = corners.iter().map(|c| c.1).sum::<f64>() / 4.0;
+
+    let mut sorted = corners;
+    // Clockwise on screen (y down) starting from the upper-left quadrant
+    sorted.sort_by(|a, b| {
+        let angle = |p: &(f64, f64)| {
+            let a = (p.1 - cy).atan2(p.0 - cx);
+            // Start from "left" so the top-left corner comes first
+            (a + std::f64::consts::PI).rem_euclid(2.0 * std::f64::consts::PI)
+        };
+        angle(a).partial_cmp(&angle(b)).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    if !is_convex(&sorted) || quad_area(&sorted) < 1.0 {
+        return Err("Board corners must form a convex quadrilateral".to_string());
+    }
+    Ok(sorted)
+}
+
+/// Pull user-picked corners onto the photo; they may be dragged past its edges
+fn clamp_corners(corners: Quad, (width, height): (u32, u32)) -> Result<Quad, String> {
+    if corners.iter().any(|c| !c.0.is_finite() || !c.1.is_finite()) {
+        return Err("Board corners must be finite".to_string());
+    }
+    Ok(corners.map(|(x, y)| (x.clamp(0.0, width as f64), y.clamp(0.0, height as f64))))
+}
+
+/// Warp the quad in `image` onto an upright rectangle of at most `max_pixels`
+pub fn warp_perspective(image: &RgbImage, quad: &Quad, max_pixels: f64) -> Result<RgbImage, String> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err("Image is empty".to_string());
+    }
+    let edge = |a: (f64, f64), b: (f64, f64)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let width = edge(quad[0], quad[1]).max(edge(quad[3], quad[2]));
+    let height = edge(quad[0], quad[3]).max(edge(quad[1], quad[2]));
+    if !(width.is_finite() && height.is_finite()) {
+        return Err("Board corners must be finite".to_string());
+    }
+    let shrink = (max_pixels / (width * height).max(1.0)).sqrt().min(1.0);
+    let width = (width * shrink).round().max(1.0);
+    let height = (height * shrink).round().max(1.0);
+
+    let target = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    // Inverse mapping: output pixel -> source position
+    let homography = homography(&target, quad)?;
+
+    Ok(RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let (sx, sy) = apply_homography(&homography, x as f64 + 0.5, y as f64 + 0.5);
+        sample_bilinear(image, sx - 0.5, sy - 0.5)
+    }))
+}
+
+/// Homography mapping each `from` corner onto the matching `to` corner
+fn homography(from: &Quad, to: &Quad) -> Result<[f64; 9], String> {
+    let mut a = SMatrix::<f64, 8, 8>::zeros();
+    let mut b = SVector::<f64, 8>::zeros();
+
+    for (i, (&(u, v), &(x, y))) in from.iter().zip(to.iter()).enumerate() {
+        let r = 2 * i;
+        a.row_mut(r).copy_from_slice(&[u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x]);
+        a.row_mut(r + 1).copy_from_slice(&[0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y]);
+        b[r] = x;
+        b[r + 1] = y;
+    }
+
+    let h = a
+        .lu()
+        .solve(&b)
+        .ok_or_else(|| "Board corners are degenerate".to_string())?;
+    Ok([h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0])
+}
+
+fn apply_homography(h: &[f64; 9], x: f64, y: f64) -> (f64, f64) {
+    let w = h[6] * x + h[7] * y + h[8];
+    ((h[0] * x + h[1] * y + h[2]) / w, (h[3] * x + h[4] * y + h[5]) / w)
+}
+
+fn sample_bilinear(image: &RgbImage, x: f64, y: f64) -> Rgb<u8> {
+    let (w, h) = image.dimensions();
+    let x = x.clamp(0.0, (w - 1) as f64);
+    let y = y.clamp(0.0, (h - 1) as f64);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+    let mut out = [0u8; 3];
+    for (c, value) in out.iter_mut().enumerate() {
+        let top = image.get_pixel(x0, y0)[c] as f64 * (1.0 - fx) + image.get_pixel(x1, y0)[c] as f64 * fx;
+        let bottom = image.get_pixel(x0, y1)[c] as f64 * (1.0 - fx) + image.get_pixel(x1, y1)[c] as f64 * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    Rgb(out)
+}
+
+/// Remove glare gradients and colour casts by dividing out the local board colour.
+///
+/// The board colour is estimated per block from the brightest pixels (ink is
+/// darker and ignored) and interpolated between block centres, per channel.
+pub fn normalize_illumination(image: &RgbImage, block: u32) -> RgbImage {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return image.clone();
+    }
+    let block = block.max(4);
+    let bw = width.div_ceil(block);
+    let bh = height.div_ceil(block);
+
+    let mut board = vec![[255.0f64; 3]; (bw * bh) as usize];
+    let mut values: [Vec<u8>; 3] = Default::default();
+    for by in 0..bh {
+        for bx in 0..bw {
+            for v in values.iter_mut() {
+                v.clear();
+            }
+            for y in by * block..((by + 1) * block).min(height) {
+                for x in bx * block..((bx + 1) * block).min(width) {
+                    let pixel = image.get_pixel(x, y);
+                    for c in 0..3 {
+                        values[c].push(pixel[c]);
+                    }
+                }
+            }
+            for c in 0..3 {
+                values[c].sort_unstable();
+                let index = (values[c].len() * 9) / 10;
+                board[(by * bw + bx) as usize][c] = values[c][index.min(values[c].len() - 1)].max(1) as f64;
+            }
+        }
+    }
+
+    let board_at = |bx: u32, by: u32| board[(by.min(bh - 1) * bw + bx.min(bw - 1)) as usize];
+
+    RgbImage::from_fn(width, height, |x, y| {
+        // Bilinear interpolation between block centres
+        let gx = ((x as f64 + 0.5) / block as f64 - 0.5).max(0.0);
+        let gy = ((y as f64 + 0.5) / block as f64 - 0.5).max(0.0);
+        let (bx, by) = (gx.floor() as u32, gy.floor() as u32);
+        let (fx, fy) = (gx - bx as f64, gy - by as f64);
+
+        let pixel = image.get_pixel(x, y);
+        let mut out = [0u8; 3];
+        for (c, value) in out.iter_mut().enumerate() {
+            let top = board_at(bx, by)[c] * (1.0 - fx) + board_at(bx + 1, by)[c] * fx;
+            let bottom = board_at(bx, by + 1)[c] * (1.0 - fx) + board_at(bx + 1, by + 1)[c] * fx;
+            let background = top * (1.0 - fy) + bottom * fy;
+            *value = (pixel[c] as f64 / background * 255.0).round().min(255.0) as u8;
+        }
+        Rgb(out)
+    })
+}
+
+/// Otsu's threshold separating board from surroundings
+fn otsu_threshold(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let sum_all: f64 = histogram.iter().enumerate().map(|(i, &n)| i as f64 * n as f64).sum();
+
+    let (mut weight_bg, mut sum_bg) = (0u64, 0.0);
+    let (mut best, mut best_variance) = (0u8, 0.0);
+    for (t, &count) in histogram.iter().enumerate() {
+        weight_bg += count;
+        if weight_bg == 0 {
+            continue;
+        }
+        let weight_fg = total - weight_bg;
+        if weight_fg == 0 {
+            break;
+        }
+        sum_bg += t as f64 * count as f64;
+        let mean_bg = sum_bg / weight_bg as f64;
+        let mean_fg = (sum_all - sum_bg) / weight_fg as f64;
+        let variance = weight_bg as f64 * weight_fg as f64 * (mean_bg - mean_fg).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = t as u8;
+        }
+    }
+    best
+}
+
+/// Pixels of the largest 4-connected region brighter than the threshold
+fn largest_bright_component(gray: &GrayImage, threshold: u8) -> Vec<(i64, i64)> {
+    let (width, height) = (gray.width() as i64, gray.height() as i64);
+    let bright = |x: i64, y: i64| {
+        x >= 0 && y >= 0 && x < width && y < height && gray.get_pixel(x as u32, y as u32)[0] > threshold
+    };
+
+    let mut visited = vec![false; (width * height) as usize];
+    let mut largest = Vec::new();
+    let mut stack = Vec::new();
+
+    for start_y in 0..height {
+        for start_x in 0..width {
+            let start = (start_y * width + start_x) as usize;
+            if visited[start] || !bright(start_x, start_y) {
+                continue;
+            }
+
+            let mut component = Vec::new();
+            visited[start] = true;
+            stack.push((start_x, start_y));
+            while let Some((x, y)) = stack.pop() {
+                component.push((x, y));
+                for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if bright(nx, ny) {
+                        let index = (ny * width + nx) as usize;
+                        if !visited[index] {
+                            visited[index] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+
+            if component.len() > largest.len() {
+                largest = component;
+            }
+        }
+    }
+
+    largest
+}
+
+/// Shoelace area of a quad
+fn quad_area(quad: &Quad) -> f64 {
+    let mut twice = 0.0;
+    for i in 0..4 {
+        let (a, b) = (quad[i], quad[(i + 1) % 4]);
+        twice += a.0 * b.1 - b.0 * a.1;
+    }
+    twice.abs() / 2.0
+}
+
+fn is_convex(quad: &Quad) -> bool {
+    let mut sign = 0.0;
+    for i in 0..4 {
+        let (a, b, c) = (quad[i], quad[(i + 1) % 4], quad[(i + 2) % 4]);
+        let cross = (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0);
+        if cross.abs() < f64::EPSILON {
+            return false;
+        }
+        if sign == 0.0 {
+            sign = cross.signum();
+        } else if cross.signum() != sign {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dark room with a bright, perspective-skewed board
+    fn skewed_board() -> (DynamicImage, Quad) {
+        let quad = [(120.0, 60.0), (520.0, 100.0), (540.0, 380.0), (90.0, 420.0)];
+        let inside = |x: f64, y: f64| {
+            (0..4).all(|i| {
+                let (a, b) = (quad[i], quad[(i + 1) % 4]);
+                (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0) >= 0.0
+            })
+        };
+        let img = RgbImage::from_fn(640, 480, |x, y| {
+            if inside(x as f64 + 0.5, y as f64 + 0.5) {
+                Rgb([235, 225, 200])
+            } else {
+                Rgb([60, 55, 50])
+            }
+        });
+        (DynamicImage::ImageRgb8(img), quad)
+    }
+
+    #[test]
+    fn test_detect_board_corners() {
+        let (img, expected) = skewed_board();
+        let quad = detect_board(&img, &RectifyParams::default()).expect("board should be found");
+
+        for (found, want) in quad.iter().zip(expected.iter()) {
+            assert!(
+                (found.0 - want.0).abs() < 4.0 && (found.1 - want.1).abs() < 4.0,
+                "corner {:?} too far from {:?}",
+                found,
+                want
+            );
+        }
+    }
+
+    #[test]
+    fn test_rectify_warps_and_neutralizes_cast() {
+        let (img, _) = skewed_board();
+        let rectified = rectify_image(&img, None, &RectifyParams::default()).unwrap().to_rgb8();
+
+        // Roughly the longer edges of the quad, and the yellowish board becomes white
+        assert!((rectified.width() as i64 - 450).abs() < 10, "width {}", rectified.width());
+        assert!((rectified.height() as i64 - 360).abs() < 10, "height {}", rectified.height());
+        let centre = rectified.get_pixel(rectified.width() / 2, rectified.height() / 2);
+        assert!(centre.0.iter().all(|&c| c >= 250), "centre {:?}", centre);
+    }
+
+    #[test]
+    fn test_corners_outside_the_photo_are_clamped() {
+        let corners = [(-500.0, -500.0), (1e12, 0.0), (1e12, 1e12), (0.0, 1e12)];
+        assert_eq!(clamp_corners(corners, (40, 30)).unwrap(), [(0.0, 0.0), (40.0, 0.0), (40.0, 30.0), (0.0, 30.0)]);
+        assert!(clamp_corners([(f64::NAN, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], (40, 30)).is_err());
+
+        // A huge quad is warped into a bounded image
+        let image = RgbImage::new(4, 4);
+        let warped = warp_perspective(&image, &[(0.0, 0.0), (1e6, 0.0), (1e6, 1e6), (0.0, 1e6)], 10_000.0).unwrap();
+        assert_eq!(warped.dimensions(), (100, 100));
+    }
+
+    #[test]
+    fn test_order_corners() {
+        let ordered = order_corners([(10.0, 90.0), (90.0, 10.0), (10.0, 10.0), (90.0, 90.0)]).unwrap();
+        assert_eq!(ordered, [(10.0, 10.0), (90.0, 10.0), (90.0, 90.0), (10.0, 90.0)]);
+
+        assert!(order_corners([(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)]).is_err());
+    }
+}
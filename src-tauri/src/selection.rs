@@ -0,0 +1,471 @@
+//! Stroke selection and transform module
+//!
+//! Rectangle and lasso selection backed by a uniform-grid spatial index, and
+//! affine transforms of selected strokes (move, scale, rotate, duplicate)
+//! that carry their detected shapes along.
+
+use crate::shapes::DetectedShape;
+use crate::Stroke;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Grid cell size of the spatial index, in canvas units
+const INDEX_CELL_SIZE: f64 = 64.0;
+
+/// Strokes covering more grid cells than this are kept in one list instead
+const MAX_CELLS_PER_STROKE: i64 = 4096;
+
+/// How much of a stroke must lie inside the selection area
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Containment {
+    /// Any part of the stroke touches the area
+    #[default]
+    Partial,
+    /// The whole stroke lies inside the area
+    Full,
+}
+
+/// Selectable strokes with their spatial index, as of one content version
+pub struct IndexedStrokes {
+    pub version: u64,
+    pub strokes: Vec<Stroke>,
+    pub index: SpatialIndex,
+}
+
+/// Uniform grid over stroke bounding boxes
+pub struct SpatialIndex {
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// Strokes too large to list per cell; every query considers them
+    oversized: Vec<usize>,
+}
+
+impl SpatialIndex {
+    /// Index the strokes; ones without finite bounds can never be selected
+    pub fn build(strokes: &[Stroke]) -> Self {
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        let mut oversized = Vec::new();
+        for (i, stroke) in strokes.iter().enumerate() {
+            let Some(span) = stroke_bounds(stroke).and_then(|(x0, y0, x1, y1)| CellSpan::new(x0, y0, x1, y1)) else {
+                continue;
+            };
+            if span.count() > MAX_CELLS_PER_STROKE {
+                oversized.push(i);
+                continue;
+            }
+            for cell in span.cells() {
+                cells.entry(cell).or_default().push(i);
+            }
+        }
+        Self { cells, oversized }
+    }
+
+    /// Indices of strokes whose bounding boxes may overlap the given box, in drawing order
+    pub fn candidates(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<usize> {
+        let Some(span) = CellSpan::new(min_x, min_y, max_x, max_y) else {
+            return Vec::new();
+        };
+        // Zoomed-out queries can span far more cells than hold anything
+        let in_cells: Vec<&Vec<usize>> = if span.count() > self.cells.len() as i64 {
+            self.cells.iter().filter(|(cell, _)| span.contains(**cell)).map(|(_, i)| i).collect()
+        } else {
+            span.cells().filter_map(|cell| self.cells.get(&cell)).collect()
+        };
+        let mut found: Vec<usize> = in_cells.into_iter().flatten().chain(&self.oversized).copied().collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+/// Inclusive range of grid cells covered by a box
+struct CellSpan {
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+}
+
+impl CellSpan {
+    /// `None` when any bound is infinite or NaN
+    fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Option<Self> {
+        if ![min_x, min_y, max_x, max_y].iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        let cell = |v: f64| (v / INDEX_CELL_SIZE).floor() as i64;
+        Some(Self { x0: cell(min_x), y0: cell(min_y), x1: cell(max_x), y1: cell(max_y) })
+    }
+
+    fn count(&self) -> i64 {
+        let side = |a: i64, b: i64| b.saturating_sub(a).saturating_add(1).max(0);
+        side(self.x0, self.x1).saturating_mul(side(self.y0, self.y1))
+    }
+
+    fn contains(&self, (x, y): (i64, i64)) -> bool {
+        (self.x0..=self.x1).contains(&x) && (self.y0..=self.y1).contains(&y)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i64, i64)> {
+        let (x0, x1, y0, y1) = (self.x0, self.x1, self.y0, self.y1);
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    }
+}
+
+pub(crate) fn stroke_bounds(stroke: &Stroke) -> Option<(f64, f64, f64, f64)> {
+    let first = stroke.points.first()?;
+    Some(stroke.points.iter().fold(
+        (first.x, first.y, first.x, first.y),
+        |(min_x, min_y, max_x, max_y), p| (min_x.min(p.x), min_y.min(p.y), max_x.max(p.x), max_y.max(p.y)),
+    ))
+}
+
+/// Ids of strokes inside an axis-aligned rectangle
+pub fn select_in_rect(
+    strokes: &[Stroke],
+    index: &SpatialIndex,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    mode: Containment,
+) -> Vec<String> {
+    let (x0, x1) = (x.min(x + width), x.max(x + width));
+    let (y0, y1) = (y.min(y + height), y.max(y + height));
+    select_in_polygon(strokes, index, &[(x0, y0), (x1, y0), (x1, y1), (x0, y1)], mode)
+}
+
+/// Ids of strokes inside a lasso polygon (closed implicitly)
+pub fn select_in_polygon(
+    strokes: &[Stroke],
+    index: &SpatialIndex,
+    polygon: &[(f64, f64)],
+    mode: Containment,
+) -> Vec<String> {
+    if polygon.len() < 3 {
+        return Vec::new();
+    }
+
+    let (min_x, min_y, max_x, max_y) = polygon.iter().fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(min_x, min_y, max_x, max_y), &(x, y)| (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)),
+    );
+
+    index
+        .candidates(min_x, min_y, max_x, max_y)
+        .into_iter()
+        .map(|i| &strokes[i])
+        .filter(|stroke| stroke_in_polygon(stroke, polygon, mode))
+        .map(|stroke| stroke.id.clone())
+        .collect()
+}
+
+fn stroke_in_polygon(stroke: &Stroke, polygon: &[(f64, f64)], mode: Containment) -> bool {
+    let points: Vec<(f64, f64)> = stroke.points.iter().map(|p| (p.x, p.y)).collect();
+    let crosses_edge = || {
+        points.windows(2).any(|seg| {
+            polygon_edges(polygon).any(|(a, b)| segments_intersect(seg[0], seg[1], a, b))
+        })
+    };
+
+    match mode {
+        Containment::Partial => points.iter().any(|&p| point_in_polygon(p, polygon)) || crosses_edge(),
+        // A concave lasso can contain both ends of a segment that leaves it
+        Containment::Full => points.iter().all(|&p| point_in_polygon(p, polygon)) && !crosses_edge(),
+    }
+}
+
+fn polygon_edges(polygon: &[(f64, f64)]) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    (0..polygon.len()).map(move |i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+}
+
+/// Even-odd ray casting test
+fn point_in_polygon(p: (f64, f64), polygon: &[(f64, f64)]) -> bool {
+    polygon_edges(polygon).fold(false, |inside, (a, b)| {
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+            !inside
+        } else {
+            inside
+        }
+    })
+}
+
+fn segments_intersect(p1: (f64, f64), p2: (f64, f64), q1: (f64, f64), q2: (f64, f64)) -> bool {
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+    let d1 = cross(q1, q2, p1);
+    let d2 = cross(q1, q2, p2);
+    let d3 = cross(p1, p2, q1);
+    let d4 = cross(p1, p2, q2);
+    (d1 * d2 < 0.0) && (d3 * d4 < 0.0)
+}
+
+/// 2D affine matrix in canvas/SVG order: `x' = a*x + c*y + e`, `y' = b*x + d*y + f`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Affine {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl Affine {
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+    }
+
+    /// Apply only the linear part, for direction vectors
+    fn apply_vector(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.a * x + self.c * y, self.b * x + self.d * y)
+    }
+
+    /// Uniform scale factor: the geometric mean of the axis scales
+    pub fn scale_factor(&self) -> f64 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+
+    fn is_axis_aligned(&self) -> bool {
+        self.b.abs() < 1e-12 && self.c.abs() < 1e-12
+    }
+}
+
+/// Strokes changed by [`transform_strokes`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransformResult {
+    /// The transformed strokes (the new copies when duplicating)
+    pub strokes: Vec<Stroke>,
+    /// Ids of detected shapes moved with the strokes (or created for copies)
+    pub shape_ids: Vec<String>,
+}
+
+/// Apply `matrix` to the selected strokes, or to copies of them when `duplicate` is set.
+///
+/// Stroke widths scale with the matrix's uniform scale factor. Shapes built
+/// entirely from selected strokes are transformed with them; shapes that
+/// only partly use the selection no longer match their ink and are dropped.
+pub fn transform_strokes(
+    strokes: &mut Vec<Stroke>,
+    shapes: &mut Vec<DetectedShape>,
+    ids: &HashSet<String>,
+    matrix: &Affine,
+    duplicate: bool,
+) -> TransformResult {
+    let scale = matrix.scale_factor();
+    let transform = |stroke: &mut Stroke| {
+        for p in stroke.points.iter_mut() {
+            (p.x, p.y) = matrix.apply(p.x, p.y);
+        }
+        for curve in stroke.curves.iter_mut() {
+            *curve = curve.map(|x, y| matrix.apply(x, y));
+        }
+        stroke.width *= scale;
+    };
+
+    let mut result = TransformResult::default();
+    // Old stroke id -> id of the stroke the attached shapes should now reference
+    let mut renamed: HashMap<String, String> = HashMap::new();
+
+    if duplicate {
+        let copies: Vec<Stroke> = strokes
+            .iter()
+            .filter(|s| ids.contains(&s.id))
+            .map(|s| {
+                let mut copy = s.clone();
+                copy.id = uuid::Uuid::new_v4().to_string();
+                renamed.insert(s.id.clone(), copy.id.clone());
+                transform(&mut copy);
+                copy
+            })
+            .collect();
+        strokes.extend(copies.iter().cloned());
+        result.strokes = copies;
+    } else {
+        for stroke in strokes.iter_mut().filter(|s| ids.contains(&s.id)) {
+            transform(stroke);
+            renamed.insert(stroke.id.clone(), stroke.id.clone());
+            result.strokes.push(stroke.clone());
+        }
+    }
+
+    let attached = |shape: &DetectedShape| {
+        !shape.stroke_ids.is_empty() && shape.stroke_ids.iter().all(|id| ids.contains(id))
+    };
+
+    if duplicate {
+        // Copy attached shapes, keeping container relationships within the copied group
+        let mut shape_ids: HashMap<String, String> = HashMap::new();
+        let mut copies: Vec<DetectedShape> = shapes
+            .iter()
+            .filter(|s| attached(s))
+            .filter_map(|s| {
+                // A shape built from strokes that are no longer on the board is stale; leave it behind
+                let stroke_ids: Option<Vec<String>> =
+                    s.stroke_ids.iter().map(|id| renamed.get(id).cloned()).collect();
+                let mut copy = s.clone();
+                copy.id = uuid::Uuid::new_v4().to_string();
+                copy.stroke_ids = stroke_ids?;
+                shape_ids.insert(s.id.clone(), copy.id.clone());
+                transform_shape(&mut copy, matrix);
+                Some(copy)
+            })
+            .collect();
+        for copy in copies.iter_mut() {
+            copy.parent_id = copy.parent_id.as_ref().and_then(|p| shape_ids.get(p).cloned());
+        }
+        result.shape_ids = copies.iter().map(|s| s.id.clone()).collect();
+        shapes.extend(copies);
+    } else {
+        let partial: HashSet<String> = shapes
+            .iter()
+            .filter(|s| !attached(s) && s.stroke_ids.iter().any(|id| ids.contains(id)))
+            .flat_map(|s| s.stroke_ids.iter().cloned())
+            .filter(|id| ids.contains(id))
+            .collect();
+        crate::editing::invalidate_shapes(shapes, &partial);
+
+        for shape in shapes.iter_mut().filter(|s| attached(s)) {
+            transform_shape(shape, matrix);
+            result.shape_ids.push(shape.id.clone());
+        }
+    }
+
+    result
+}
+
+/// Map a detected shape's geometry through `matrix`
+fn transform_shape(shape: &mut DetectedShape, matrix: &Affine) {
+    let scale = matrix.scale_factor();
+    let b = &mut shape.bounds;
+
+    if matrix.is_axis_aligned() {
+        // Keep the box axis-aligned; flips just swap its edges
+        let (x0, y0) = matrix.apply(b.x, b.y);
+        let (x1, y1) = matrix.apply(b.x + b.width, b.y + b.height);
+        b.x = x0.min(x1);
+        b.y = y0.min(y1);
+        b.width = (x1 - x0).abs();
+        b.height = (y1 - y0).abs();
+    } else {
+        let (cx, cy) = matrix.apply(b.x + b.width / 2.0, b.y + b.height / 2.0);
+        b.width *= matrix.a.hypot(matrix.b);
+        b.height *= matrix.c.hypot(matrix.d);
+        b.x = cx - b.width / 2.0;
+        b.y = cy - b.height / 2.0;
+        b.rotation += matrix.b.atan2(matrix.a).to_degrees();
+    }
+
+    let props = &mut shape.properties;
+    (props.center_x, props.center_y) = matrix.apply(props.center_x, props.center_y);
+    props.radius = props.radius.map(|r| r * scale);
+    props.corner_radius = props.corner_radius.map(|r| r * scale);
+    props.start_point = props.start_point.map(|(x, y)| matrix.apply(x, y));
+    props.end_point = props.end_point.map(|(x, y)| matrix.apply(x, y));
+    if let Some(head) = props.arrow_head.as_mut() {
+        let angle = head.direction.to_radians();
+        let (dx, dy) = matrix.apply_vector(angle.cos(), angle.sin());
+        head.direction = dy.atan2(dx).to_degrees();
+        head.size *= scale;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+    use crate::Point;
+
+    fn stroke(id: &str, points: &[(f64, f64)]) -> Stroke {
+        test_stroke(id, points.iter().map(|&(x, y)| Point { x, y, pressure: None, timestamp: 0 }).collect())
+    }
+
+    #[test]
+    fn test_partial_and_full_selection() {
+        let strokes = vec![
+            stroke("inside", &[(10.0, 10.0), (20.0, 20.0)]),
+            stroke("crossing", &[(50.0, 10.0), (150.0, 10.0)]),
+            stroke("through", &[(-20.0, 50.0), (300.0, 50.0)]),
+            stroke("outside", &[(500.0, 500.0), (510.0, 510.0)]),
+        ];
+        let index = SpatialIndex::build(&strokes);
+
+        let partial = select_in_rect(&strokes, &index, 0.0, 0.0, 100.0, 100.0, Containment::Partial);
+        assert_eq!(partial, vec!["inside", "crossing", "through"]);
+
+        let full = select_in_rect(&strokes, &index, 0.0, 0.0, 100.0, 100.0, Containment::Full);
+        assert_eq!(full, vec!["inside"]);
+
+        // A U-shaped lasso whose notch the stroke bridges
+        let lasso = [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (60.0, 100.0), (60.0, 30.0), (40.0, 30.0), (40.0, 100.0), (0.0, 100.0)];
+        let bridge = vec![stroke("bridge", &[(20.0, 60.0), (80.0, 60.0)])];
+        let index = SpatialIndex::build(&bridge);
+        assert!(select_in_polygon(&bridge, &index, &lasso, Containment::Full).is_empty());
+        assert_eq!(select_in_polygon(&bridge, &index, &lasso, Containment::Partial), vec!["bridge"]);
+    }
+
+    #[test]
+    fn test_index_skips_non_finite_and_lists_huge_strokes() {
+        let strokes = vec![
+            stroke("broken", &[(f64::NEG_INFINITY, 0.0), (f64::INFINITY, 10.0)]),
+            stroke("huge", &[(-1e12, 5.0), (1e12, 5.0)]),
+            stroke("small", &[(10.0, 10.0), (20.0, 20.0)]),
+        ];
+        let index = SpatialIndex::build(&strokes);
+
+        assert_eq!(index.candidates(0.0, 0.0, 30.0, 30.0), vec![1, 2]);
+        assert_eq!(index.candidates(5e11, 0.0, 5e11 + 1.0, 1.0), vec![1]);
+        assert!(index.candidates(f64::NEG_INFINITY, 0.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_wide_queries_scan_occupied_cells() {
+        let strokes = vec![
+            stroke("near", &[(10.0, 10.0), (20.0, 20.0)]),
+            stroke("far", &[(1e6, 1e6), (1e6 + 10.0, 1e6)]),
+        ];
+        let index = SpatialIndex::build(&strokes);
+
+        assert_eq!(index.candidates(-1e9, -1e9, 1e9, 1e9), vec![0, 1]);
+        assert_eq!(index.candidates(-1e9, -1e9, 1e5, 1e5), vec![0]);
+    }
+
+    #[test]
+    fn test_transform_moves_strokes_and_shapes() {
+        use crate::shapes::{test_shape, ShapeType};
+
+        let mut strokes = vec![stroke("a", &[(0.0, 0.0), (10.0, 0.0)]), stroke("b", &[(0.0, 5.0), (10.0, 5.0)])];
+        let mut shapes = vec![DetectedShape {
+            stroke_ids: vec!["a".to_string()],
+            ..test_shape("box", ShapeType::Rectangle, (0.0, 0.0), (10.0, 5.0))
+        }];
+
+        let scale_and_move = Affine { a: 2.0, b: 0.0, c: 0.0, d: 2.0, e: 100.0, f: 50.0 };
+        let ids = HashSet::from(["a".to_string()]);
+        let result = transform_strokes(&mut strokes, &mut shapes, &ids, &scale_and_move, false);
+
+        assert_eq!(result.shape_ids, vec!["box"]);
+        assert_eq!((strokes[0].points[1].x, strokes[0].points[1].y), (120.0, 50.0));
+        assert_eq!(strokes[0].width, 4.0);
+        assert_eq!(strokes[1].points[0].x, 0.0);
+        let b = &shapes[0].bounds;
+        assert_eq!((b.x, b.y, b.width, b.height), (100.0, 50.0, 20.0, 10.0));
+
+        // Duplicating leaves the originals and copies the shape onto the new strokes
+        let shift = Affine { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 200.0 };
+        let result = transform_strokes(&mut strokes, &mut shapes, &ids, &shift, true);
+        assert_eq!(strokes.len(), 3);
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[1].stroke_ids, vec![result.strokes[0].id.clone()]);
+        assert_eq!(shapes[1].bounds.y, 250.0);
+
+        // A stale shape whose stroke has been deleted is not copied, but the box still is
+        let mut stale = shapes[0].clone();
+        stale.id = "stale".to_string();
+        stale.stroke_ids = vec!["a".to_string(), "gone".to_string()];
+        shapes.push(stale);
+        let ids = HashSet::from(["a".to_string(), "gone".to_string()]);
+        transform_strokes(&mut strokes, &mut shapes, &ids, &shift, true);
+        assert_eq!(strokes.len(), 4);
+        assert_eq!(shapes.len(), 4);
+        assert_eq!(shapes.iter().filter(|s| s.stroke_ids.len() == 2).count(), 1);
+    }
+}
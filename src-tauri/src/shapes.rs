@@ -5,6 +5,7 @@
 
 use crate::{Point, Stroke};
 use crate::ocr::TextRegion;
+use crate::wireframe::{has_wireframe_evidence, WireframeElement};
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 use geo::{LineString, Simplify};
@@ -22,10 +23,13 @@ pub enum ShapeType {
     Line,
     Connector,
     Freeform,
+    Swimlane,
+    /// Highlighter emphasis drawn behind other content
+    Highlight,
 }
 
 /// A detected shape with its properties
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DetectedShape {
     pub id: String,
     pub shape_type: ShapeType,
@@ -33,10 +37,16 @@ pub struct DetectedShape {
     pub confidence: f64,
     pub stroke_ids: Vec<String>,
     pub properties: ShapeProperties,
+    /// Explicit label; when absent the exporter looks up overlapping OCR text
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Id of the container shape (e.g. a swimlane) this shape belongs to
+    #[serde(default)]
+    pub parent_id: Option<String>,
 }
 
 /// Bounding box of a shape
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShapeBounds {
     pub x: f64,
     pub y: f64,
@@ -46,7 +56,7 @@ pub struct ShapeBounds {
 }
 
 /// Additional properties for shapes
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShapeProperties {
     pub center_x: f64,
     pub center_y: f64,
@@ -55,10 +65,30 @@ pub struct ShapeProperties {
     pub end_point: Option<(f64, f64)>,
     pub corner_radius: Option<f64>,
     pub arrow_head: Option<ArrowHead>,
+    #[serde(default)]
+    pub bpmn: Option<BpmnElement>,
+    #[serde(default)]
+    pub icon: Option<crate::icons::IconKind>,
+    #[serde(default)]
+    pub wireframe: Option<crate::wireframe::WireframeElement>,
+}
+
+/// BPMN role assigned to a shape by [`annotate_bpmn`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BpmnElement {
+    StartEvent,
+    IntermediateEvent,
+    EndEvent,
+    ExclusiveGateway,
+    ParallelGateway,
+    Task,
+    Pool,
+    Lane,
 }
 
 /// Arrow head configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ArrowHead {
     pub style: String,
     pub size: f64,
@@ -87,17 +117,104 @@ impl Default for DetectionParams {
     }
 }
 
+/// Pen-lift merging parameters
+#[derive(Debug, Clone)]
+pub struct PenLiftParams {
+    /// Longest pause between two fragments of one stroke, in milliseconds
+    pub max_gap_ms: u64,
+    /// Largest distance from one fragment's end to the next one's start
+    pub max_distance: f64,
+    /// Largest change of direction across the lift, in degrees
+    pub max_turn: f64,
+    /// Points used to estimate the direction at a fragment's end
+    pub direction_span: usize,
+}
+
+impl Default for PenLiftParams {
+    fn default() -> Self {
+        Self {
+            max_gap_ms: 250,
+            max_distance: 12.0,
+            max_turn: 45.0,
+            direction_span: 4,
+        }
+    }
+}
+
+/// Strokes joined across short pen lifts, with the ids of every fragment
+#[derive(Debug, Clone)]
+pub struct LogicalStroke {
+    pub stroke: Stroke,
+    pub stroke_ids: Vec<String>,
+}
+
+/// Join consecutive strokes separated only by a brief pen lift.
+///
+/// A fragment continues the previous one when it starts soon after and close
+/// to where the previous one ended, heading the same way, with the same tool
+/// on the same layer. Strokes without timing (imported ones carry timestamp
+/// 0) are never joined.
+pub fn merge_pen_lifts(strokes: &[Stroke], params: &PenLiftParams) -> Vec<LogicalStroke> {
+    let mut merged: Vec<LogicalStroke> = Vec::new();
+    for stroke in strokes {
+        match merged.last_mut() {
+            Some(last) if continues(&last.stroke, stroke, params) => {
+                last.stroke.points.extend(stroke.points.iter().cloned());
+                last.stroke_ids.push(stroke.id.clone());
+            }
+            _ => merged.push(LogicalStroke { stroke: stroke.clone(), stroke_ids: vec![stroke.id.clone()] }),
+        }
+    }
+    merged
+}
+
+/// Whether `next` picks up where `prev` was lifted
+fn continues(prev: &Stroke, next: &Stroke, params: &PenLiftParams) -> bool {
+    if prev.tool != next.tool || prev.layer_id != next.layer_id {
+        return false;
+    }
+    let (Some(end), Some(start)) = (prev.points.last(), next.points.first()) else {
+        return false;
+    };
+    if end.timestamp == 0 || start.timestamp == 0 || start.timestamp < end.timestamp {
+        return false;
+    }
+    if start.timestamp - end.timestamp > params.max_gap_ms {
+        return false;
+    }
+    if ((start.x - end.x).powi(2) + (start.y - end.y).powi(2)).sqrt() > params.max_distance {
+        return false;
+    }
+
+    let span = params.direction_span.max(1);
+    if prev.points.len() <= span || next.points.len() <= span {
+        return false;
+    }
+    let before = &prev.points[prev.points.len() - 1 - span];
+    let after = &next.points[span];
+    let incoming = (end.y - before.y).atan2(end.x - before.x);
+    let outgoing = (after.y - start.y).atan2(after.x - start.x);
+    let turn = (outgoing - incoming).rem_euclid(2.0 * PI);
+    turn.min(2.0 * PI - turn).to_degrees() <= params.max_turn
+}
+
 /// Detect shapes from a collection of strokes
 pub fn detect_shapes(strokes: &[Stroke]) -> Vec<DetectedShape> {
     let params = DetectionParams::default();
     let mut shapes = Vec::new();
 
-    for stroke in strokes {
-        if stroke.points.len() < params.min_points {
+    // Highlighter ink marks emphasis rather than content (see `detect_emphasis`)
+    let ink: Vec<Stroke> = strokes.iter().filter(|s| !is_highlighter(s)).cloned().collect();
+    let strokes = ink.as_slice();
+
+    // Fragments drawn with brief pen lifts are classified as one stroke
+    for logical in merge_pen_lifts(strokes, &PenLiftParams::default()) {
+        if logical.stroke.points.len() < params.min_points {
             continue;
         }
 
-        if let Some(shape) = detect_shape_from_stroke(stroke, &params) {
+        if let Some(mut shape) = detect_shape_from_stroke(&logical.stroke, &params) {
+            shape.stroke_ids = logical.stroke_ids;
             shapes.push(shape);
         }
     }
@@ -258,12 +375,19 @@ fn detect_shape_from_stroke(stroke: &Stroke, params: &DetectionParams) -> Option
         },
         start_point: Some((raw_points.first()?.x, raw_points.first()?.y)),
         end_point: Some((raw_points.last()?.x, raw_points.last()?.y)),
-        corner_radius: None,
+        corner_radius: if shape_type == ShapeType::Rectangle {
+            Some(estimate_corner_radius(raw_points))
+        } else {
+            None
+        },
         arrow_head: if shape_type == ShapeType::Arrow {
             detect_arrow_head(points, params.arrow_angle_tolerance)
         } else {
             None
         },
+        bpmn: None,
+        icon: None,
+        wireframe: None,
     };
 
     Some(DetectedShape {
@@ -273,9 +397,48 @@ fn detect_shape_from_stroke(stroke: &Stroke, params: &DetectionParams) -> Option
         confidence,
         stroke_ids: vec![stroke.id.clone()],
         properties,
+        label: None,
+        parent_id: None,
+    })
+}
+
+/// Estimate a rectangle's corner radius from how far the stroke stays away
+/// from its bounding box corners (a quarter circle of radius r misses the
+/// corner by r·(√2 − 1))
+fn estimate_corner_radius(points: &[Point]) -> f64 {
+    let bounds = calculate_bounds(points);
+    let corners = [
+        (bounds.x, bounds.y),
+        (bounds.x + bounds.width, bounds.y),
+        (bounds.x + bounds.width, bounds.y + bounds.height),
+        (bounds.x, bounds.y + bounds.height),
+    ];
+    let mean_gap = corners
+        .iter()
+        .map(|&(cx, cy)| {
+            points
+                .iter()
+                .map(|p| ((p.x - cx).powi(2) + (p.y - cy).powi(2)).sqrt())
+                .fold(f64::INFINITY, f64::min)
+        })
+        .sum::<f64>()
+        / corners.len() as f64;
+    (mean_gap / (std::f64::consts::SQRT_2 - 1.0)).min(bounds.width.min(bounds.height) / 2.0)
+}
+
+/// Whether a rectangle was drawn with clearly rounded corners
+pub fn has_rounded_corners(shape: &DetectedShape) -> bool {
+    let min_side = shape.bounds.width.min(shape.bounds.height);
+    shape.properties.corner_radius.is_some_and(|r| {
+        r >= ROUNDED_CORNER_MIN_RADIUS && r >= min_side * ROUNDED_CORNER_MIN_RATIO
     })
 }
 
+/// Smallest corner radius, in canvas pixels, that counts as rounded
+const ROUNDED_CORNER_MIN_RADIUS: f64 = 8.0;
+/// Smallest corner radius relative to the shorter side that counts as rounded
+const ROUNDED_CORNER_MIN_RATIO: f64 = 0.2;
+
 /// Calculate bounding box of points
 fn calculate_bounds(points: &[Point]) -> ShapeBounds {
     let mut min_x = f64::MAX;
@@ -674,6 +837,767 @@ fn merge_shapes(individual: Vec<DetectedShape>, compound: Vec<DetectedShape>) ->
     result
 }
 
+/// BPMN recognition parameters
+#[derive(Debug, Clone)]
+pub struct BpmnParams {
+    /// Stroke width relative to the median pen width above which a circle is an end event
+    pub thick_stroke_ratio: f64,
+    /// Minimum width/height ratio for a rectangle to be a pool or lane band
+    pub band_aspect_ratio: f64,
+    /// Minimum width of a pool or lane band in canvas pixels
+    pub band_min_width: f64,
+    /// Maximum gateway marker size relative to the enclosing diamond
+    pub marker_max_ratio: f64,
+}
+
+impl Default for BpmnParams {
+    fn default() -> Self {
+        Self {
+            thick_stroke_ratio: 1.8,
+            band_aspect_ratio: 3.0,
+            band_min_width: 300.0,
+            marker_max_ratio: 0.8,
+        }
+    }
+}
+
+/// Assign BPMN roles to detected shapes.
+///
+/// Thin circles become start events, thick circles end events and double
+/// circles intermediate events. Diamonds containing an X or + marker become
+/// exclusive or parallel gateways (the marker strokes are folded into the
+/// gateway), long horizontal bands become pools and lanes, and rounded
+/// rectangles become tasks.
+///
+/// The roles and merges are only kept when the result shows BPMN evidence
+/// (see [`has_bpmn_evidence`]) or `force` is set because the user asked for
+/// a BPMN diagram; other diagrams are left untouched.
+pub fn annotate_bpmn(shapes: &mut Vec<DetectedShape>, strokes: &[Stroke], force: bool) {
+    let mut annotated = shapes.clone();
+    assign_bpmn_roles(&mut annotated, strokes);
+    if force || has_bpmn_evidence(&annotated) {
+        *shapes = annotated;
+    }
+}
+
+/// Tasks alongside a gateway, a pool or lane, or a start/end event pair
+pub fn has_bpmn_evidence(shapes: &[DetectedShape]) -> bool {
+    let roles: Vec<&BpmnElement> = shapes.iter().filter_map(|s| s.properties.bpmn.as_ref()).collect();
+    let has = |matches: fn(&BpmnElement) -> bool| roles.iter().any(|role| matches(role));
+
+    let has_event_pair = has(|e| *e == BpmnElement::StartEvent)
+        && has(|e| matches!(e, BpmnElement::EndEvent | BpmnElement::IntermediateEvent));
+    has(|e| *e == BpmnElement::Task)
+        && (has(|e| matches!(e, BpmnElement::ExclusiveGateway | BpmnElement::ParallelGateway))
+            || has(|e| matches!(e, BpmnElement::Pool | BpmnElement::Lane))
+            || has_event_pair)
+}
+
+fn assign_bpmn_roles(shapes: &mut Vec<DetectedShape>, strokes: &[Stroke]) {
+    let params = BpmnParams::default();
+
+    merge_concentric_circles(shapes);
+    absorb_gateway_markers(shapes, &params);
+
+    let median_width = median_stroke_width(strokes);
+    let bands = find_bands(shapes, &params);
+
+    let roles: Vec<Option<BpmnElement>> = shapes
+        .iter()
+        .enumerate()
+        .map(|(i, shape)| {
+            if shape.properties.bpmn.is_some() {
+                return shape.properties.bpmn.clone();
+            }
+            match shape.shape_type {
+                ShapeType::Circle | ShapeType::Ellipse => {
+                    let width = shape_stroke_width(shape, strokes);
+                    if median_width > 0.0 && width >= median_width * params.thick_stroke_ratio {
+                        Some(BpmnElement::EndEvent)
+                    } else {
+                        Some(BpmnElement::StartEvent)
+                    }
+                }
+                ShapeType::Rectangle if bands.contains(&i) => {
+                    let (cx, cy) = bounds_center(&shape.bounds);
+                    let nested = bands
+                        .iter()
+                        .any(|&j| j != i && bounds_contain(&shapes[j].bounds, cx, cy));
+                    if nested {
+                        Some(BpmnElement::Lane)
+                    } else {
+                        Some(BpmnElement::Pool)
+                    }
+                }
+                ShapeType::Rectangle if has_rounded_corners(shape) => Some(BpmnElement::Task),
+                _ => None,
+            }
+        })
+        .collect();
+
+    for (shape, role) in shapes.iter_mut().zip(roles) {
+        shape.properties.bpmn = role;
+    }
+}
+
+/// Merge pairs of concentric circles into a single intermediate event
+fn merge_concentric_circles(shapes: &mut Vec<DetectedShape>) {
+    let mut removed = std::collections::HashSet::new();
+
+    for i in 0..shapes.len() {
+        if removed.contains(&i) || !is_round(&shapes[i]) {
+            continue;
+        }
+        for j in (i + 1)..shapes.len() {
+            if removed.contains(&j) || !is_round(&shapes[j]) {
+                continue;
+            }
+
+            let (r_i, r_j) = (shape_radius(&shapes[i]), shape_radius(&shapes[j]));
+            let (outer_r, inner_r) = (r_i.max(r_j), r_i.min(r_j));
+            if outer_r == 0.0 {
+                continue;
+            }
+
+            let (cx_i, cy_i) = bounds_center(&shapes[i].bounds);
+            let (cx_j, cy_j) = bounds_center(&shapes[j].bounds);
+            let center_distance = ((cx_i - cx_j).powi(2) + (cy_i - cy_j).powi(2)).sqrt();
+
+            if center_distance < outer_r * 0.25 && inner_r / outer_r >= 0.6 {
+                let (outer, inner) = if r_i >= r_j { (i, j) } else { (j, i) };
+                let inner_ids = shapes[inner].stroke_ids.clone();
+                shapes[outer].stroke_ids.extend(inner_ids);
+                shapes[outer].properties.bpmn = Some(BpmnElement::IntermediateEvent);
+                removed.insert(inner);
+                if inner == i {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut index = 0;
+    shapes.retain(|_| {
+        let keep = !removed.contains(&index);
+        index += 1;
+        keep
+    });
+}
+
+/// Fold X / + marker strokes drawn inside diamonds into gateway shapes
+fn absorb_gateway_markers(shapes: &mut Vec<DetectedShape>, params: &BpmnParams) {
+    let mut absorbed = std::collections::HashSet::new();
+
+    for d in 0..shapes.len() {
+        if shapes[d].shape_type != ShapeType::Diamond {
+            continue;
+        }
+
+        let diamond = shapes[d].bounds.clone();
+        let (dcx, dcy) = bounds_center(&diamond);
+        let max_marker = diamond.width.max(diamond.height) * params.marker_max_ratio;
+
+        let markers: Vec<usize> = (0..shapes.len())
+            .filter(|&m| {
+                let marker = &shapes[m];
+                let (mcx, mcy) = bounds_center(&marker.bounds);
+                m != d
+                    && !absorbed.contains(&marker.id)
+                    && matches!(
+                        marker.shape_type,
+                        ShapeType::Line | ShapeType::Arrow | ShapeType::Connector | ShapeType::Freeform
+                    )
+                    && marker.bounds.width.max(marker.bounds.height) <= max_marker
+                    && (mcx - dcx).abs() < diamond.width / 4.0
+                    && (mcy - dcy).abs() < diamond.height / 4.0
+            })
+            .collect();
+
+        if markers.is_empty() {
+            continue;
+        }
+
+        // Diagonal strokes form an X (exclusive), axis-aligned ones a + (parallel)
+        let mut diagonal = 0;
+        let mut axis_aligned = 0;
+        for &m in &markers {
+            let props = &shapes[m].properties;
+            if let (Some((sx, sy)), Some((ex, ey))) = (props.start_point, props.end_point) {
+                let angle = (ey - sy).atan2(ex - sx).to_degrees().rem_euclid(180.0);
+                let from_axis = (angle % 90.0).min(90.0 - angle % 90.0);
+                if from_axis > 22.5 {
+                    diagonal += 1;
+                } else {
+                    axis_aligned += 1;
+                }
+            }
+        }
+
+        let role = if axis_aligned > diagonal {
+            BpmnElement::ParallelGateway
+        } else {
+            BpmnElement::ExclusiveGateway
+        };
+
+        let marker_ids: Vec<String> = markers
+            .iter()
+            .flat_map(|&m| shapes[m].stroke_ids.clone())
+            .collect();
+        for &m in &markers {
+            absorbed.insert(shapes[m].id.clone());
+        }
+
+        shapes[d].stroke_ids.extend(marker_ids);
+        shapes[d].properties.bpmn = Some(role);
+    }
+
+    shapes.retain(|s| !absorbed.contains(&s.id));
+}
+
+/// Indices of rectangles that form pool/lane bands
+fn find_bands(shapes: &[DetectedShape], params: &BpmnParams) -> Vec<usize> {
+    shapes
+        .iter()
+        .enumerate()
+        .filter(|(i, shape)| {
+            shape.shape_type == ShapeType::Rectangle
+                && shape.bounds.height > 0.0
+                && shape.bounds.width / shape.bounds.height >= params.band_aspect_ratio
+                && shape.bounds.width >= params.band_min_width
+                && shapes.iter().enumerate().any(|(j, other)| {
+                    let (cx, cy) = bounds_center(&other.bounds);
+                    j != *i
+                        && !matches!(
+                            other.shape_type,
+                            ShapeType::Arrow | ShapeType::Line | ShapeType::Connector
+                        )
+                        && bounds_contain(&shape.bounds, cx, cy)
+                })
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn is_round(shape: &DetectedShape) -> bool {
+    matches!(shape.shape_type, ShapeType::Circle | ShapeType::Ellipse)
+}
+
+fn shape_radius(shape: &DetectedShape) -> f64 {
+    shape
+        .properties
+        .radius
+        .unwrap_or_else(|| shape.bounds.width.max(shape.bounds.height) / 2.0)
+}
+
+pub(crate) fn bounds_center(bounds: &ShapeBounds) -> (f64, f64) {
+    (bounds.x + bounds.width / 2.0, bounds.y + bounds.height / 2.0)
+}
+
+pub(crate) fn bounds_contain(bounds: &ShapeBounds, x: f64, y: f64) -> bool {
+    x >= bounds.x && x <= bounds.x + bounds.width && y >= bounds.y && y <= bounds.y + bounds.height
+}
+
+/// Average width of the strokes that make up a shape
+fn shape_stroke_width(shape: &DetectedShape, strokes: &[Stroke]) -> f64 {
+    let widths: Vec<f64> = strokes
+        .iter()
+        .filter(|s| shape.stroke_ids.contains(&s.id))
+        .map(|s| s.width)
+        .collect();
+    if widths.is_empty() {
+        return 0.0;
+    }
+    widths.iter().sum::<f64>() / widths.len() as f64
+}
+
+/// Median width of all pen strokes on the canvas
+fn median_stroke_width(strokes: &[Stroke]) -> f64 {
+    let mut widths: Vec<f64> = strokes
+        .iter()
+        .filter(|s| s.tool != "eraser")
+        .map(|s| s.width)
+        .collect();
+    if widths.is_empty() {
+        return 0.0;
+    }
+    widths.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    widths[widths.len() / 2]
+}
+
+/// Swimlane detection parameters
+#[derive(Debug, Clone)]
+pub struct SwimlaneParams {
+    /// Maximum deviation from horizontal/vertical for a divider, in degrees
+    pub angle_tolerance: f64,
+    /// Minimum divider length relative to the content extent
+    pub min_span_ratio: f64,
+    /// Minimum number of parallel dividers
+    pub min_dividers: usize,
+    /// Minimum band thickness in canvas pixels
+    pub min_band_size: f64,
+    /// Portion of a band (from its start) searched for the header label
+    pub header_ratio: f64,
+}
+
+impl Default for SwimlaneParams {
+    fn default() -> Self {
+        Self {
+            angle_tolerance: 10.0,
+            min_span_ratio: 0.8,
+            min_dividers: 1,
+            min_band_size: 40.0,
+            header_ratio: 0.2,
+        }
+    }
+}
+
+/// Detect swimlanes from long parallel divider lines.
+///
+/// Full-width (or full-height) lines split the content into bands; a single
+/// divider is enough when it leaves content on both sides.
+/// Each band becomes a `Swimlane` shape labelled with the text at its start,
+/// shapes inside a band get the lane as their parent, and the divider lines
+/// themselves are removed.
+pub fn detect_swimlanes(shapes: &mut Vec<DetectedShape>, text_regions: &[TextRegion]) {
+    let params = SwimlaneParams::default();
+
+    let extent = match content_extent(shapes, text_regions) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let horizontal = find_dividers(shapes, &extent, true, &params);
+    let vertical = find_dividers(shapes, &extent, false, &params);
+    let (dividers, is_horizontal) = if horizontal.len() >= vertical.len() {
+        (horizontal, true)
+    } else {
+        (vertical, false)
+    };
+    if dividers.len() < params.min_dividers {
+        return;
+    }
+
+    // Position of each divider across the bands, and the span along them
+    let mut positions: Vec<(f64, usize)> = dividers
+        .iter()
+        .map(|&i| {
+            let (cx, cy) = bounds_center(&shapes[i].bounds);
+            (if is_horizontal { cy } else { cx }, i)
+        })
+        .collect();
+    positions.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let (span_start, span_end) = dividers.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &i| {
+        let b = &shapes[i].bounds;
+        if is_horizontal {
+            (lo.min(b.x), hi.max(b.x + b.width))
+        } else {
+            (lo.min(b.y), hi.max(b.y + b.height))
+        }
+    });
+
+    // Band edges: the dividers, plus the content edges when content lies outside them
+    let (extent_start, extent_end) = if is_horizontal {
+        (extent.y, extent.y + extent.height)
+    } else {
+        (extent.x, extent.x + extent.width)
+    };
+    let mut edges: Vec<(f64, Option<usize>)> =
+        positions.iter().map(|&(pos, i)| (pos, Some(i))).collect();
+    if edges[0].0 - extent_start > params.min_band_size {
+        edges.insert(0, (extent_start, None));
+    }
+    if extent_end - edges[edges.len() - 1].0 > params.min_band_size {
+        edges.push((extent_end, None));
+    }
+
+    let mut lanes = Vec::new();
+    for pair in edges.windows(2) {
+        let (start, start_divider) = pair[0];
+        let (end, end_divider) = pair[1];
+        if end - start < params.min_band_size {
+            continue;
+        }
+
+        let bounds = if is_horizontal {
+            ShapeBounds { x: span_start, y: start, width: span_end - span_start, height: end - start, rotation: 0.0 }
+        } else {
+            ShapeBounds { x: start, y: span_start, width: end - start, height: span_end - span_start, rotation: 0.0 }
+        };
+
+        let stroke_ids = [start_divider, end_divider]
+            .iter()
+            .flatten()
+            .flat_map(|&i| shapes[i].stroke_ids.clone())
+            .collect();
+
+        let label = find_lane_header(&bounds, is_horizontal, text_regions, params.header_ratio);
+        let (cx, cy) = bounds_center(&bounds);
+
+        lanes.push(DetectedShape {
+            id: uuid::Uuid::new_v4().to_string(),
+            shape_type: ShapeType::Swimlane,
+            confidence: 0.7,
+            stroke_ids,
+            properties: ShapeProperties {
+                center_x: cx,
+                center_y: cy,
+                radius: None,
+                start_point: None,
+                end_point: None,
+                corner_radius: None,
+                arrow_head: None,
+                bpmn: None,
+                icon: None,
+                wireframe: None,
+            },
+            label: Some(label),
+            parent_id: None,
+            bounds,
+        });
+    }
+
+    // A lone line with everything on one side is an underline, not a lane split
+    if lanes.is_empty() || (dividers.len() == 1 && lanes.len() < 2) {
+        return;
+    }
+
+    let divider_ids: Vec<String> = dividers.iter().map(|&i| shapes[i].id.clone()).collect();
+    shapes.retain(|s| !divider_ids.contains(&s.id));
+
+    // Assign each non-connector shape to the lane containing its center
+    for shape in shapes.iter_mut() {
+        if matches!(
+            shape.shape_type,
+            ShapeType::Arrow | ShapeType::Line | ShapeType::Connector
+        ) {
+            continue;
+        }
+        let (cx, cy) = bounds_center(&shape.bounds);
+        if let Some(lane) = lanes.iter().find(|l| bounds_contain(&l.bounds, cx, cy)) {
+            shape.parent_id = Some(lane.id.clone());
+        }
+    }
+
+    shapes.extend(lanes);
+}
+
+/// Bounding box of all shapes and text
+fn content_extent(shapes: &[DetectedShape], text_regions: &[TextRegion]) -> Option<ShapeBounds> {
+    if shapes.is_empty() {
+        return None;
+    }
+
+    let boxes = shapes
+        .iter()
+        .map(|s| (s.bounds.x, s.bounds.y, s.bounds.width, s.bounds.height))
+        .chain(
+            text_regions
+                .iter()
+                .map(|t| (t.bounds.x, t.bounds.y, t.bounds.width, t.bounds.height)),
+        );
+
+    let (min_x, min_y, max_x, max_y) = boxes.fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(min_x, min_y, max_x, max_y), (x, y, w, h)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x + w), max_y.max(y + h))
+        },
+    );
+
+    Some(ShapeBounds {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x,
+        height: max_y - min_y,
+        rotation: 0.0,
+    })
+}
+
+/// Indices of line-like strokes spanning the content in the given orientation
+fn find_dividers(
+    shapes: &[DetectedShape],
+    extent: &ShapeBounds,
+    horizontal: bool,
+    params: &SwimlaneParams,
+) -> Vec<usize> {
+    let extent_span = if horizontal { extent.width } else { extent.height };
+    if extent_span <= 0.0 {
+        return Vec::new();
+    }
+
+    shapes
+        .iter()
+        .enumerate()
+        .filter(|(_, shape)| {
+            // Long dividers often come out slightly wavy (connectors) or with a stray hook (arrows)
+            let line_like = match shape.shape_type {
+                ShapeType::Line | ShapeType::Connector => true,
+                ShapeType::Arrow => shape.properties.arrow_head.is_none(),
+                _ => false,
+            };
+            let thickness = if horizontal { shape.bounds.height } else { shape.bounds.width };
+            if !line_like || thickness > params.min_band_size {
+                return false;
+            }
+            let (Some((sx, sy)), Some((ex, ey))) =
+                (shape.properties.start_point, shape.properties.end_point)
+            else {
+                return false;
+            };
+
+            let angle = (ey - sy).atan2(ex - sx).to_degrees().rem_euclid(180.0);
+            let deviation = if horizontal {
+                angle.min(180.0 - angle)
+            } else {
+                (angle - 90.0).abs()
+            };
+            let span = if horizontal { (ex - sx).abs() } else { (ey - sy).abs() };
+
+            deviation <= params.angle_tolerance && span >= extent_span * params.min_span_ratio
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Text at the start of a band (left for horizontal lanes, top for vertical ones)
+fn find_lane_header(
+    band: &ShapeBounds,
+    horizontal: bool,
+    text_regions: &[TextRegion],
+    header_ratio: f64,
+) -> String {
+    text_regions
+        .iter()
+        .filter(|t| {
+            let cx = t.bounds.x + t.bounds.width / 2.0;
+            let cy = t.bounds.y + t.bounds.height / 2.0;
+            let in_header = if horizontal {
+                cx <= band.x + band.width * header_ratio
+            } else {
+                cy <= band.y + band.height * header_ratio
+            };
+            bounds_contain(band, cx, cy) && in_header
+        })
+        .min_by(|a, b| {
+            let (ka, kb) = if horizontal { (a.bounds.x, b.bounds.x) } else { (a.bounds.y, b.bounds.y) };
+            ka.partial_cmp(&kb).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|t| t.text.clone())
+        .unwrap_or_default()
+}
+
+/// Tool name recorded on highlighter strokes
+pub const HIGHLIGHTER_TOOL: &str = "highlighter";
+
+pub fn is_highlighter(stroke: &Stroke) -> bool {
+    stroke.tool == HIGHLIGHTER_TOOL
+}
+
+/// Turn highlighter strokes into `Highlight` emphasis regions.
+///
+/// Each region covers its strokes' ink; highlighter strokes whose ink
+/// overlaps are merged into one region.
+pub fn detect_emphasis(shapes: &mut Vec<DetectedShape>, strokes: &[Stroke]) {
+    let mut regions: Vec<(ShapeBounds, Vec<String>)> = Vec::new();
+
+    for stroke in strokes.iter().filter(|s| is_highlighter(s) && !s.points.is_empty()) {
+        let half = stroke.width / 2.0;
+        let (min_x, min_y, max_x, max_y) = stroke.points.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(x0, y0, x1, y1), p| (x0.min(p.x), y0.min(p.y), x1.max(p.x), y1.max(p.y)),
+        );
+        let mut bounds = ShapeBounds {
+            x: min_x - half,
+            y: min_y - half,
+            width: max_x - min_x + stroke.width,
+            height: max_y - min_y + stroke.width,
+            rotation: 0.0,
+        };
+        let mut stroke_ids = vec![stroke.id.clone()];
+
+        // Absorb every region this one overlaps, growing as it goes
+        while let Some(i) = regions.iter().position(|(other, _)| bounds_overlap(&bounds, other)) {
+            let (other, ids) = regions.remove(i);
+            bounds = bounds_union(&bounds, &other);
+            stroke_ids.splice(0..0, ids);
+        }
+        regions.push((bounds, stroke_ids));
+    }
+
+    for (bounds, stroke_ids) in regions {
+        let (cx, cy) = bounds_center(&bounds);
+        shapes.push(DetectedShape {
+            id: uuid::Uuid::new_v4().to_string(),
+            shape_type: ShapeType::Highlight,
+            confidence: 1.0,
+            stroke_ids,
+            properties: ShapeProperties {
+                center_x: cx,
+                center_y: cy,
+                radius: None,
+                start_point: None,
+                end_point: None,
+                corner_radius: None,
+                arrow_head: None,
+                bpmn: None,
+                icon: None,
+                wireframe: None,
+            },
+            label: None,
+            parent_id: None,
+            bounds,
+        });
+    }
+}
+
+fn bounds_overlap(a: &ShapeBounds, b: &ShapeBounds) -> bool {
+    a.x <= b.x + b.width && b.x <= a.x + a.width && a.y <= b.y + b.height && b.y <= a.y + a.height
+}
+
+fn bounds_union(a: &ShapeBounds, b: &ShapeBounds) -> ShapeBounds {
+    let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+    ShapeBounds {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+        rotation: 0.0,
+    }
+}
+
+/// Free-standing OCR text, optionally linked to a shape by a leader line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub text_id: String,
+    pub text: String,
+    pub bounds: crate::ocr::TextBounds,
+    pub font_size: f64,
+    /// Shape the note points at
+    pub target_shape_id: Option<String>,
+    /// Line/arrow shape linking the note to its target
+    pub leader_shape_id: Option<String>,
+}
+
+/// Whether OCR text labels a shape: its center falls inside the shape's
+/// bounds grown by `margin`.
+///
+/// Label lookup and [`detect_annotations`] both use this, so a text region
+/// is either a label or an annotation, never both.
+pub fn text_labels_shape(shape: &DetectedShape, text: &TextRegion, margin: f64) -> bool {
+    let tx = text.bounds.x + text.bounds.width / 2.0;
+    let ty = text.bounds.y + text.bounds.height / 2.0;
+    tx >= shape.bounds.x - margin
+        && tx <= shape.bounds.x + shape.bounds.width + margin
+        && ty >= shape.bounds.y - margin
+        && ty <= shape.bounds.y + shape.bounds.height + margin
+}
+
+/// Find OCR text that is not a shape label.
+///
+/// Text that labels a shape (see [`text_labels_shape`]) is skipped;
+/// everything else is an annotation. When a short line or arrow runs from
+/// the text to a shape, the annotation is linked to it.
+pub fn detect_annotations(
+    shapes: &[DetectedShape],
+    text_regions: &[TextRegion],
+    margin: f64,
+) -> Vec<Annotation> {
+    let is_link = |s: &DetectedShape| {
+        matches!(s.shape_type, ShapeType::Arrow | ShapeType::Line | ShapeType::Connector)
+    };
+    // Containers and highlights sit behind text rather than being labelled by it
+    let is_container = |s: &DetectedShape| {
+        matches!(s.shape_type, ShapeType::Swimlane | ShapeType::Highlight)
+            || matches!(s.properties.bpmn, Some(BpmnElement::Pool | BpmnElement::Lane))
+    };
+
+    let mut used_leaders: Vec<String> = Vec::new();
+    let mut annotations = Vec::new();
+
+    for text in text_regions {
+        // Unreadable regions from the fallback recognizer are only useful as labels
+        if text.text.contains(crate::ocr::PLACEHOLDER_TEXT) {
+            continue;
+        }
+
+        let is_label = shapes.iter().any(|s| {
+            if s.label.as_deref().is_some_and(|l| l.contains(&text.text)) {
+                return true;
+            }
+            !is_link(s) && !is_container(s) && text_labels_shape(s, text, margin)
+        });
+        if is_label {
+            continue;
+        }
+
+        // A short line with one end at the text and the other at a shape
+        let link_threshold = 30.0;
+        let max_leader_length = 200.0;
+        let near_text = |(x, y): (f64, f64)| {
+            x >= text.bounds.x - link_threshold
+                && x <= text.bounds.x + text.bounds.width + link_threshold
+                && y >= text.bounds.y - link_threshold
+                && y <= text.bounds.y + text.bounds.height + link_threshold
+        };
+
+        let mut leader = None;
+        for line in shapes.iter().filter(|s| is_link(s) && !used_leaders.contains(&s.id)) {
+            let (Some(start), Some(end)) = (line.properties.start_point, line.properties.end_point) else {
+                continue;
+            };
+            if point_distance(start, end) > max_leader_length {
+                continue;
+            }
+            let far_end = if near_text(start) {
+                end
+            } else if near_text(end) {
+                start
+            } else {
+                continue;
+            };
+
+            let target = shapes
+                .iter()
+                .filter(|s| !is_link(s) && !is_container(s))
+                .filter(|s| {
+                    far_end.0 >= s.bounds.x - link_threshold
+                        && far_end.0 <= s.bounds.x + s.bounds.width + link_threshold
+                        && far_end.1 >= s.bounds.y - link_threshold
+                        && far_end.1 <= s.bounds.y + s.bounds.height + link_threshold
+                })
+                .min_by(|a, b| {
+                    let da = point_distance(far_end, bounds_center(&a.bounds));
+                    let db = point_distance(far_end, bounds_center(&b.bounds));
+                    da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+            if let Some(target) = target {
+                leader = Some((line.id.clone(), target.id.clone()));
+                break;
+            }
+        }
+
+        if let Some((leader_id, _)) = &leader {
+            used_leaders.push(leader_id.clone());
+        }
+
+        annotations.push(Annotation {
+            text_id: text.id.clone(),
+            text: text.text.clone(),
+            bounds: text.bounds.clone(),
+            font_size: text.font_size_estimate,
+            target_shape_id: leader.as_ref().map(|(_, target)| target.clone()),
+            leader_shape_id: leader.map(|(line, _)| line),
+        });
+    }
+
+    annotations
+}
+
+fn point_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
 /// Classify the overall diagram type
 pub fn classify_diagram(
     shapes: &[DetectedShape],
@@ -684,8 +1608,28 @@ pub fn classify_diagram(
     let mut arrow_count = 0;
     let mut circle_count = 0;
     let mut connector_count = 0;
+    let mut gateway_count = 0;
+    let mut start_event_count = 0;
+    let mut end_event_count = 0;
+    let mut band_count = 0;
+    let mut placeholder_count = 0;
+    let mut control_count = 0;
 
     for shape in shapes {
+        match shape.properties.wireframe {
+            Some(WireframeElement::ImagePlaceholder) => placeholder_count += 1,
+            Some(_) => control_count += 1,
+            None => {}
+        }
+
+        match shape.properties.bpmn {
+            Some(BpmnElement::ExclusiveGateway | BpmnElement::ParallelGateway) => gateway_count += 1,
+            Some(BpmnElement::StartEvent) => start_event_count += 1,
+            Some(BpmnElement::EndEvent | BpmnElement::IntermediateEvent) => end_event_count += 1,
+            Some(BpmnElement::Pool | BpmnElement::Lane) => band_count += 1,
+            Some(BpmnElement::Task) | None => {}
+        }
+
         match shape.shape_type {
             ShapeType::Rectangle => rectangle_count += 1,
             ShapeType::Diamond => diamond_count += 1,
@@ -699,6 +1643,7 @@ pub fn classify_diagram(
     // Check for flowchart indicators in text
     let flowchart_keywords = ["start", "end", "if", "yes", "no", "begin", "process"];
     let uml_keywords = ["class", "interface", "extends", "implements", "public", "private"];
+    let bpmn_keywords = ["task", "gateway", "event", "pool", "lane"];
     
     let text_content: String = text_regions
         .iter()
@@ -716,10 +1661,29 @@ pub fn classify_diagram(
         .filter(|k| text_content.contains(*k))
         .count() as f64;
 
+    let bpmn_text_score: f64 = bpmn_keywords
+        .iter()
+        .filter(|k| text_content.contains(*k))
+        .count() as f64;
+
     // Determine diagram type
     let total_shapes = shapes.len() as f64;
-    
-    if diamond_count > 0 && arrow_count > 0 && rectangle_count > 0 {
+    let has_event_pair = start_event_count > 0 && end_event_count > 0;
+
+    if has_bpmn_evidence(shapes) {
+        // Gateway markers, lanes or start/end event pairs indicate BPMN
+        let confidence = (0.4
+            + gateway_count as f64 * 0.15
+            + band_count as f64 * 0.1
+            + if has_event_pair { 0.15 } else { 0.0 }
+            + bpmn_text_score * 0.05)
+            .min(0.95);
+        ("bpmn".to_string(), confidence)
+    } else if has_wireframe_evidence(shapes) {
+        // Image placeholders and form controls indicate a UI mockup
+        let confidence = (0.4 + placeholder_count as f64 * 0.15 + control_count as f64 * 0.1).min(0.9);
+        ("wireframe".to_string(), confidence)
+    } else if diamond_count > 0 && arrow_count > 0 && rectangle_count > 0 {
         // Likely a flowchart
         let confidence = (0.3 + flowchart_text_score * 0.1 + 
             (diamond_count + arrow_count) as f64 / total_shapes.max(1.0) * 0.3)
@@ -740,6 +1704,55 @@ pub fn classify_diagram(
     }
 }
 
+/// Test fixture: a black 2px pen stroke on the default layer
+#[cfg(test)]
+pub(crate) fn test_stroke(id: &str, points: Vec<Point>) -> Stroke {
+    Stroke {
+        id: id.to_string(),
+        points,
+        color: "#000000".to_string(),
+        width: 2.0,
+        tool: "pen".to_string(),
+        layer_id: crate::layers::DEFAULT_LAYER_ID.to_string(),
+        curves: Vec::new(),
+    }
+}
+
+/// Test fixture: a shape of `shape_type` drawn from `start` to `end` with a
+/// single stroke named after it
+#[cfg(test)]
+pub(crate) fn test_shape(id: &str, shape_type: ShapeType, start: (f64, f64), end: (f64, f64)) -> DetectedShape {
+    let bounds = ShapeBounds {
+        x: start.0.min(end.0),
+        y: start.1.min(end.1),
+        width: (end.0 - start.0).abs(),
+        height: (end.1 - start.1).abs(),
+        rotation: 0.0,
+    };
+    let (center_x, center_y) = bounds_center(&bounds);
+    DetectedShape {
+        id: id.to_string(),
+        shape_type,
+        bounds,
+        confidence: 0.9,
+        stroke_ids: vec![id.to_string()],
+        properties: ShapeProperties {
+            center_x,
+            center_y,
+            radius: None,
+            start_point: Some(start),
+            end_point: Some(end),
+            corner_radius: None,
+            arrow_head: None,
+            bpmn: None,
+            icon: None,
+            wireframe: None,
+        },
+        label: None,
+        parent_id: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -806,4 +1819,246 @@ mod tests {
         let straightness = calculate_straightness(&straight_points);
         assert!(straightness > 0.99);
     }
+
+    #[test]
+    fn test_annotate_bpmn_gateway_markers() {
+        let mut shapes = vec![
+            test_shape("gw", ShapeType::Diamond, (100.0, 100.0), (180.0, 180.0)),
+            test_shape("x1", ShapeType::Line, (125.0, 125.0), (155.0, 155.0)),
+            // The second marker runs bottom-left to top-right
+            test_shape("x2", ShapeType::Line, (125.0, 155.0), (155.0, 125.0)),
+            test_shape("task", ShapeType::Rectangle, (300.0, 100.0), (420.0, 160.0)),
+        ];
+        shapes[3].properties.corner_radius = Some(15.0);
+
+        annotate_bpmn(&mut shapes, &[], false);
+
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0].properties.bpmn, Some(BpmnElement::ExclusiveGateway));
+        assert_eq!(shapes[0].stroke_ids.len(), 3);
+        assert_eq!(shapes[1].properties.bpmn, Some(BpmnElement::Task));
+    }
+
+    #[test]
+    fn test_classify_bpmn() {
+        let mut shapes = vec![
+            test_shape("pool", ShapeType::Rectangle, (0.0, 0.0), (900.0, 200.0)),
+            test_shape("start", ShapeType::Circle, (40.0, 80.0), (80.0, 120.0)),
+            test_shape("task", ShapeType::Rectangle, (150.0, 70.0), (270.0, 130.0)),
+            test_shape("flow", ShapeType::Arrow, (80.0, 100.0), (150.0, 100.0)),
+        ];
+        shapes[2].properties.corner_radius = Some(15.0);
+
+        annotate_bpmn(&mut shapes, &[], false);
+        assert_eq!(shapes[0].properties.bpmn, Some(BpmnElement::Pool));
+        assert_eq!(shapes[1].properties.bpmn, Some(BpmnElement::StartEvent));
+        assert_eq!(shapes[2].properties.bpmn, Some(BpmnElement::Task));
+
+        let (diagram_type, confidence) = classify_diagram(&shapes, &[]);
+        assert_eq!(diagram_type, "bpmn");
+        assert!(confidence > 0.4);
+    }
+
+    #[test]
+    fn test_flowchart_is_left_alone_by_bpmn() {
+        let flowchart = vec![
+            test_shape("box", ShapeType::Rectangle, (0.0, 0.0), (120.0, 60.0)),
+            test_shape("diamond", ShapeType::Diamond, (200.0, 0.0), (280.0, 80.0)),
+            test_shape("mark", ShapeType::Line, (225.0, 25.0), (255.0, 55.0)),
+            test_shape("outer", ShapeType::Circle, (400.0, 0.0), (460.0, 60.0)),
+            test_shape("inner", ShapeType::Circle, (405.0, 5.0), (455.0, 55.0)),
+            test_shape("flow", ShapeType::Arrow, (120.0, 30.0), (200.0, 30.0)),
+        ];
+
+        let mut shapes = flowchart.clone();
+        shapes[0].properties.corner_radius = Some(1.0);
+        annotate_bpmn(&mut shapes, &[], false);
+        assert_eq!(shapes.len(), flowchart.len());
+        assert!(shapes.iter().all(|s| s.properties.bpmn.is_none()));
+        assert_ne!(classify_diagram(&shapes, &[]).0, "bpmn");
+
+        // Asking for BPMN applies the roles and merges regardless
+        annotate_bpmn(&mut shapes, &[], true);
+        assert_eq!(shapes.len(), flowchart.len() - 2);
+        assert_eq!(shapes[1].properties.bpmn, Some(BpmnElement::ExclusiveGateway));
+    }
+
+    #[test]
+    fn test_estimate_corner_radius() {
+        let pt = |x: f64, y: f64| Point { x, y, pressure: None, timestamp: 0 };
+        let sharp: Vec<Point> = [(0.0, 0.0), (100.0, 0.0), (100.0, 60.0), (0.0, 60.0), (0.0, 0.0)]
+            .iter()
+            .map(|&(x, y)| pt(x, y))
+            .collect();
+        assert!(estimate_corner_radius(&sharp) < 1.0);
+
+        // Rectangle with quarter-circle corners of radius 15
+        let r = 15.0;
+        let corners = [(100.0 - r, r, -90.0), (100.0 - r, 60.0 - r, 0.0), (r, 60.0 - r, 90.0), (r, r, 180.0)];
+        let rounded: Vec<Point> = corners
+            .iter()
+            .flat_map(|&(cx, cy, start): &(f64, f64, f64)| {
+                (0..=9).map(move |i| {
+                    let a = (start + i as f64 * 10.0).to_radians();
+                    pt(cx + r * a.cos(), cy + r * a.sin())
+                })
+            })
+            .collect();
+        assert!((estimate_corner_radius(&rounded) - r).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_detect_swimlanes_from_dividers() {
+        use crate::ocr::TextBounds;
+
+        let mut shapes = vec![
+            test_shape("top", ShapeType::Line, (0.0, 0.0), (1000.0, 0.0)),
+            test_shape("middle", ShapeType::Line, (0.0, 200.0), (1000.0, 200.0)),
+            test_shape("bottom", ShapeType::Line, (0.0, 400.0), (1000.0, 400.0)),
+            test_shape("task", ShapeType::Rectangle, (300.0, 250.0), (420.0, 310.0)),
+        ];
+        let text_regions = vec![TextRegion {
+            id: "t".to_string(),
+            text: "Sales".to_string(),
+            bounds: TextBounds { x: 10.0, y: 280.0, width: 60.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 14.0,
+        }];
+
+        detect_swimlanes(&mut shapes, &text_regions);
+
+        let lanes: Vec<_> = shapes.iter().filter(|s| s.shape_type == ShapeType::Swimlane).collect();
+        assert_eq!(lanes.len(), 2);
+        assert!(!shapes.iter().any(|s| s.shape_type == ShapeType::Line));
+
+        let second = lanes.iter().find(|l| l.bounds.y == 200.0).unwrap();
+        assert_eq!(second.label.as_deref(), Some("Sales"));
+
+        let task = shapes.iter().find(|s| s.shape_type == ShapeType::Rectangle).unwrap();
+        assert_eq!(task.parent_id.as_ref(), Some(&second.id));
+    }
+
+    #[test]
+    fn test_single_divider_splits_lanes() {
+        let mut divider = test_shape("divider", ShapeType::Connector, (0.0, 195.0), (1000.0, 205.0));
+        divider.properties.start_point = Some((0.0, 200.0));
+        divider.properties.end_point = Some((1000.0, 200.0));
+        let mut shapes = vec![
+            divider,
+            test_shape("upper", ShapeType::Rectangle, (300.0, 50.0), (420.0, 110.0)),
+            test_shape("lower", ShapeType::Rectangle, (300.0, 300.0), (420.0, 360.0)),
+        ];
+
+        detect_swimlanes(&mut shapes, &[]);
+
+        let lanes = shapes.iter().filter(|s| s.shape_type == ShapeType::Swimlane).count();
+        assert_eq!(lanes, 2);
+        assert!(!shapes.iter().any(|s| s.shape_type == ShapeType::Connector));
+
+        // A headless arrow under a single box is just an underline
+        let mut shapes = vec![
+            test_shape("underline", ShapeType::Arrow, (0.0, 200.0), (1000.0, 200.0)),
+            test_shape("box", ShapeType::Rectangle, (300.0, 50.0), (420.0, 110.0)),
+        ];
+        detect_swimlanes(&mut shapes, &[]);
+        assert!(!shapes.iter().any(|s| s.shape_type == ShapeType::Swimlane));
+    }
+
+    #[test]
+    fn test_detect_annotations() {
+        use crate::ocr::TextBounds;
+
+        let text = |id: &str, x: f64, y: f64| TextRegion {
+            id: id.to_string(),
+            text: id.to_string(),
+            bounds: TextBounds { x, y, width: 80.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 18.0,
+        };
+        let box_shape = test_shape("box", ShapeType::Rectangle, (100.0, 100.0), (220.0, 160.0));
+        let leader = test_shape("leader", ShapeType::Line, (300.0, 60.0), (220.0, 120.0));
+        let shapes = vec![box_shape.clone(), leader.clone()];
+        let texts = vec![text("label", 120.0, 120.0), text("note", 300.0, 40.0), text("margin", 600.0, 600.0)];
+
+        let annotations = detect_annotations(&shapes, &texts, 20.0);
+
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].text, "note");
+        assert_eq!(annotations[0].target_shape_id.as_ref(), Some(&box_shape.id));
+        assert_eq!(annotations[0].leader_shape_id.as_ref(), Some(&leader.id));
+        assert_eq!(annotations[1].text, "margin");
+        assert!(annotations[1].target_shape_id.is_none());
+
+        // A long connector that happens to start at the text is not a leader line
+        let connector = test_shape("connector", ShapeType::Line, (300.0, 60.0), (800.0, 120.0));
+        let far_box = test_shape("far", ShapeType::Rectangle, (780.0, 100.0), (900.0, 160.0));
+        let annotations = detect_annotations(&[far_box, connector], &texts[1..2], 20.0);
+        assert!(annotations[0].target_shape_id.is_none());
+    }
+
+    #[test]
+    fn test_highlighter_strokes_become_emphasis_regions() {
+        let stroke = |id: &str, tool: &str, y: f64| Stroke {
+            color: "#ffeb3b80".to_string(),
+            width: 20.0,
+            tool: tool.to_string(),
+            ..test_stroke(id, (0..=20).map(|i| Point { x: i as f64 * 10.0, y, pressure: None, timestamp: i }).collect())
+        };
+        // Two overlapping highlighter passes and one separate one
+        let strokes = vec![
+            stroke("h1", HIGHLIGHTER_TOOL, 0.0),
+            stroke("h2", HIGHLIGHTER_TOOL, 15.0),
+            stroke("h3", HIGHLIGHTER_TOOL, 200.0),
+            stroke("pen", "pen", 100.0),
+        ];
+
+        let mut shapes = detect_shapes(&strokes);
+        assert!(shapes.iter().all(|s| s.stroke_ids == vec!["pen".to_string()]));
+
+        detect_emphasis(&mut shapes, &strokes);
+        let mut regions: Vec<&DetectedShape> = shapes.iter().filter(|s| s.shape_type == ShapeType::Highlight).collect();
+        regions.sort_by(|a, b| a.bounds.y.total_cmp(&b.bounds.y));
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].stroke_ids, vec!["h1".to_string(), "h2".to_string()]);
+        assert_eq!((regions[0].bounds.y, regions[0].bounds.height), (-10.0, 35.0));
+        assert_eq!(regions[1].stroke_ids, vec!["h3".to_string()]);
+    }
+
+    #[test]
+    fn test_pen_lift_fragments_merge_into_one_shape() {
+        // A rectangle outline, lifted once halfway down the right edge
+        let mut outline: Vec<(f64, f64)> = Vec::new();
+        outline.extend((0..=20).map(|i| (i as f64 * 5.0, 0.0)));
+        outline.extend((1..=12).map(|i| (100.0, i as f64 * 5.0)));
+        outline.extend((1..=20).map(|i| (100.0 - i as f64 * 5.0, 60.0)));
+        outline.extend((1..=12).map(|i| (0.0, 60.0 - i as f64 * 5.0)));
+        let split = 20 + 6;
+
+        let fragment = |id: &str, points: &[(f64, f64)], t0: u64| {
+            let points = points
+                .iter()
+                .enumerate()
+                .map(|(i, &(x, y))| Point { x, y, pressure: None, timestamp: t0 + i as u64 * 10 })
+                .collect();
+            test_stroke(id, points)
+        };
+        let first = fragment("a", &outline[..split], 1000);
+        let last_time = first.points.last().unwrap().timestamp;
+        let second = fragment("b", &outline[split..], last_time + 80);
+
+        let shapes = detect_shapes(&[first.clone(), second.clone()]);
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].shape_type, ShapeType::Rectangle);
+        assert_eq!(shapes[0].stroke_ids, vec!["a".to_string(), "b".to_string()]);
+
+        // Without timing, or after a long pause, the fragments stay apart
+        let untimed = |s: &Stroke| Stroke {
+            points: s.points.iter().map(|p| Point { timestamp: 0, ..p.clone() }).collect(),
+            ..s.clone()
+        };
+        let params = PenLiftParams::default();
+        assert_eq!(merge_pen_lifts(&[untimed(&first), untimed(&second)], &params).len(), 2);
+        let late = fragment("b", &outline[split..], last_time + 2000);
+        assert_eq!(merge_pen_lifts(&[first, late], &params).len(), 2);
+    }
 }
@@ -0,0 +1,315 @@
+//! SVG ink export module
+//!
+//! Writes the raw hand-drawn strokes as smoothed SVG paths so the sketch
+//! itself can be embedded in documents.
+
+use crate::canvas::{calculate_bounding_box, fit_bezier, point_radius, simplify_stroke, CanvasConfig, CubicBezier};
+use crate::color::{self, Color};
+use crate::{Point, Stroke};
+use serde::{Deserialize, Serialize};
+
+/// SVG export options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgOptions {
+    /// Crop the document to the strokes' bounding box
+    pub crop: bool,
+    /// Margin around the strokes when cropping, in canvas pixels
+    pub padding: f64,
+    /// Simplification tolerance for pressure outlines, and curve-fitting
+    /// tolerance for strokes without stored curves
+    pub simplify_epsilon: f64,
+    /// Optional background fill colour
+    pub background: Option<Color>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            crop: true,
+            padding: 20.0,
+            simplify_epsilon: 1.0,
+            background: None,
+        }
+    }
+}
+
+/// Render strokes as an SVG document
+pub fn generate_svg(strokes: &[Stroke], options: &SvgOptions) -> String {
+    let canvas = CanvasConfig::default();
+    let (x, y, width, height) = match calculate_bounding_box(strokes) {
+        Some((min_x, min_y, max_x, max_y)) if options.crop && min_x <= max_x => (
+            min_x - options.padding,
+            min_y - options.padding,
+            max_x - min_x + 2.0 * options.padding,
+            max_y - min_y + 2.0 * options.padding,
+        ),
+        Some((min_x, _, max_x, max_y)) if min_x <= max_x => (
+            0.0,
+            0.0,
+            (canvas.width as f64).max(max_x),
+            (canvas.height as f64).max(max_y),
+        ),
+        _ => (0.0, 0.0, canvas.width as f64, canvas.height as f64),
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"{:.2} {:.2} {:.2} {:.2}\">\n",
+        width.ceil(),
+        height.ceil(),
+        x,
+        y,
+        width,
+        height
+    );
+
+    if let Some(background) = &options.background {
+        let (fill, opacity) = (background.rgb_hex(), background.opacity());
+        svg.push_str(&format!(
+            "  <rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"{}/>\n",
+            x,
+            y,
+            width,
+            height,
+            fill,
+            opacity_attr("fill-opacity", opacity)
+        ));
+    }
+
+    for stroke in strokes {
+        if let Some(element) = stroke_element(stroke, options.simplify_epsilon) {
+            svg.push_str("  ");
+            svg.push_str(&element);
+            svg.push('\n');
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Build the SVG element for one stroke
+fn stroke_element(stroke: &Stroke, epsilon: f64) -> Option<String> {
+    let first = stroke.points.first()?;
+    let ink = color::ink(&stroke.color);
+    let (color, opacity) = (ink.rgb_hex(), ink.opacity());
+    let id = escape_attr(&stroke.id);
+
+    if stroke.points.len() == 1 {
+        return Some(format!(
+            "<circle id=\"{}\" cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"{}/>",
+            id,
+            first.x,
+            first.y,
+            point_radius(stroke.width, first.pressure),
+            color,
+            opacity_attr("fill-opacity", opacity)
+        ));
+    }
+
+    if has_varying_pressure(&stroke.points) {
+        let points = simplify_stroke(&stroke.points, epsilon);
+        Some(format!(
+            "<path id=\"{}\" d=\"{}\" fill=\"{}\"{}/>",
+            id,
+            outline_path(&points, stroke.width),
+            color,
+            opacity_attr("fill-opacity", opacity)
+        ))
+    } else {
+        let fitted;
+        let curves = if stroke.curves.is_empty() {
+            fitted = fit_bezier(&stroke.points, epsilon);
+            &fitted
+        } else {
+            &stroke.curves
+        };
+        let start = curves.first().map_or((first.x, first.y), |c| c.p0);
+        Some(format!(
+            "<path id=\"{}\" d=\"M {:.2} {:.2}{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"{}/>",
+            id,
+            start.0,
+            start.1,
+            bezier_path(curves),
+            color,
+            point_radius(stroke.width, first.pressure) * 2.0,
+            opacity_attr("stroke-opacity", opacity)
+        ))
+    }
+}
+
+/// Whether the stroke's width changes along its length
+fn has_varying_pressure(points: &[Point]) -> bool {
+    let mut pressures = points.iter().filter_map(|p| p.pressure);
+    match pressures.next() {
+        Some(first) => pressures.any(|p| (p - first).abs() > 0.01),
+        None => false,
+    }
+}
+
+/// Closed outline of a variable-width stroke with round caps
+fn outline_path(points: &[Point], width: f64) -> String {
+    let n = points.len();
+    let mut left = Vec::with_capacity(n);
+    let mut right = Vec::with_capacity(n);
+    let mut radii = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let prev = &points[i.saturating_sub(1)];
+        let next = &points[(i + 1).min(n - 1)];
+        let (dx, dy) = (next.x - prev.x, next.y - prev.y);
+        let len = (dx * dx + dy * dy).sqrt().max(f64::EPSILON);
+        let (nx, ny) = (-dy / len, dx / len);
+
+        let r = point_radius(width, points[i].pressure);
+        let p = &points[i];
+        left.push((p.x + nx * r, p.y + ny * r));
+        right.push((p.x - nx * r, p.y - ny * r));
+        radii.push(r);
+    }
+    right.reverse();
+
+    // Left sits on the +90° side of the direction of travel, so the caps
+    // sweep the negative-angle way to bulge out past the ends
+    let (start_r, end_r) = (radii[0], radii[n - 1]);
+    format!(
+        "M {:.2} {:.2}{} A {:.2} {:.2} 0 0 0 {:.2} {:.2}{} A {:.2} {:.2} 0 0 0 {:.2} {:.2} Z",
+        left[0].0,
+        left[0].1,
+        smooth_curve(&left),
+        end_r,
+        end_r,
+        right[0].0,
+        right[0].1,
+        smooth_curve(&right),
+        start_r,
+        start_r,
+        left[0].0,
+        left[0].1
+    )
+}
+
+/// Path commands for a chain of fitted Bézier segments
+fn bezier_path(curves: &[CubicBezier]) -> String {
+    curves
+        .iter()
+        .map(|c| {
+            format!(
+                " C {:.2} {:.2} {:.2} {:.2} {:.2} {:.2}",
+                c.p1.0, c.p1.1, c.p2.0, c.p2.1, c.p3.0, c.p3.1
+            )
+        })
+        .collect()
+}
+
+/// Catmull-Rom spline through the points as cubic Bézier segments
+fn smooth_curve(points: &[(f64, f64)]) -> String {
+    let mut d = String::new();
+    let n = points.len();
+    for i in 0..n.saturating_sub(1) {
+        let p0 = points[i.saturating_sub(1)];
+        let p1 = points[i];
+        let p2 = points[i + 1];
+        let p3 = points[(i + 2).min(n - 1)];
+
+        let c1 = (p1.0 + (p2.0 - p0.0) / 6.0, p1.1 + (p2.1 - p0.1) / 6.0);
+        let c2 = (p2.0 - (p3.0 - p1.0) / 6.0, p2.1 - (p3.1 - p1.1) / 6.0);
+        d.push_str(&format!(
+            " C {:.2} {:.2} {:.2} {:.2} {:.2} {:.2}",
+            c1.0, c1.1, c2.0, c2.1, p2.0, p2.1
+        ));
+    }
+    d
+}
+
+fn opacity_attr(name: &str, opacity: f64) -> String {
+    if opacity < 1.0 {
+        format!(" {}=\"{:.3}\"", name, opacity)
+    } else {
+        String::new()
+    }
+}
+
+fn escape_attr(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+
+    fn stroke(color: &str, points: &[(f64, f64, Option<f64>)]) -> Stroke {
+        let points = points
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y, pressure))| Point { x, y, pressure, timestamp: i as u64 })
+            .collect();
+        Stroke {
+            color: color.to_string(),
+            width: 4.0,
+            ..test_stroke("s1", points)
+        }
+    }
+
+    #[test]
+    fn test_crop_to_bounding_box() {
+        let strokes = vec![stroke("#000000", &[(100.0, 50.0, None), (200.0, 150.0, None)])];
+        let svg = generate_svg(&strokes, &SvgOptions::default());
+
+        assert!(svg.contains("viewBox=\"80.00 30.00 140.00 140.00\""));
+        assert!(svg.contains("stroke=\"#000000\""));
+        assert!(svg.contains(" C "));
+    }
+
+    #[test]
+    fn test_pressure_outline_and_alpha() {
+        let strokes = vec![stroke(
+            "#ff000080",
+            &[(0.0, 0.0, Some(0.2)), (50.0, 10.0, Some(0.6)), (100.0, 0.0, Some(1.0))],
+        )];
+        let svg = generate_svg(&strokes, &SvgOptions { crop: false, ..SvgOptions::default() });
+
+        assert!(svg.contains("viewBox=\"0.00 0.00 1920.00 1080.00\""));
+        assert!(svg.contains("fill=\"#ff0000\" fill-opacity=\"0.502\""));
+        assert!(!svg.contains("stroke-width"));
+    }
+
+    /// Midpoint of a semicircular SVG arc from `from` to `to`
+    fn semicircle_midpoint(from: (f64, f64), to: (f64, f64), sweep: bool) -> (f64, f64) {
+        let centre = ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0);
+        let (rx, ry) = (from.0 - centre.0, from.1 - centre.1);
+        // Sweep 1 turns the positive-angle way (clockwise on screen)
+        let (mx, my) = if sweep { (-ry, rx) } else { (ry, -rx) };
+        (centre.0 + mx, centre.1 + my)
+    }
+
+    #[test]
+    fn test_pressure_caps_bulge_past_the_ends() {
+        let points: Vec<Point> = [(0.0, 0.0, 0.5), (50.0, 0.0, 0.5), (100.0, 0.0, 1.0)]
+            .iter()
+            .map(|&(x, y, p)| Point { x, y, pressure: Some(p), timestamp: 0 })
+            .collect();
+        let d = outline_path(&points, 4.0);
+
+        let numbers = |s: &str| -> Vec<f64> { s.split_whitespace().filter_map(|t| t.parse().ok()).collect() };
+        let segments: Vec<&str> = d.split(" A ").collect();
+        assert_eq!(segments.len(), 3);
+        let start = numbers(segments[0]);
+        let end_arc = numbers(segments[1]);
+        let start_arc = numbers(segments[2]);
+
+        // End cap: from the last left point to the first right point
+        let from = (start[start.len() - 2], start[start.len() - 1]);
+        let mid = semicircle_midpoint(from, (end_arc[5], end_arc[6]), end_arc[4] == 1.0);
+        assert!(mid.0 > 100.0, "end cap midpoint {:?} is not beyond the end", mid);
+
+        // Start cap: from the last right point back to the first left point
+        let from = (end_arc[end_arc.len() - 2], end_arc[end_arc.len() - 1]);
+        let mid = semicircle_midpoint(from, (start_arc[5], start_arc[6]), start_arc[4] == 1.0);
+        assert!(mid.0 < 0.0, "start cap midpoint {:?} is not before the start", mid);
+    }
+}
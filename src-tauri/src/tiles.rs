@@ -0,0 +1,290 @@
+//! Tile rendering module
+//!
+//! Renders the board as fixed-size PNG tiles on a world-space grid, one grid
+//! per zoom level (`2^zoom` pixels per canvas unit). Only strokes near a
+//! tile are drawn, found through a spatial index, and rendered tiles are
+//! cached until the board's content version changes.
+
+use crate::canvas::{self, RasterTransform};
+use crate::color::Color;
+use crate::selection::SpatialIndex;
+use crate::Stroke;
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tile edge length in pixels
+pub const TILE_SIZE: u32 = 256;
+
+/// Supported zoom levels
+pub const MIN_ZOOM: i32 = -8;
+pub const MAX_ZOOM: i32 = 6;
+
+/// Most tiles a single `render_tiles` request may cover
+const MAX_TILES_PER_REQUEST: usize = 256;
+
+/// Most tiles kept in the cache
+const MAX_CACHED_TILES: usize = 1024;
+
+/// Largest image `render_viewport` will produce, per side
+const MAX_EXPORT_SIZE: f64 = 16384.0;
+
+/// A world-space rectangle
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A rendered tile for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedTile {
+    pub zoom: i32,
+    pub x: i64,
+    pub y: i64,
+    /// World-space position and edge length of the tile
+    pub world_x: f64,
+    pub world_y: f64,
+    pub world_size: f64,
+    /// Base64-encoded PNG
+    pub png: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TileKey {
+    zoom: i32,
+    x: i64,
+    y: i64,
+    background: Color,
+}
+
+/// Visible strokes, their index and rendered tiles for one content version
+#[derive(Default)]
+pub struct TileCache {
+    version: Option<u64>,
+    /// The visible strokes as of `version`
+    strokes: Vec<Stroke>,
+    index: Option<SpatialIndex>,
+    /// Widest stroke, used to pad tile queries so edge-straddling ink is drawn
+    max_width: f64,
+    tiles: HashMap<TileKey, (u64, Vec<u8>)>,
+    clock: u64,
+}
+
+fn zoom_scale(zoom: i32) -> f64 {
+    2f64.powi(zoom)
+}
+
+/// World-space edge length of a tile at `zoom`
+pub fn tile_world_size(zoom: i32) -> f64 {
+    TILE_SIZE as f64 / zoom_scale(zoom)
+}
+
+/// Tiles covering a viewport at `zoom`, or an error when there are more than `max_tiles`
+pub fn tiles_for_viewport(viewport: &Viewport, zoom: i32, max_tiles: usize) -> Result<Vec<(i64, i64)>, String> {
+    if !(MIN_ZOOM..=MAX_ZOOM).contains(&zoom) {
+        return Err(format!("Zoom level {} outside {}..={}", zoom, MIN_ZOOM, MAX_ZOOM));
+    }
+    let finite = [viewport.x, viewport.y, viewport.width, viewport.height].iter().all(|v| v.is_finite());
+    if !(finite && viewport.width > 0.0 && viewport.height > 0.0) {
+        return Err("Invalid viewport".to_string());
+    }
+
+    let size = tile_world_size(zoom);
+    let x0 = (viewport.x / size).floor() as i64;
+    let y0 = (viewport.y / size).floor() as i64;
+    let x1 = ((viewport.x + viewport.width) / size).ceil() as i64;
+    let y1 = ((viewport.y + viewport.height) / size).ceil() as i64;
+
+    let count = (x1 - x0).max(0).saturating_mul((y1 - y0).max(0)) as usize;
+    if count > max_tiles {
+        return Err(format!("Viewport needs {} tiles at zoom {}; zoom out", count, zoom));
+    }
+
+    Ok((y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).collect())
+}
+
+impl TileCache {
+    /// Bring the cache up to `version` of the board.
+    ///
+    /// `load` is only called, and the rendered tiles only dropped, when the
+    /// version changed; it returns the visible strokes in drawing order.
+    pub fn sync(&mut self, version: u64, load: impl FnOnce() -> Result<Vec<Stroke>, String>) -> Result<(), String> {
+        if self.version == Some(version) && self.index.is_some() {
+            return Ok(());
+        }
+        let strokes = load()?;
+        self.version = Some(version);
+        self.index = Some(SpatialIndex::build(&strokes));
+        self.max_width = strokes.iter().map(|s| s.width).fold(0.0, f64::max);
+        self.strokes = strokes;
+        self.tiles.clear();
+        Ok(())
+    }
+
+    /// PNG bytes of one tile, rendered or from the cache
+    pub fn tile_png(&mut self, zoom: i32, x: i64, y: i64, background: Color) -> Result<Vec<u8>, String> {
+        self.clock += 1;
+
+        let key = TileKey { zoom, x, y, background };
+        if let Some((used, png)) = self.tiles.get_mut(&key) {
+            *used = self.clock;
+            return Ok(png.clone());
+        }
+
+        let png = encode_png(&self.render_tile(zoom, x, y, background))?;
+        if self.tiles.len() >= MAX_CACHED_TILES {
+            self.evict_oldest();
+        }
+        self.tiles.insert(key, (self.clock, png.clone()));
+        Ok(png)
+    }
+
+    fn render_tile(&self, zoom: i32, x: i64, y: i64, background: Color) -> RgbaImage {
+        let size = tile_world_size(zoom);
+        let (left, top) = (x as f64 * size, y as f64 * size);
+        // Pressure can double a stroke's radius (see `canvas::point_radius`)
+        let margin = self.max_width;
+
+        let nearby: Vec<Stroke> = self
+            .index
+            .as_ref()
+            .map(|index| index.candidates(left - margin, top - margin, left + size + margin, top + size + margin))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| self.strokes[i].clone())
+            .collect();
+
+        let transform = RasterTransform { origin_x: left, origin_y: top, scale: zoom_scale(zoom) };
+        canvas::render_region(&nearby, &transform, TILE_SIZE, TILE_SIZE, background)
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(key) = self.tiles.iter().min_by_key(|(_, (used, _))| *used).map(|(k, _)| k.clone()) {
+            self.tiles.remove(&key);
+        }
+    }
+
+    /// Render the tiles covering a viewport
+    pub fn render_tiles(
+        &mut self,
+        viewport: &Viewport,
+        zoom: i32,
+        background: Color,
+    ) -> Result<Vec<RenderedTile>, String> {
+        let size = tile_world_size(zoom);
+        tiles_for_viewport(viewport, zoom, MAX_TILES_PER_REQUEST)?
+            .into_iter()
+            .map(|(x, y)| {
+                let png = self.tile_png(zoom, x, y, background)?;
+                Ok(RenderedTile {
+                    zoom,
+                    x,
+                    y,
+                    world_x: x as f64 * size,
+                    world_y: y as f64 * size,
+                    world_size: size,
+                    png: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, png),
+                })
+            })
+            .collect()
+    }
+
+    /// Stitch the tiles covering a viewport into one image cropped to it, for export
+    pub fn render_viewport(
+        &mut self,
+        viewport: &Viewport,
+        zoom: i32,
+        background: Color,
+    ) -> Result<DynamicImage, String> {
+        let scale = zoom_scale(zoom);
+        let (width, height) = (viewport.width * scale, viewport.height * scale);
+        if width > MAX_EXPORT_SIZE || height > MAX_EXPORT_SIZE {
+            return Err(format!("Export of {:.0}x{:.0} pixels is too large; zoom out", width, height));
+        }
+
+        let size = tile_world_size(zoom);
+        let mut img = RgbaImage::new(width.ceil().max(1.0) as u32, height.ceil().max(1.0) as u32);
+        // The size check above already bounds the tile count
+        for (x, y) in tiles_for_viewport(viewport, zoom, usize::MAX)? {
+            let png = self.tile_png(zoom, x, y, background)?;
+            let tile = image::load_from_memory(&png)
+                .map_err(|e| format!("Failed to decode tile: {}", e))?
+                .to_rgba8();
+            let offset_x = ((x as f64 * size - viewport.x) * scale).round() as i64;
+            let offset_y = ((y as f64 * size - viewport.y) * scale).round() as i64;
+            image::imageops::replace(&mut img, &tile, offset_x, offset_y);
+        }
+        Ok(DynamicImage::ImageRgba8(img))
+    }
+}
+
+pub fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png_bytes = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut png_bytes);
+    image::ImageEncoder::write_image(
+        encoder,
+        img.as_raw(),
+        img.width(),
+        img.height(),
+        image::ExtendedColorType::Rgba8,
+    )
+    .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(png_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::test_stroke;
+    use crate::Point;
+
+    fn line(id: &str, from: (f64, f64), to: (f64, f64)) -> Stroke {
+        Stroke {
+            width: 4.0,
+            ..test_stroke(id, vec![
+                Point { x: from.0, y: from.1, pressure: None, timestamp: 0 },
+                Point { x: to.0, y: to.1, pressure: None, timestamp: 0 },
+            ])
+        }
+    }
+
+    #[test]
+    fn test_tile_grid_covers_viewport() {
+        let viewport = Viewport { x: -10.0, y: 0.0, width: 300.0, height: 100.0 };
+        let tiles = tiles_for_viewport(&viewport, 0, 16).unwrap();
+        assert_eq!(tiles, vec![(-1, 0), (0, 0), (1, 0)]);
+        assert!(tiles_for_viewport(&viewport, 20, 16).is_err());
+        assert!(tiles_for_viewport(&viewport, 0, 2).is_err());
+
+        let huge = Viewport { x: 0.0, y: 0.0, width: 1e7, height: 1e7 };
+        assert!(TileCache::default().render_tiles(&huge, 0, Color::WHITE).is_err());
+        let endless = Viewport { x: 0.0, y: 0.0, width: f64::INFINITY, height: 10.0 };
+        assert!(tiles_for_viewport(&endless, 0, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_tiles_draw_far_strokes_and_cache_by_version() {
+        // A stroke far from the origin, crossing the boundary between tiles 390 and 391
+        let tile_x = 390.0 * TILE_SIZE as f64;
+        let strokes = vec![line("a", (tile_x + 200.0, 50.0), (tile_x + 300.0, 50.0))];
+        let mut cache = TileCache::default();
+
+        let tile = |cache: &mut TileCache, x: i64, version: u64| {
+            cache.sync(version, || Ok(strokes.clone())).unwrap();
+            let png = cache.tile_png(0, x, 0, Color::TRANSPARENT).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgba8()
+        };
+        assert!(tile(&mut cache, 390, 1).get_pixel(250, 50)[3] > 200);
+        assert!(tile(&mut cache, 391, 1).get_pixel(24, 50)[3] > 200);
+        assert_eq!(tile(&mut cache, 392, 1).get_pixel(24, 50)[3], 0);
+        assert_eq!(cache.tiles.len(), 3);
+
+        // The strokes are only read again, and the tiles dropped, for a new content version
+        cache.sync(1, || Err("not reloaded".to_string())).unwrap();
+        tile(&mut cache, 390, 2);
+        assert_eq!(cache.tiles.len(), 1);
+    }
+}
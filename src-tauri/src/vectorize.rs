@@ -0,0 +1,604 @@
+//! Photo vectorization module
+//!
+//! Turns a photo of a whiteboard into vector strokes: the background is
+//! flattened, ink is found with adaptive binarization, thinned to a
+//! one-pixel skeleton and the skeleton is traced into polylines.
+
+use crate::{Point, Stroke};
+use image::{DynamicImage, GenericImageView, GrayImage, RgbImage};
+
+/// Photo vectorization parameters
+#[derive(Debug, Clone)]
+pub struct VectorizeParams {
+    /// Photos larger than this (longest side, pixels) are downscaled first
+    pub max_dimension: u32,
+    /// Background estimation window relative to the longest image side
+    pub background_window: f64,
+    /// Binarization window relative to the longest image side
+    pub threshold_window: f64,
+    /// A pixel is ink when it is this much darker than its neighbourhood (0-1)
+    pub threshold_ratio: f64,
+    /// Ink blobs smaller than this many pixels are discarded as noise
+    pub min_component_area: usize,
+    /// Traced paths shorter than this (pixels) are dropped
+    pub min_stroke_length: f64,
+    /// Path ends closer than this (pixels) are joined into one stroke
+    pub join_distance: f64,
+    /// Keep every n-th skeleton pixel as a stroke point
+    pub sample_spacing: usize,
+}
+
+impl Default for VectorizeParams {
+    fn default() -> Self {
+        Self {
+            max_dimension: 2048,
+            background_window: 1.0 / 12.0,
+            threshold_window: 1.0 / 40.0,
+            threshold_ratio: 0.15,
+            min_component_area: 16,
+            min_stroke_length: 6.0,
+            join_distance: 6.0,
+            sample_spacing: 3,
+        }
+    }
+}
+
+/// Binary image, `true` for ink
+#[derive(Debug, Clone)]
+struct Bitmap {
+    width: usize,
+    height: usize,
+    data: Vec<bool>,
+}
+
+impl Bitmap {
+    fn get(&self, x: i64, y: i64) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.data[y as usize * self.width + x as usize]
+    }
+
+    fn count(&self) -> usize {
+        self.data.iter().filter(|&&v| v).count()
+    }
+}
+
+/// 8-neighbourhood offsets, clockwise from north (P2..P9 in Zhang-Suen terms)
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// Vectorize a photo into strokes in image pixel coordinates
+pub fn vectorize_image(image: &DynamicImage, params: &VectorizeParams) -> Vec<Stroke> {
+    let image = downscale(image, params.max_dimension);
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let longest = width.max(height) as f64;
+
+    let gray = image.to_luma8();
+    let flat = flatten_background(&gray, window_radius(longest, params.background_window));
+    let mut ink = binarize(&flat, window_radius(longest, params.threshold_window), params.threshold_ratio);
+    remove_small_components(&mut ink, params.min_component_area);
+
+    let ink_pixels = ink.count();
+    let mut skeleton = ink.clone();
+    thin(&mut skeleton);
+
+    // Average stroke width ≈ ink area / centre-line length
+    let skeleton_pixels = skeleton.count().max(1);
+    let stroke_width = (ink_pixels as f64 / skeleton_pixels as f64).clamp(1.0, 12.0);
+
+    // Short spurs are thinning artefacts; drop them before joining so they
+    // don't hijack the continuation at corners
+    let paths: Vec<Vec<(i64, i64)>> = trace_skeleton(&skeleton)
+        .into_iter()
+        .filter(|path| path_length(path) >= params.min_stroke_length)
+        .collect();
+    let join_distance = params.join_distance.max(stroke_width * 1.5);
+
+    let rgb = image.to_rgb8();
+    join_paths(paths, join_distance)
+        .into_iter()
+        .map(|path| Stroke {
+            id: uuid::Uuid::new_v4().to_string(),
+            color: ink_color(&rgb, &ink, &path),
+            points: sample_points(&path, params.sample_spacing),
+            width: stroke_width,
+            tool: "pen".to_string(),
+            layer_id: crate::layers::DEFAULT_LAYER_ID.to_string(),
+            curves: Vec::new(),
+        })
+        .collect()
+}
+
+fn downscale(image: &DynamicImage, max_dimension: u32) -> DynamicImage {
+    if image.width().max(image.height()) > max_dimension {
+        image.resize(max_dimension, max_dimension, image::imageops::FilterType::Triangle)
+    } else {
+        image.clone()
+    }
+}
+
+fn window_radius(longest: f64, fraction: f64) -> usize {
+    ((longest * fraction) / 2.0).round().max(1.0) as usize
+}
+
+/// Summed-area table for constant-time box means
+struct Integral {
+    width: usize,
+    height: usize,
+    sums: Vec<f64>,
+}
+
+impl Integral {
+    fn new(width: usize, height: usize, value: impl Fn(usize, usize) -> f64) -> Self {
+        let mut sums = vec![0.0; (width + 1) * (height + 1)];
+        for y in 0..height {
+            let mut row = 0.0;
+            for x in 0..width {
+                row += value(x, y);
+                sums[(y + 1) * (width + 1) + x + 1] = sums[y * (width + 1) + x + 1] + row;
+            }
+        }
+        Self { width, height, sums }
+    }
+
+    /// Mean over the box of the given radius around (x, y), clipped to the image
+    fn mean(&self, x: usize, y: usize, radius: usize) -> f64 {
+        let x0 = x.saturating_sub(radius);
+        let y0 = y.saturating_sub(radius);
+        let x1 = (x + radius + 1).min(self.width);
+        let y1 = (y + radius + 1).min(self.height);
+        let w = self.width + 1;
+        let sum = self.sums[y1 * w + x1] - self.sums[y0 * w + x1] - self.sums[y1 * w + x0]
+            + self.sums[y0 * w + x0];
+        sum / ((x1 - x0) * (y1 - y0)) as f64
+    }
+}
+
+/// Divide out uneven lighting so the board becomes uniformly white
+fn flatten_background(gray: &GrayImage, radius: usize) -> GrayImage {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let integral = Integral::new(width, height, |x, y| gray.get_pixel(x as u32, y as u32)[0] as f64);
+
+    GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let background = integral.mean(x as usize, y as usize, radius).max(1.0);
+        let value = gray.get_pixel(x, y)[0] as f64;
+        image::Luma([(value / background * 255.0).min(255.0) as u8])
+    })
+}
+
+/// Bradley-Roth adaptive threshold: ink is noticeably darker than its surroundings
+fn binarize(flat: &GrayImage, radius: usize, ratio: f64) -> Bitmap {
+    let (width, height) = (flat.width() as usize, flat.height() as usize);
+    let integral = Integral::new(width, height, |x, y| flat.get_pixel(x as u32, y as u32)[0] as f64);
+
+    let mut data = vec![false; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let value = flat.get_pixel(x as u32, y as u32)[0] as f64;
+            // The absolute bound keeps flat, evenly lit regions from turning into noise
+            data[y * width + x] =
+                value < integral.mean(x, y, radius) * (1.0 - ratio) && value < 255.0 * (1.0 - ratio);
+        }
+    }
+    Bitmap { width, height, data }
+}
+
+/// Drop 8-connected ink blobs below the minimum area
+fn remove_small_components(bitmap: &mut Bitmap, min_area: usize) {
+    let mut visited = vec![false; bitmap.data.len()];
+    let mut stack = Vec::new();
+    let mut component = Vec::new();
+
+    for start in 0..bitmap.data.len() {
+        if !bitmap.data[start] || visited[start] {
+            continue;
+        }
+
+        component.clear();
+        visited[start] = true;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            component.push(index);
+            let (x, y) = ((index % bitmap.width) as i64, (index / bitmap.width) as i64);
+            for (dx, dy) in NEIGHBOURS {
+                let (nx, ny) = (x + dx, y + dy);
+                if bitmap.get(nx, ny) {
+                    let next = ny as usize * bitmap.width + nx as usize;
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+
+        if component.len() < min_area {
+            for &index in &component {
+                bitmap.data[index] = false;
+            }
+        }
+    }
+}
+
+/// Zhang-Suen thinning down to a one-pixel-wide skeleton
+fn thin(bitmap: &mut Bitmap) {
+    let mut to_clear = Vec::new();
+    loop {
+        let mut changed = false;
+        for step in 0..2 {
+            to_clear.clear();
+            for y in 0..bitmap.height as i64 {
+                for x in 0..bitmap.width as i64 {
+                    if !bitmap.get(x, y) {
+                        continue;
+                    }
+                    let n: [bool; 8] = NEIGHBOURS.map(|(dx, dy)| bitmap.get(x + dx, y + dy));
+                    let neighbours = n.iter().filter(|&&v| v).count();
+                    let transitions = (0..8).filter(|&i| !n[i] && n[(i + 1) % 8]).count();
+
+                    let (p2, p4, p6, p8) = (n[0], n[2], n[4], n[6]);
+                    let removable = if step == 0 {
+                        !(p4 && p6 && (p2 || p8))
+                    } else {
+                        !(p2 && p8 && (p4 || p6))
+                    };
+
+                    if (2..=6).contains(&neighbours) && transitions == 1 && removable {
+                        to_clear.push(y as usize * bitmap.width + x as usize);
+                    }
+                }
+            }
+            for &index in &to_clear {
+                bitmap.data[index] = false;
+            }
+            changed |= !to_clear.is_empty();
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Trace skeleton pixels into polylines.
+///
+/// Paths run between end points and junctions; closed loops without either
+/// are traced from an arbitrary pixel. Every skeleton pixel is used once,
+/// except junctions which may end several paths.
+fn trace_skeleton(skeleton: &Bitmap) -> Vec<Vec<(i64, i64)>> {
+    let mut tracer = SkeletonTracer {
+        skeleton,
+        visited: vec![false; skeleton.data.len()],
+    };
+    let mut paths = Vec::new();
+
+    // End points first, then junction branches, then whatever is left (loops)
+    for pass in 0..3 {
+        for y in 0..skeleton.height as i64 {
+            for x in 0..skeleton.width as i64 {
+                if !skeleton.get(x, y) {
+                    continue;
+                }
+                let degree = tracer.degree(x, y);
+                match pass {
+                    0 if degree == 1 && !tracer.is_visited(x, y) => {
+                        tracer.mark(x, y);
+                        if let Some(first) = tracer.next_unvisited(x, y) {
+                            paths.push(tracer.walk((x, y), first));
+                        }
+                    }
+                    1 if degree >= 3 => {
+                        tracer.mark(x, y);
+                        while let Some(first) = tracer.next_unvisited(x, y) {
+                            paths.push(tracer.walk((x, y), first));
+                        }
+                    }
+                    2 if !tracer.is_visited(x, y) => {
+                        tracer.mark(x, y);
+                        if let Some(first) = tracer.next_unvisited(x, y) {
+                            let mut path = tracer.walk((x, y), first);
+                            let (lx, ly) = path[path.len() - 1];
+                            if path.len() > 2 && (lx - x).abs() <= 1 && (ly - y).abs() <= 1 {
+                                path.push((x, y));
+                            }
+                            paths.push(path);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    paths
+}
+
+/// Walks skeleton pixels, remembering which have been consumed
+struct SkeletonTracer<'a> {
+    skeleton: &'a Bitmap,
+    visited: Vec<bool>,
+}
+
+impl SkeletonTracer<'_> {
+    fn index(&self, x: i64, y: i64) -> usize {
+        y as usize * self.skeleton.width + x as usize
+    }
+
+    fn is_visited(&self, x: i64, y: i64) -> bool {
+        self.visited[self.index(x, y)]
+    }
+
+    fn mark(&mut self, x: i64, y: i64) {
+        let index = self.index(x, y);
+        self.visited[index] = true;
+    }
+
+    fn degree(&self, x: i64, y: i64) -> usize {
+        NEIGHBOURS
+            .iter()
+            .filter(|(dx, dy)| self.skeleton.get(x + dx, y + dy))
+            .count()
+    }
+
+    /// Unvisited neighbour, preferring 4-connected steps so corners aren't cut
+    fn next_unvisited(&self, x: i64, y: i64) -> Option<(i64, i64)> {
+        NEIGHBOURS
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&(nx, ny)| self.skeleton.get(nx, ny) && !self.is_visited(nx, ny))
+            .min_by_key(|&(nx, ny)| (nx - x).abs() + (ny - y).abs())
+    }
+
+    /// Follow the skeleton from `start` through `first` until an end or a junction
+    fn walk(&mut self, start: (i64, i64), first: (i64, i64)) -> Vec<(i64, i64)> {
+        let mut path = vec![start, first];
+        self.mark(first.0, first.1);
+        let mut current = first;
+
+        while self.degree(current.0, current.1) < 3 {
+            if let Some(next) = self.next_unvisited(current.0, current.1) {
+                self.mark(next.0, next.1);
+                path.push(next);
+                current = next;
+                continue;
+            }
+
+            // Finish on an adjacent junction another path already reached
+            let previous = path[path.len() - 2];
+            let junction = NEIGHBOURS
+                .iter()
+                .map(|(dx, dy)| (current.0 + dx, current.1 + dy))
+                .find(|&(nx, ny)| {
+                    (nx, ny) != previous
+                        && (nx, ny) != start
+                        && self.skeleton.get(nx, ny)
+                        && self.degree(nx, ny) >= 3
+                });
+            if let Some(junction) = junction {
+                path.push(junction);
+            }
+            break;
+        }
+
+        path
+    }
+}
+
+/// Join paths whose ends meet into continuous strokes.
+///
+/// At a junction the continuation with the smallest change of direction wins;
+/// a stroke whose ends meet is closed.
+fn join_paths(mut paths: Vec<Vec<(i64, i64)>>, max_gap: f64) -> Vec<Vec<(i64, i64)>> {
+    let mut joined = Vec::new();
+
+    while let Some(mut path) = paths.pop() {
+        loop {
+            let mut extended = false;
+            // Try the end first, then (reversed) the start
+            for _ in 0..2 {
+                if let Some((index, reversed)) = best_continuation(&path, &paths, max_gap) {
+                    let mut next = paths.swap_remove(index);
+                    if reversed {
+                        next.reverse();
+                    }
+                    let skip = usize::from(next.first() == path.last());
+                    path.extend(next.into_iter().skip(skip));
+                    extended = true;
+                    break;
+                }
+                path.reverse();
+            }
+            if !extended {
+                break;
+            }
+        }
+
+        let (first, last) = (path[0], path[path.len() - 1]);
+        if path.len() > 3 && first != last && pixel_distance(first, last) <= max_gap {
+            path.push(first);
+        }
+        joined.push(path);
+    }
+
+    joined
+}
+
+/// Candidate path (and whether to reverse it) that best continues `path`'s end
+fn best_continuation(
+    path: &[(i64, i64)],
+    candidates: &[Vec<(i64, i64)>],
+    max_gap: f64,
+) -> Option<(usize, bool)> {
+    let end = path[path.len() - 1];
+    let direction = direction_at(path.iter().rev());
+
+    let mut best: Option<(usize, bool, f64)> = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        for reversed in [false, true] {
+            let start = if reversed { candidate[candidate.len() - 1] } else { candidate[0] };
+            let gap = pixel_distance(end, start);
+            if gap > max_gap {
+                continue;
+            }
+
+            let onward = if reversed {
+                direction_at(candidate.iter().rev())
+            } else {
+                direction_at(candidate.iter())
+            };
+            // Walking away from the end should keep heading the same way
+            let turn = 1.0 - (-(direction.0 * onward.0 + direction.1 * onward.1));
+            let score = gap + turn * max_gap;
+            if best.is_none_or(|(_, _, s)| score < s) {
+                best = Some((index, reversed, score));
+            }
+        }
+    }
+
+    best.map(|(index, reversed, _)| (index, reversed))
+}
+
+/// Unit direction from the first point to a point a few pixels further along
+fn direction_at<'a>(mut points: impl Iterator<Item = &'a (i64, i64)>) -> (f64, f64) {
+    let Some(&start) = points.next() else { return (0.0, 0.0) };
+    let far = points.take(6).last().copied().unwrap_or(start);
+    let (dx, dy) = ((far.0 - start.0) as f64, (far.1 - start.1) as f64);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length > 0.0 {
+        (dx / length, dy / length)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+fn pixel_distance(a: (i64, i64), b: (i64, i64)) -> f64 {
+    (((a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)) as f64).sqrt()
+}
+
+/// Keep every n-th pixel of a path (and always its last one) as stroke points
+fn sample_points(path: &[(i64, i64)], spacing: usize) -> Vec<Point> {
+    let spacing = spacing.max(1);
+    let mut points: Vec<Point> = path
+        .iter()
+        .step_by(spacing)
+        .map(|&(x, y)| Point { x: x as f64, y: y as f64, pressure: None, timestamp: 0 })
+        .collect();
+    let (x, y) = path[path.len() - 1];
+    if points.last().is_some_and(|p| (p.x, p.y) != (x as f64, y as f64)) {
+        points.push(Point { x: x as f64, y: y as f64, pressure: None, timestamp: 0 });
+    }
+    points
+}
+
+fn path_length(path: &[(i64, i64)]) -> f64 {
+    path.windows(2)
+        .map(|w| (((w[1].0 - w[0].0).pow(2) + (w[1].1 - w[0].1).pow(2)) as f64).sqrt())
+        .sum()
+}
+
+/// Average colour of the ink around a traced path, as a hex string
+fn ink_color(rgb: &RgbImage, ink: &Bitmap, path: &[(i64, i64)]) -> String {
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+    for &(x, y) in path {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (px, py) = (x + dx, y + dy);
+                if ink.get(px, py) {
+                    let pixel = rgb.get_pixel(px as u32, py as u32);
+                    for c in 0..3 {
+                        sum[c] += pixel[c] as u64;
+                    }
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    if count == 0 {
+        return "#000000".to_string();
+    }
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        sum[0] / count,
+        sum[1] / count,
+        sum[2] / count
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// Off-white photo with uneven lighting and a dark blue rectangle outline
+    fn board_photo() -> DynamicImage {
+        let mut img = RgbImage::from_fn(400, 300, |x, _| {
+            let shade = 230 - (x / 20) as u8;
+            Rgb([shade, shade, shade - 5])
+        });
+        for x in 100..=300 {
+            for t in 0..4 {
+                img.put_pixel(x, 80 + t, Rgb([20, 30, 120]));
+                img.put_pixel(x, 220 + t, Rgb([20, 30, 120]));
+            }
+        }
+        for y in 80..=223 {
+            for t in 0..4 {
+                img.put_pixel(100 + t, y, Rgb([20, 30, 120]));
+                img.put_pixel(297 + t, y, Rgb([20, 30, 120]));
+            }
+        }
+        // Speckle that should be filtered out
+        img.put_pixel(50, 50, Rgb([0, 0, 0]));
+        DynamicImage::ImageRgb8(img)
+    }
+
+    #[test]
+    fn test_vectorize_rectangle_outline() {
+        let strokes = vectorize_image(&board_photo(), &VectorizeParams::default());
+        assert!(!strokes.is_empty());
+
+        let points: Vec<&Point> = strokes.iter().flat_map(|s| &s.points).collect();
+        assert!(points.iter().all(|p| p.x >= 95.0 && p.x <= 306.0 && p.y >= 75.0 && p.y <= 228.0));
+
+        // The four sides are joined at the corners into one closed stroke
+        assert_eq!(strokes.len(), 1);
+        let (first, last) = (&strokes[0].points[0], strokes[0].points.last().unwrap());
+        assert_eq!((first.x, first.y), (last.x, last.y));
+
+        let shapes = crate::shapes::detect_shapes(&strokes);
+        assert!(matches!(shapes[0].shape_type, crate::shapes::ShapeType::Rectangle));
+
+        assert!(strokes.iter().all(|s| s.width >= 2.0 && s.width <= 6.0));
+        assert!(strokes[0].color.starts_with("#1") || strokes[0].color.starts_with("#2"));
+    }
+
+    #[test]
+    fn test_thinning_yields_single_pixel_line() {
+        let mut bitmap = Bitmap { width: 20, height: 7, data: vec![false; 140] };
+        for y in 2..5 {
+            for x in 2..18 {
+                bitmap.data[y * 20 + x] = true;
+            }
+        }
+        thin(&mut bitmap);
+
+        for x in 4..16 {
+            let column = (0..7).filter(|&y| bitmap.get(x, y)).count();
+            assert_eq!(column, 1, "column {} is not one pixel wide", x);
+        }
+    }
+}
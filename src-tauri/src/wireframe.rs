@@ -0,0 +1,407 @@
+//! UI wireframe recognition module
+//!
+//! Recognizes hand-drawn UI mockup conventions (image placeholders, buttons,
+//! text inputs, checkboxes) among detected shapes and renders them as a
+//! skeleton HTML page.
+
+use crate::ocr::TextRegion;
+use crate::shapes::{
+    bounds_center, bounds_contain, has_rounded_corners, DetectedShape, ShapeBounds, ShapeType,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Wireframe role assigned to a shape by [`annotate_wireframe`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WireframeElement {
+    ImagePlaceholder,
+    Button,
+    Input,
+    Checkbox,
+}
+
+/// Wireframe recognition parameters
+#[derive(Debug, Clone)]
+pub struct WireframeParams {
+    /// Maximum button width in canvas pixels
+    pub button_max_width: f64,
+    /// Maximum button and input height in canvas pixels
+    pub control_max_height: f64,
+    /// Minimum width/height ratio of a text input
+    pub input_min_aspect: f64,
+    /// Maximum checkbox side length in canvas pixels
+    pub checkbox_max_size: f64,
+    /// Minimum diagonal length of a placeholder cross relative to the box diagonal
+    pub cross_span_ratio: f64,
+}
+
+impl Default for WireframeParams {
+    fn default() -> Self {
+        Self {
+            button_max_width: 240.0,
+            control_max_height: 70.0,
+            input_min_aspect: 3.0,
+            checkbox_max_size: 36.0,
+            cross_span_ratio: 0.6,
+        }
+    }
+}
+
+/// Assign wireframe roles to detected shapes.
+///
+/// Rectangles crossed by an X become image placeholders (the cross strokes
+/// are folded in), small rounded rectangles with text become buttons, wide
+/// short rectangles with an underline become text inputs (absorbing the
+/// underline) and small squares become checkboxes labelled by the text to
+/// their right.
+///
+/// The roles are only kept when the result looks like a UI mockup (see
+/// [`has_wireframe_evidence`]) or `force` is set because the user asked for
+/// a wireframe.
+pub fn annotate_wireframe(shapes: &mut Vec<DetectedShape>, text_regions: &[TextRegion], force: bool) {
+    let mut annotated = shapes.clone();
+    assign_wireframe_roles(&mut annotated, text_regions);
+    if force || has_wireframe_evidence(&annotated) {
+        *shapes = annotated;
+    }
+}
+
+/// An image placeholder, or a form field among at least two controls on a
+/// board without arrows
+pub fn has_wireframe_evidence(shapes: &[DetectedShape]) -> bool {
+    let count = |matches: fn(&WireframeElement) -> bool| {
+        shapes
+            .iter()
+            .filter(|s| s.properties.wireframe.as_ref().is_some_and(matches))
+            .count()
+    };
+    let has_arrows = shapes
+        .iter()
+        .any(|s| matches!(s.shape_type, ShapeType::Arrow | ShapeType::Line));
+
+    count(|e| *e == WireframeElement::ImagePlaceholder) > 0
+        || (count(|e| matches!(e, WireframeElement::Input | WireframeElement::Checkbox)) > 0
+            && count(|e| *e != WireframeElement::ImagePlaceholder) >= 2
+            && !has_arrows)
+}
+
+fn assign_wireframe_roles(shapes: &mut Vec<DetectedShape>, text_regions: &[TextRegion]) {
+    let params = WireframeParams::default();
+    let mut absorbed: HashSet<String> = HashSet::new();
+
+    for i in 0..shapes.len() {
+        if absorbed.contains(&shapes[i].id) || shapes[i].shape_type != ShapeType::Rectangle {
+            continue;
+        }
+
+        let bounds = shapes[i].bounds.clone();
+        let aspect = if bounds.height > 0.0 { bounds.width / bounds.height } else { 0.0 };
+        let inner_text = text_inside(&bounds, text_regions);
+
+        let cross = find_cross(i, shapes, &absorbed, &params);
+        let (role, parts, label) = if cross.len() >= 2 {
+            (WireframeElement::ImagePlaceholder, cross, None)
+        } else if bounds.width <= params.checkbox_max_size
+            && bounds.height <= params.checkbox_max_size
+            && (0.75..=1.33).contains(&aspect)
+        {
+            let label = text_right_of(&bounds, text_regions);
+            (WireframeElement::Checkbox, Vec::new(), label)
+        } else if bounds.height > params.control_max_height {
+            continue;
+        } else if aspect >= params.input_min_aspect {
+            let Some(underline) = find_underline(i, shapes, &absorbed) else {
+                continue;
+            };
+            (WireframeElement::Input, vec![underline], inner_text)
+        } else if bounds.width <= params.button_max_width
+            && inner_text.is_some()
+            && has_rounded_corners(&shapes[i])
+        {
+            (WireframeElement::Button, Vec::new(), inner_text)
+        } else {
+            continue;
+        };
+
+        let part_ids: Vec<String> = parts
+            .iter()
+            .flat_map(|&p| shapes[p].stroke_ids.clone())
+            .collect();
+        for &p in &parts {
+            absorbed.insert(shapes[p].id.clone());
+        }
+
+        let shape = &mut shapes[i];
+        shape.stroke_ids.extend(part_ids);
+        shape.properties.wireframe = Some(role);
+        if label.is_some() {
+            shape.label = label;
+        }
+    }
+
+    shapes.retain(|s| !absorbed.contains(&s.id));
+}
+
+/// Two diagonal lines running corner to corner inside a box
+fn find_cross(
+    i: usize,
+    shapes: &[DetectedShape],
+    absorbed: &HashSet<String>,
+    params: &WireframeParams,
+) -> Vec<usize> {
+    let b = &shapes[i].bounds;
+    let diagonal = (b.width.powi(2) + b.height.powi(2)).sqrt();
+    let mut falling = None;
+    let mut rising = None;
+
+    for (j, shape) in shapes.iter().enumerate() {
+        if j == i || absorbed.contains(&shape.id) || shape.shape_type != ShapeType::Line {
+            continue;
+        }
+        let (Some((sx, sy)), Some((ex, ey))) =
+            (shape.properties.start_point, shape.properties.end_point)
+        else {
+            continue;
+        };
+        let (cx, cy) = bounds_center(&shape.bounds);
+        let length = ((ex - sx).powi(2) + (ey - sy).powi(2)).sqrt();
+        if !bounds_contain(b, cx, cy) || length < diagonal * params.cross_span_ratio {
+            continue;
+        }
+
+        // Screen y grows downwards: a "\" stroke has dx and dy of the same sign
+        if (ex - sx) * (ey - sy) > 0.0 {
+            falling.get_or_insert(j);
+        } else {
+            rising.get_or_insert(j);
+        }
+    }
+
+    falling.into_iter().chain(rising).collect()
+}
+
+/// A horizontal line drawn along (or just below) the bottom edge of a box
+fn find_underline(i: usize, shapes: &[DetectedShape], absorbed: &HashSet<String>) -> Option<usize> {
+    let b = &shapes[i].bounds;
+    let bottom = b.y + b.height;
+
+    shapes.iter().enumerate().position(|(j, s)| {
+        j != i
+            && !absorbed.contains(&s.id)
+            && s.shape_type == ShapeType::Line
+            && s.bounds.height < b.height * 0.3
+            && (s.bounds.y + s.bounds.height / 2.0 - bottom).abs() < b.height * 0.5
+            && s.bounds.width >= b.width * 0.7
+            && (s.bounds.x - b.x).abs() < b.width * 0.15
+    })
+}
+
+/// Text whose center lies inside a box
+fn text_inside(bounds: &ShapeBounds, text_regions: &[TextRegion]) -> Option<String> {
+    let texts: Vec<String> = text_regions
+        .iter()
+        .filter(|t| {
+            bounds_contain(
+                bounds,
+                t.bounds.x + t.bounds.width / 2.0,
+                t.bounds.y + t.bounds.height / 2.0,
+            )
+        })
+        .map(|t| t.text.clone())
+        .collect();
+
+    if texts.is_empty() {
+        None
+    } else {
+        Some(texts.join(" "))
+    }
+}
+
+/// Closest text on the same row to the right of a box
+fn text_right_of(bounds: &ShapeBounds, text_regions: &[TextRegion]) -> Option<String> {
+    let (_, cy) = bounds_center(bounds);
+    let right = bounds.x + bounds.width;
+
+    text_regions
+        .iter()
+        .filter(|t| {
+            let ty = t.bounds.y + t.bounds.height / 2.0;
+            t.bounds.x >= right - bounds.width * 0.5
+                && t.bounds.x - right < bounds.width * 4.0
+                && (ty - cy).abs() < bounds.height.max(t.bounds.height)
+        })
+        .min_by(|a, b| a.bounds.x.partial_cmp(&b.bounds.x).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|t| t.text.clone())
+}
+
+/// Render wireframe shapes as a skeleton HTML page.
+///
+/// Elements are absolutely positioned at their drawn location so the page
+/// mirrors the sketch; shapes without a wireframe role become plain boxes.
+pub fn generate_html(shapes: &[DetectedShape], text_regions: &[TextRegion], title: &str) -> String {
+    let mut ordered: Vec<&DetectedShape> = shapes
+        .iter()
+        .filter(|s| {
+            !matches!(
+                s.shape_type,
+                ShapeType::Arrow | ShapeType::Line | ShapeType::Connector
+            )
+        })
+        .collect();
+    ordered.sort_by(|a, b| {
+        (a.bounds.y, a.bounds.x)
+            .partial_cmp(&(b.bounds.y, b.bounds.x))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let min_x = ordered.iter().map(|s| s.bounds.x).fold(f64::MAX, f64::min);
+    let min_y = ordered.iter().map(|s| s.bounds.y).fold(f64::MAX, f64::min);
+    let (origin_x, origin_y) = if ordered.is_empty() { (0.0, 0.0) } else { (min_x, min_y) };
+
+    let mut body = String::new();
+    for shape in ordered {
+        let b = &shape.bounds;
+        let position = format!(
+            "left:{:.0}px;top:{:.0}px;width:{:.0}px;height:{:.0}px;",
+            b.x - origin_x,
+            b.y - origin_y,
+            b.width,
+            b.height
+        );
+        let text = shape
+            .label
+            .clone()
+            .or_else(|| text_inside(b, text_regions))
+            .unwrap_or_default();
+        let text = escape_html(&text);
+
+        let element = match shape.properties.wireframe {
+            Some(WireframeElement::ImagePlaceholder) => {
+                format!("<img class=\"placeholder\" style=\"{}\" alt=\"\">", position)
+            }
+            Some(WireframeElement::Button) => {
+                format!("<button type=\"button\" style=\"{}\">{}</button>", position, text)
+            }
+            Some(WireframeElement::Input) => {
+                format!("<input type=\"text\" style=\"{}\" placeholder=\"{}\">", position, text)
+            }
+            Some(WireframeElement::Checkbox) => format!(
+                "<label style=\"left:{:.0}px;top:{:.0}px;\"><input type=\"checkbox\"> {}</label>",
+                b.x - origin_x,
+                b.y - origin_y,
+                text
+            ),
+            None => format!("<div class=\"box\" style=\"{}\">{}</div>", position, text),
+        };
+        body.push_str("    ");
+        body.push_str(&element);
+        body.push('\n');
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{title}</title>
+  <style>
+    .screen {{ position: relative; font-family: sans-serif; }}
+    .screen > * {{ position: absolute; box-sizing: border-box; }}
+    .placeholder {{ background: #e0e0e0; border: 1px solid #999; }}
+    .box {{ border: 1px solid #999; }}
+  </style>
+</head>
+<body>
+  <div class="screen">
+{body}  </div>
+</body>
+</html>
+"#,
+        title = escape_html(title),
+        body = body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::TextBounds;
+    use crate::shapes::test_shape;
+
+    fn text(content: &str, x: f64, y: f64) -> TextRegion {
+        TextRegion {
+            id: content.to_string(),
+            text: content.to_string(),
+            bounds: TextBounds { x, y, width: 60.0, height: 20.0 },
+            confidence: 0.9,
+            font_size_estimate: 14.0,
+        }
+    }
+
+    #[test]
+    fn test_annotate_wireframe_elements() {
+        let mut shapes = vec![
+            test_shape("image", ShapeType::Rectangle, (0.0, 0.0), (200.0, 150.0)),
+            test_shape("x1", ShapeType::Line, (5.0, 5.0), (195.0, 145.0)),
+            test_shape("x2", ShapeType::Line, (5.0, 145.0), (195.0, 5.0)),
+            test_shape("button", ShapeType::Rectangle, (0.0, 200.0), (100.0, 240.0)),
+            test_shape("input", ShapeType::Rectangle, (0.0, 300.0), (300.0, 340.0)),
+            test_shape("underline", ShapeType::Line, (0.0, 342.0), (300.0, 342.0)),
+            test_shape("check", ShapeType::Rectangle, (0.0, 400.0), (20.0, 420.0)),
+        ];
+        shapes[3].properties.corner_radius = Some(12.0);
+        let texts = vec![text("OK", 20.0, 210.0), text("Remember me", 30.0, 400.0)];
+
+        annotate_wireframe(&mut shapes, &texts, false);
+
+        let role = |id: &str| shapes.iter().find(|s| s.id == id).unwrap().properties.wireframe;
+        assert_eq!(shapes.len(), 4);
+        assert_eq!(role("image"), Some(WireframeElement::ImagePlaceholder));
+        assert_eq!(role("button"), Some(WireframeElement::Button));
+        assert_eq!(role("input"), Some(WireframeElement::Input));
+        assert_eq!(role("check"), Some(WireframeElement::Checkbox));
+
+        let checkbox = shapes.iter().find(|s| s.id == "check").unwrap();
+        assert_eq!(checkbox.label.as_deref(), Some("Remember me"));
+    }
+
+    #[test]
+    fn test_plain_boxes_are_not_controls() {
+        let mut shapes = vec![
+            test_shape("step", ShapeType::Rectangle, (0.0, 0.0), (100.0, 40.0)),
+            test_shape("wide", ShapeType::Rectangle, (0.0, 100.0), (300.0, 140.0)),
+            test_shape("blob", ShapeType::Freeform, (0.0, 200.0), (20.0, 220.0)),
+        ];
+        let texts = vec![text("Load", 20.0, 10.0)];
+
+        annotate_wireframe(&mut shapes, &texts, true);
+        assert!(shapes.iter().all(|s| s.properties.wireframe.is_none()));
+
+        // A lone checkbox next to a flow is not enough to call the board a mockup
+        let mut shapes = vec![
+            test_shape("check", ShapeType::Rectangle, (0.0, 0.0), (20.0, 20.0)),
+            test_shape("arrow", ShapeType::Arrow, (40.0, 10.0), (140.0, 10.0)),
+        ];
+        annotate_wireframe(&mut shapes, &[], false);
+        assert!(shapes[0].properties.wireframe.is_none());
+    }
+
+    #[test]
+    fn test_generate_html_skeleton() {
+        let mut button = test_shape("button", ShapeType::Rectangle, (10.0, 20.0), (110.0, 60.0));
+        button.properties.wireframe = Some(WireframeElement::Button);
+        button.label = Some("Save & close".to_string());
+
+        let html = generate_html(&[button], &[], "Login");
+        assert!(html.contains("<title>Login</title>"));
+        assert!(html.contains("left:0px;top:0px;width:100px;height:40px;\">Save &amp; close</button>"));
+    }
+}
@@ -1,6 +1,7 @@
 import { invoke as tauriInvoke } from '@tauri-apps/api/core';
 import { open, save, message as showDialog, ask } from '@tauri-apps/plugin-dialog';
-import type { Stroke, ProcessingResult } from '../store';
+import type { Stroke, ProcessingResult, DetectedShape, TextRegion } from '../store';
+import { decodeStrokeSync, type StrokeSync } from './strokeCodec';
 
 export interface ExportOptions {
   filename: string;
@@ -8,6 +9,169 @@ export interface ExportOptions {
   page_width: number;
   page_height: number;
   theme: string;
+  diagram_type?: string;
+  icon_styles?: IconStyleConfig;
+  /** Ink-to-draw.io colour pairs; draw.io's standard pairs when omitted */
+  palette?: Palette;
+  /** Cell colours from the theme presets (default), the palette, or the ink itself */
+  ink_style?: InkStyle;
+  /** Highlighter regions as translucent rectangles (default) or as fills of the cells they cover */
+  highlight_style?: HighlightStyle;
+}
+
+export type InkStyle = 'theme' | 'palette' | 'preserve';
+
+export type HighlightStyle = 'region' | 'fill';
+
+/** Colours are sent as CSS strings and returned as #rrggbb[aa] */
+export interface PaletteEntry {
+  ink: string;
+  fill: string;
+  stroke: string;
+}
+
+export interface Palette {
+  entries: PaletteEntry[];
+}
+
+export type IconKind = 'database' | 'cloud' | 'server' | 'firewall';
+
+export interface IconStyleConfig {
+  library: 'network' | 'aws' | 'azure';
+  overrides?: Partial<Record<IconKind, string>>;
+}
+
+export interface SvgOptions {
+  crop?: boolean;
+  padding?: number;
+  simplify_epsilon?: number;
+  background?: string;
+}
+
+export type PdfShapeLayer = 'none' | 'page' | 'overlay';
+
+/** Board corners in photo pixels: top-left, top-right, bottom-right, bottom-left */
+export type BoardCorners = [[number, number], [number, number], [number, number], [number, number]];
+
+export type Eraser =
+  | { type: 'circle'; x: number; y: number; radius: number }
+  | { type: 'path'; points: [number, number][]; radius: number };
+
+export interface EraseResult {
+  removed_ids: string[];
+  added: Stroke[];
+}
+
+/** World-space rectangle */
+export interface Viewport {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface RenderedTile {
+  zoom: number;
+  x: number;
+  y: number;
+  world_x: number;
+  world_y: number;
+  world_size: number;
+  /** Base64-encoded PNG */
+  png: string;
+}
+
+export interface Layer {
+  id: string;
+  name: string;
+  visible: boolean;
+  locked: boolean;
+}
+
+export type Containment = 'partial' | 'full';
+
+/** Affine matrix in canvas order: x' = a*x + c*y + e, y' = b*x + d*y + f */
+export interface AffineMatrix {
+  a: number;
+  b: number;
+  c: number;
+  d: number;
+  e: number;
+  f: number;
+}
+
+export interface TransformResult {
+  strokes: Stroke[];
+  shape_ids: string[];
+}
+
+export type OperationKind =
+  | 'add_stroke'
+  | 'delete_strokes'
+  | 'update_stroke'
+  | 'erase'
+  | 'transform'
+  | 'clear'
+  | 'import'
+  | 'analysis';
+
+export interface HistoryEntry {
+  id: number;
+  kind: OperationKind;
+  timestamp: number;
+  strokes_added: number;
+  strokes_removed: number;
+  applied: boolean;
+}
+
+export interface HistorySummary {
+  entries: HistoryEntry[];
+  can_undo: boolean;
+  can_redo: boolean;
+}
+
+export interface BoardSnapshot {
+  strokes: Stroke[];
+  shapes: DetectedShape[];
+  text_regions: TextRegion[];
+  history: HistorySummary;
+}
+
+/** Positions are absolute; `parent` names the container node */
+export interface DiagramNode {
+  id: string;
+  label: string;
+  shape_type: string;
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  style: string;
+  parent?: string | null;
+}
+
+export interface DiagramEdge {
+  id: string;
+  /** Empty for an end not attached to a node */
+  source: string;
+  target: string;
+  label?: string | null;
+  style: string;
+  waypoints?: [number, number][];
+  source_point?: [number, number] | null;
+  target_point?: [number, number] | null;
+}
+
+export interface DiagramStructure {
+  diagram_type: string;
+  nodes: DiagramNode[];
+  edges: DiagramEdge[];
+  metadata: {
+    title?: string | null;
+    author?: string | null;
+    created_at?: string | null;
+    version?: string | null;
+  };
 }
 
 export interface LlmConfig {
@@ -48,13 +212,94 @@ export const api = {
     return safeInvoke('clear_strokes');
   },
 
+  async deleteStrokes(ids: string[]): Promise<number> {
+    return safeInvoke('delete_strokes', { ids });
+  },
+
+  async updateStroke(stroke: Stroke): Promise<void> {
+    return safeInvoke('update_stroke', { stroke });
+  },
+
+  async eraseAt(eraser: Eraser): Promise<EraseResult> {
+    return safeInvoke('erase_at', { eraser });
+  },
+
+  async selectStrokesInRect(
+    x: number,
+    y: number,
+    width: number,
+    height: number,
+    mode?: Containment
+  ): Promise<string[]> {
+    return safeInvoke('select_strokes_in_rect', { x, y, width, height, mode });
+  },
+
+  async selectStrokesInPolygon(points: [number, number][], mode?: Containment): Promise<string[]> {
+    return safeInvoke('select_strokes_in_polygon', { points, mode });
+  },
+
+  async transformStrokes(ids: string[], matrix: AffineMatrix, duplicate?: boolean): Promise<TransformResult> {
+    return safeInvoke('transform_strokes', { ids, matrix, duplicate });
+  },
+
+  async getLayers(): Promise<Layer[]> {
+    return safeInvoke('get_layers');
+  },
+
+  async createLayer(name: string): Promise<Layer> {
+    return safeInvoke('create_layer', { name });
+  },
+
+  async reorderLayers(ids: string[]): Promise<Layer[]> {
+    return safeInvoke('reorder_layers', { ids });
+  },
+
+  async setLayerVisible(id: string, visible: boolean): Promise<Layer> {
+    return safeInvoke('set_layer_visible', { id, visible });
+  },
+
+  async setLayerLocked(id: string, locked: boolean): Promise<Layer> {
+    return safeInvoke('set_layer_locked', { id, locked });
+  },
+
+  async undo(): Promise<BoardSnapshot> {
+    return safeInvoke('undo');
+  },
+
+  async redo(): Promise<BoardSnapshot> {
+    return safeInvoke('redo');
+  },
+
+  async getHistory(): Promise<HistorySummary> {
+    return safeInvoke('get_history');
+  },
+
   async getStrokes(): Promise<Stroke[]> {
     return safeInvoke('get_strokes');
   },
 
+  /** Strokes changed since the version returned by the previous call; everything when omitted */
+  async getStrokesSince(version?: number): Promise<StrokeSync> {
+    return decodeStrokeSync(await safeInvoke<ArrayBuffer>('get_strokes_since', { version }));
+  },
+
   // Processing
-  async processCanvas(imageData: string, width: number, height: number): Promise<ProcessingResult> {
-    return safeInvoke('process_canvas', { imageData, width, height });
+  async processCanvas(
+    imageData: string,
+    width: number,
+    height: number,
+    layers?: string[],
+    diagramType?: string
+  ): Promise<ProcessingResult> {
+    return safeInvoke('process_canvas', { imageData, width, height, layers, diagramType });
+  },
+
+  async analyzeStrokes(
+    scale?: number,
+    layers?: string[],
+    diagramType?: string
+  ): Promise<ProcessingResult> {
+    return safeInvoke('analyze_strokes', { scale, layers, diagramType });
   },
 
   // LLM
@@ -75,6 +320,27 @@ export const api = {
     return safeInvoke('export_drawio_file', { path, options });
   },
 
+  async exportWireframeHtml(path: string, title?: string): Promise<void> {
+    return safeInvoke('export_wireframe_html', { path, title });
+  },
+
+  /** Tiles covering a viewport; zoom is a level with 2^zoom pixels per canvas unit */
+  async renderTiles(viewport: Viewport, zoom: number, background?: string): Promise<RenderedTile[]> {
+    return safeInvoke('render_tiles', { viewport, zoom, background });
+  },
+
+  async exportPng(path: string, viewport: Viewport, zoom: number, background?: string): Promise<void> {
+    return safeInvoke('export_png', { path, viewport, zoom, background });
+  },
+
+  async exportSvg(path: string, options?: SvgOptions): Promise<void> {
+    return safeInvoke('export_svg', { path, options });
+  },
+
+  async exportPdf(path: string, options: ExportOptions, shapeLayer?: PdfShapeLayer): Promise<void> {
+    return safeInvoke('export_pdf', { path, options, shapeLayer });
+  },
+
   // Backup
   async saveBackup(path: string): Promise<void> {
     return safeInvoke('save_backup', { path });
@@ -84,6 +350,34 @@ export const api = {
     return safeInvoke('load_backup', { path });
   },
 
+  // InkML
+  async exportInkml(path: string): Promise<void> {
+    return safeInvoke('export_inkml', { path });
+  },
+
+  async importInkml(path: string, append?: boolean, layerId?: string): Promise<Stroke[]> {
+    return safeInvoke('import_inkml', { path, append, layerId });
+  },
+
+  // Photo import
+  async importImage(
+    path: string,
+    append?: boolean,
+    corners?: BoardCorners,
+    layerId?: string
+  ): Promise<Stroke[]> {
+    return safeInvoke('import_image', { path, append, corners, layerId });
+  },
+
+  async detectBoardCorners(path: string): Promise<BoardCorners | null> {
+    return safeInvoke('detect_board_corners', { path });
+  },
+
+  // draw.io import
+  async importDrawio(path: string): Promise<DiagramStructure> {
+    return safeInvoke('import_drawio', { path });
+  },
+
   // Info
   async getAppInfo(): Promise<AppInfo> {
     return safeInvoke('get_app_info');
@@ -0,0 +1,117 @@
+import type { Point, Stroke } from '../store';
+
+// Mirrors src-tauri/src/codec.rs
+const SYNC_MAGIC = 'WBD';
+const FORMAT_VERSION = 1;
+const COORD_SCALE = 100;
+const WIDTH_SCALE = 100;
+const PRESSURE_SCALE = 1000;
+const HAS_PRESSURE = 1;
+const HAS_TIMESTAMPS = 2;
+
+/** Strokes changed since a version, as sent by `get_strokes_since` */
+export interface StrokeSync {
+  /** Version to pass to the next `get_strokes_since` call */
+  version: number;
+  /** When set, `changed` holds every stroke and everything else should be dropped */
+  full: boolean;
+  removed: string[];
+  /** New or changed strokes with their index in the board's stroke list, ascending */
+  changed: { index: number; stroke: Stroke }[];
+}
+
+class Reader {
+  private pos = 0;
+  private readonly text = new TextDecoder();
+
+  constructor(private readonly bytes: Uint8Array) {}
+
+  take(n: number): Uint8Array {
+    if (this.pos + n > this.bytes.length) throw new Error('Stroke data is truncated');
+    const slice = this.bytes.subarray(this.pos, this.pos + n);
+    this.pos += n;
+    return slice;
+  }
+
+  byte(): number {
+    return this.take(1)[0];
+  }
+
+  /** Varints are read with float arithmetic; values stay exact up to 2^53 */
+  varint(): number {
+    let value = 0;
+    let scale = 1;
+    for (let i = 0; i < 10; i++) {
+      const byte = this.byte();
+      value += (byte & 0x7f) * scale;
+      if ((byte & 0x80) === 0) return value;
+      scale *= 128;
+    }
+    throw new Error('Malformed varint in stroke data');
+  }
+
+  signed(): number {
+    const value = this.varint();
+    return value % 2 === 0 ? value / 2 : -(value + 1) / 2;
+  }
+
+  string(): string {
+    return this.text.decode(this.take(this.varint()));
+  }
+
+  stroke(): Stroke {
+    const id = this.string();
+    const color = this.string();
+    const tool = this.string();
+    const layer_id = this.string();
+    const width = this.varint() / WIDTH_SCALE;
+    const flags = this.byte();
+    const count = this.varint();
+
+    const points: Point[] = [];
+    let x = 0;
+    let y = 0;
+    let timestamp = 0;
+    for (let i = 0; i < count; i++) {
+      x += this.signed();
+      y += this.signed();
+      if (flags & HAS_TIMESTAMPS) timestamp += this.signed();
+      const point: Point = { x: x / COORD_SCALE, y: y / COORD_SCALE, timestamp };
+      if (flags & HAS_PRESSURE) {
+        const pressure = this.varint();
+        if (pressure > 0) point.pressure = (pressure - 1) / PRESSURE_SCALE;
+      }
+      points.push(point);
+    }
+
+    return { id, points, color, width, tool, layer_id };
+  }
+}
+
+/** Decode a `get_strokes_since` response. */
+export function decodeStrokeSync(buffer: ArrayBuffer): StrokeSync {
+  const reader = new Reader(new Uint8Array(buffer));
+  if (new TextDecoder().decode(reader.take(3)) !== SYNC_MAGIC) {
+    throw new Error('Not a stroke sync message');
+  }
+  const version = reader.byte();
+  if (version !== FORMAT_VERSION) throw new Error(`Unsupported stroke format version ${version}`);
+
+  const sync: StrokeSync = { version: reader.varint(), full: reader.byte() !== 0, removed: [], changed: [] };
+  for (let n = reader.varint(); n > 0; n--) sync.removed.push(reader.string());
+  for (let n = reader.varint(); n > 0; n--) {
+    const index = reader.varint();
+    sync.changed.push({ index, stroke: reader.stroke() });
+  }
+  return sync;
+}
+
+/** Apply a sync to a local copy of the board's strokes */
+export function applyStrokeSync(strokes: Stroke[], sync: StrokeSync): Stroke[] {
+  if (sync.full) return sync.changed.map((c) => c.stroke);
+
+  const dropped = new Set([...sync.removed, ...sync.changed.map((c) => c.stroke.id)]);
+  const next = strokes.filter((s) => !dropped.has(s.id));
+  for (const { index, stroke } of sync.changed) next.splice(index, 0, stroke);
+  return next;
+}
@@ -15,6 +15,8 @@ export interface Stroke {
   color: string;
   width: number;
   tool: string;
+  /** Layer id; the backend assigns the default layer when omitted */
+  layer_id?: string;
 }
 
 export interface DetectedShape {
@@ -47,6 +49,16 @@ export interface ProcessingResult {
   text_regions: TextRegion[];
   suggested_diagram_type: string;
   confidence: number;
+  /** Strokes left out of analysis as likely accidental; candidates for deletion */
+  stray_strokes?: StrayStroke[];
+}
+
+export type NoiseReason = 'tiny' | 'palm' | 'tap' | 'grazing' | 'isolated';
+
+export interface StrayStroke {
+  stroke_id: string;
+  score: number;
+  reasons: NoiseReason[];
 }
 
 export type Tool = 'pen' | 'eraser' | 'select' | 'pan';